@@ -0,0 +1,105 @@
+use std::{error::Error, path::Path};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use escrow_scanner::{book::Now, dump};
+use paulx_escrow_contract::state::{Config, Escrow};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account,
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+pub type CliResult<T> = Result<T, Box<dyn Error>>;
+
+// talks to the cluster, or with --offline signs against the given blockhash and prints the transaction.
+// offline, every token leg is assumed to belong to the given token program
+pub enum Client {
+    Online(RpcClient),
+    Offline { blockhash: Hash, token_program: Pubkey },
+}
+
+impl Client {
+    pub fn new(url: &str) -> Self {
+        Client::Online(RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()))
+    }
+
+    pub fn is_offline(&self) -> bool {
+        matches!(self, Client::Offline { .. })
+    }
+
+    // offline, the default rent is assumed
+    pub fn minimum_balance(&self, data_len: usize) -> CliResult<u64> {
+        match self {
+            Client::Online(rpc) => Ok(rpc.get_minimum_balance_for_rent_exemption(data_len)?),
+            Client::Offline { .. } => Ok(Rent::default().minimum_balance(data_len)),
+        }
+    }
+
+    pub fn account(&self, pubkey: &Pubkey) -> CliResult<Option<Account>> {
+        match self {
+            Client::Online(rpc) => Ok(rpc.get_account_with_commitment(pubkey, CommitmentConfig::confirmed())?.value),
+            Client::Offline { .. } => Err(format!("{} can't be fetched offline", pubkey).into()),
+        }
+    }
+
+    // offline the escrow comes from a `solana account <escrow> --output json` dump taken beforehand
+    pub fn escrow(&self, pubkey: &Pubkey, dump_path: Option<&Path>) -> CliResult<Escrow> {
+        let data = match dump_path {
+            Some(dump_path) => dump::load(dump_path)?
+                .into_iter()
+                .find(|account| account.pubkey == *pubkey)
+                .ok_or_else(|| format!("{} is not in {}", pubkey, dump_path.display()))?
+                .data,
+            None if self.is_offline() => return Err("--escrow-dump is required offline".into()),
+            None => self.account(pubkey)?.ok_or_else(|| format!("escrow {} not found", pubkey))?.data,
+        };
+        Ok(Escrow::unpack(&data)?)
+    }
+
+    pub fn config(&self, program_id: &Pubkey) -> CliResult<Option<Config>> {
+        match self.account(&Config::find_pda(program_id).0)? {
+            Some(account) => Ok(Some(Config::unpack(&account.data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn token_program(&self, mint: &Pubkey) -> CliResult<Pubkey> {
+        match self {
+            Client::Online(_) => Ok(self.account(mint)?.ok_or_else(|| format!("mint {} not found", mint))?.owner),
+            Client::Offline { token_program, .. } => Ok(*token_program),
+        }
+    }
+
+    pub fn now(&self) -> CliResult<Now> {
+        match self {
+            Client::Online(rpc) => {
+                let slot = rpc.get_slot()?;
+                Ok(Now { slot, unix_timestamp: rpc.get_block_time(slot)? })
+            },
+            Client::Offline { .. } => Err("the cluster clock can't be read offline".into()),
+        }
+    }
+
+    // the signature once confirmed, or offline the signed transaction to send from an online machine
+    // through sendTransaction with base64 encoding
+    pub fn submit(&self, instructions: &[Instruction], payer: &Keypair, signers: &[&Keypair]) -> CliResult<String> {
+        let mut all_signers = vec![payer];
+        all_signers.extend_from_slice(signers);
+        match self {
+            Client::Online(rpc) => {
+                let transaction = Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &all_signers, rpc.get_latest_blockhash()?);
+                Ok(format!("Signature: {}", rpc.send_and_confirm_transaction(&transaction)?))
+            },
+            Client::Offline { blockhash, .. } => {
+                let transaction = Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &all_signers, *blockhash);
+                Ok(format!("Transaction: {}", STANDARD.encode(bincode::serialize(&transaction)?)))
+            },
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::Path;
+use escrow_scanner::book::{format_deadline, Listing};
+use paulx_escrow_contract::{
+    instruction,
+    state::{AssetKind, ClockMode, Escrow, PriceBand},
+};
+use solana_sdk::{
+    instruction::AccountMeta,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+
+use crate::client::{CliResult, Client};
+
+pub struct InitArgs {
+    pub mint: Pubkey,
+    // the initializer's account the deposit is taken from, their associated token account by default
+    pub from: Option<Pubkey>,
+    pub amount: u64,
+    // the initializer's token account for the expected mint
+    pub receive: Pubkey,
+    pub expected_amount: u64,
+    // where Expire returns the deposit, `from` by default
+    pub refund: Option<Pubkey>,
+    pub unlock_delay: u64,
+    pub timeout_window: u64,
+    pub clock_mode: ClockMode,
+    pub allowed_taker: Option<Pubkey>,
+    // makes the escrow a dutch auction falling from expected_amount to this price
+    pub auction_end_price: Option<u64>,
+    // only settles while the oracle's reference price is inside the band
+    pub price_band: Option<PriceBand>,
+}
+
+pub struct ExchangeArgs {
+    pub escrow: Pubkey,
+    // the whole remaining deposit by default
+    pub amount: Option<u64>,
+    // the most the taker is debited for it, transfer fees included, the escrow's current price by default
+    pub max_payment: Option<u64>,
+    // the least of the deposit that has to arrive after its mint's transfer fee, the amount taken by default
+    pub min_receive: Option<u64>,
+    // the taker's account paying the expected mint and the one receiving the deposit,
+    // their associated token accounts by default
+    pub from: Option<Pubkey>,
+    pub to: Option<Pubkey>,
+    // offline the configured treasury can't be looked up, so it has to be given
+    pub treasury: Option<Pubkey>,
+}
+
+// opens the escrow and has the program create its vault and pull the deposit in, in one transaction
+pub fn init(client: &Client, program_id: &Pubkey, initializer: &Keypair, args: &InitArgs) -> CliResult<String> {
+    let token_program = client.token_program(&args.mint)?;
+    let from = args.from.unwrap_or_else(|| get_associated_token_address_with_program_id(&initializer.pubkey(), &args.mint, &token_program));
+    let escrow = Keypair::new();
+
+    let instructions = vec![
+        system_instruction::create_account(
+            &initializer.pubkey(),
+            &escrow.pubkey(),
+            client.minimum_balance(Escrow::LEN)?,
+            Escrow::LEN as u64,
+            program_id,
+        ),
+        instruction::init_escrow_with_vault(
+            program_id,
+            &initializer.pubkey(),
+            &from,
+            &args.receive,
+            &escrow.pubkey(),
+            &args.refund.unwrap_or(from),
+            &args.mint,
+            &token_program,
+            args.amount,
+            args.expected_amount,
+            args.unlock_delay,
+            args.timeout_window,
+            args.clock_mode,
+            AssetKind::Token,
+            args.allowed_taker,
+            args.auction_end_price,
+            args.price_band,
+        ),
+    ];
+
+    println!("Escrow: {}", escrow.pubkey());
+    println!("Vault: {}", Escrow::find_vault(&escrow.pubkey(), &args.mint, &token_program, program_id));
+    println!("Vault authority: {}", Escrow::find_pda(&escrow.pubkey(), program_id).0);
+    client.submit(&instructions, initializer, &[&escrow])
+}
+
+pub fn exchange(client: &Client, program_id: &Pubkey, taker: &Keypair, args: &ExchangeArgs, escrow_dump: Option<&Path>) -> CliResult<String> {
+    let escrow = client.escrow(&args.escrow, escrow_dump)?;
+    let (deposit_program, default_to) = leg(client, escrow.deposit_kind, &escrow.deposit_mint, &taker.pubkey())?;
+    let (expected_program, default_from) = leg(client, escrow.expected_kind, &escrow.expected_mint, &taker.pubkey())?;
+
+    // without a config the program takes no fee and never touches the treasury account
+    let treasury = match args.treasury {
+        Some(treasury) => treasury,
+        None if client.is_offline() => return Err("--treasury is required offline".into()),
+        None => match client.config(program_id)? {
+            Some(config) => config.treasury_account(&escrow, &expected_program),
+            None => escrow.initializer_token_to_receive_account_pubkey,
+        },
+    };
+
+    let amount = args.amount.unwrap_or(escrow.deposit_amount);
+    let max_payment = match args.max_payment {
+        Some(max_payment) => max_payment,
+        None => {
+            // an auction is priced at the cluster's current time, offline at its start price
+            let now = if client.is_offline() { escrow.unlock_time } else { client.now()?.in_mode(escrow.clock_mode) };
+            escrow.payment_at(amount, now).ok_or_else(|| format!("can't take {} of a {} deposit", amount, escrow.deposit_amount))?
+        },
+    };
+
+    let mut ix = instruction::exchange(
+        program_id,
+        &taker.pubkey(),
+        &args.from.unwrap_or(default_from),
+        &args.to.unwrap_or(default_to),
+        &escrow.temp_token_account_pubkey,
+        &escrow.initializer_pubkey,
+        &escrow.initializer_token_to_receive_account_pubkey,
+        &args.escrow,
+        &treasury,
+        &escrow.deposit_mint,
+        &escrow.expected_mint,
+        &deposit_program,
+        &expected_program,
+        amount,
+        max_payment,
+        args.min_receive.unwrap_or(amount),
+    );
+    if let Some(price_band) = escrow.price_band {
+        ix.accounts.push(AccountMeta::new_readonly(price_band.oracle, false));
+    }
+    client.submit(&[ix], taker, &[])
+}
+
+// returns the remaining deposit to the initializer's refund account unless another one is given
+pub fn cancel(client: &Client, program_id: &Pubkey, initializer: &Keypair, escrow_pubkey: &Pubkey, to: Option<Pubkey>, escrow_dump: Option<&Path>) -> CliResult<String> {
+    let escrow = client.escrow(escrow_pubkey, escrow_dump)?;
+    let (deposit_program, _default_to) = leg(client, escrow.deposit_kind, &escrow.deposit_mint, &initializer.pubkey())?;
+    let ix = instruction::cancel(
+        program_id,
+        &initializer.pubkey(),
+        &escrow.temp_token_account_pubkey,
+        &to.unwrap_or(escrow.refund_token_account_pubkey),
+        escrow_pubkey,
+        &escrow.deposit_mint,
+        &deposit_program,
+    );
+    client.submit(&[ix], initializer, &[])
+}
+
+pub fn reset_time_lock(client: &Client, program_id: &Pubkey, initializer: &Keypair, escrow_pubkey: &Pubkey) -> CliResult<String> {
+    let ix = instruction::reset_time_lock(program_id, &initializer.pubkey(), escrow_pubkey);
+    client.submit(&[ix], initializer, &[])
+}
+
+pub fn show(client: &Client, program_id: &Pubkey, escrow_pubkey: &Pubkey) -> CliResult<String> {
+    let escrow = client.escrow(escrow_pubkey, None)?;
+    let now = client.now()?;
+    let (pda, _bump_seed) = Escrow::find_pda(escrow_pubkey, program_id);
+
+    let allowed_taker = escrow.allowed_taker.map_or_else(|| "anyone".to_string(), |allowed_taker| allowed_taker.to_string());
+    let price_band = escrow.price_band.map_or_else(
+        || "none".to_string(),
+        |price_band| format!("{} to {} on {}", price_band.min_price, price_band.max_price, price_band.oracle),
+    );
+    let auction = escrow.auction.map_or_else(
+        || "none".to_string(),
+        |auction| format!(
+            "from {} to {}, {} now",
+            auction.start_price,
+            auction.end_price,
+            escrow.price_at(now.in_mode(escrow.clock_mode)),
+        ),
+    );
+    let lines = [
+        format!("Escrow: {}", escrow_pubkey),
+        format!("Initializer: {}", escrow.initializer_pubkey),
+        format!("Vault: {} (authority {})", escrow.temp_token_account_pubkey, pda),
+        format!("Deposit: {} of {} ({:?})", escrow.deposit_amount, escrow.deposit_mint, escrow.deposit_kind),
+        format!("Expected: {} of {} ({:?}) to {}", escrow.expected_amount, escrow.expected_mint, escrow.expected_kind, escrow.initializer_token_to_receive_account_pubkey),
+        format!("Refund account: {}", escrow.refund_token_account_pubkey),
+        format!("Allowed taker: {}", allowed_taker),
+        format!("Auction: {}", auction),
+        format!("Price band: {}", price_band),
+        format!("Open counter-offers: {}", escrow.open_counters),
+        format!("Unlocks: {}", format_deadline(escrow.clock_mode, escrow.unlock_time, &now)),
+        format!("Times out: {}", format_deadline(escrow.clock_mode, escrow.time_out, &now)),
+        format!("Status: {}", Listing { pubkey: *escrow_pubkey, escrow }.status(&now)),
+    ];
+    Ok(lines.join("\n"))
+}
+
+// (token program, owner's default account) of a leg, a native leg is paid from and to the wallet
+fn leg(client: &Client, kind: AssetKind, mint: &Pubkey, owner: &Pubkey) -> CliResult<(Pubkey, Pubkey)> {
+    let token_program = match kind {
+        AssetKind::Native => return Ok((spl_token::id(), *owner)),
+        AssetKind::Token => client.token_program(mint)?,
+    };
+    Ok((token_program, get_associated_token_address_with_program_id(owner, mint, &token_program)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{path::PathBuf, str::FromStr};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use paulx_escrow_contract::instruction::EscrowInstruction;
+    use solana_sdk::{hash::Hash, transaction::Transaction};
+
+    // escrow A of the scanner fixtures: 50 of mint 11 for 30 of mint 12, program 9
+    const ESCROW_A: &str = "2RJD1KnDRGEkvuFfAGrJ7PD28LRE9LRDjZznDywagzmr";
+
+    fn escrow_dump() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../escrow-scanner/tests/fixtures/escrow_a.json")
+    }
+
+    fn offline() -> Client {
+        Client::Offline { blockhash: Hash::new_unique(), token_program: spl_token::id() }
+    }
+
+    fn exchange_args(escrow: Pubkey) -> ExchangeArgs {
+        ExchangeArgs { escrow, amount: None, max_payment: None, min_receive: None, from: None, to: None, treasury: None }
+    }
+
+    #[test]
+    fn test_offline_exchange() {
+        let program_id = Pubkey::new_from_array([9; 32]);
+        let escrow = Pubkey::from_str(ESCROW_A).unwrap();
+        let taker = Keypair::new();
+
+        let treasury = Pubkey::new_unique();
+        let args = ExchangeArgs { treasury: Some(treasury), ..exchange_args(escrow) };
+
+        // the configured treasury can't be looked up offline
+        assert!(exchange(&offline(), &program_id, &taker, &exchange_args(escrow), Some(&escrow_dump())).is_err());
+        let output = exchange(&offline(), &program_id, &taker, &args, Some(&escrow_dump())).unwrap();
+        let transaction: Transaction = bincode::deserialize(&STANDARD.decode(output.strip_prefix("Transaction: ").unwrap()).unwrap()).unwrap();
+        transaction.verify().unwrap();
+        assert_eq!(transaction.message.account_keys[0], taker.pubkey());
+
+        // the whole deposit is taken, with the vault and its authority derived from the dumped escrow
+        let ix = &transaction.message.instructions[0];
+        assert_eq!(EscrowInstruction::unpack(&ix.data).unwrap(), EscrowInstruction::Exchange { amount: 50, max_payment: 30, min_receive: 50 });
+        let keys: Vec<_> = ix.accounts.iter().map(|&index| transaction.message.account_keys[index as usize]).collect();
+        assert_eq!(keys[3], Pubkey::new_from_array([101; 32]));
+        assert_eq!(keys[6], escrow);
+        assert_eq!(keys[8], Escrow::find_pda(&escrow, &program_id).0);
+        assert_eq!(keys[11], treasury);
+        assert_eq!(keys[2], get_associated_token_address_with_program_id(&taker.pubkey(), &Pubkey::new_from_array([11; 32]), &spl_token::id()));
+    }
+
+    #[test]
+    fn test_offline_needs_escrow_dump() {
+        let escrow = Pubkey::from_str(ESCROW_A).unwrap();
+        let initializer = Keypair::new();
+        assert!(exchange(&offline(), &Pubkey::new_unique(), &initializer, &exchange_args(escrow), None).is_err());
+        assert!(cancel(&offline(), &Pubkey::new_unique(), &initializer, &escrow, None, None).is_err());
+        assert!(show(&offline(), &Pubkey::new_unique(), &escrow).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// operator tool for the native escrow program
+mod client;
+mod command;
+
+use std::{env, path::{Path, PathBuf}, process, str::FromStr};
+use clap::{Arg, ArgMatches, Command};
+use paulx_escrow_contract::state::{ClockMode, PriceBand};
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::{read_keypair_file, Keypair}};
+
+use crate::{
+    client::{CliResult, Client},
+    command::{ExchangeArgs, InitArgs},
+};
+
+fn app() -> Command<'static> {
+    let escrow_arg = Arg::new("escrow").long("escrow").takes_value(true).required(true).help("Escrow account");
+    let escrow_dump_arg = Arg::new("escrow-dump")
+        .long("escrow-dump")
+        .takes_value(true)
+        .help("`solana account <ESCROW> --output json` dump to read the escrow from, required with --offline");
+
+    Command::new("escrow")
+        .about("Open, take and manage escrows of the native escrow program")
+        .subcommand_required(true)
+        .arg(Arg::new("url").long("url").short('u').global(true).takes_value(true).default_value("http://localhost:8899").help("RPC URL of the cluster"))
+        .arg(Arg::new("program-id").long("program-id").global(true).takes_value(true).help("Escrow program id, required"))
+        .arg(Arg::new("keypair").long("keypair").short('k').global(true).takes_value(true).help("Signer and fee payer keypair file [default: ~/.config/solana/id.json]"))
+        .arg(Arg::new("offline").long("offline").global(true).requires("blockhash").help("Sign without a cluster connection and print the transaction instead of sending it"))
+        .arg(Arg::new("blockhash").long("blockhash").global(true).takes_value(true).requires("offline").help("Recent blockhash to sign with offline"))
+        .arg(Arg::new("token-program").long("token-program").global(true).takes_value(true).help("Token program of the escrowed mints when offline [default: spl-token]"))
+        .subcommand(
+            Command::new("init")
+                .about("Move tokens into a new vault and open an escrow over them")
+                .arg(Arg::new("mint").long("mint").takes_value(true).required(true).help("Mint of the deposit"))
+                .arg(Arg::new("amount").long("amount").takes_value(true).required(true).help("Amount to deposit"))
+                .arg(Arg::new("from").long("from").takes_value(true).help("Token account to deposit from [default: associated token account]"))
+                .arg(Arg::new("receive").long("receive").takes_value(true).required(true).help("Token account receiving the expected mint"))
+                .arg(Arg::new("expected-amount").long("expected-amount").takes_value(true).required(true).help("Amount expected for the whole deposit"))
+                .arg(Arg::new("refund").long("refund").takes_value(true).help("Token account an expired deposit is returned to [default: --from]"))
+                .arg(Arg::new("unlock-delay").long("unlock-delay").takes_value(true).default_value("100").help("Time until the escrow can be taken"))
+                .arg(Arg::new("timeout-window").long("timeout-window").takes_value(true).default_value("1000").help("Time after unlock during which the escrow can be taken"))
+                .arg(Arg::new("clock-mode").long("clock-mode").takes_value(true).possible_values(["slot", "unix"]).default_value("slot").help("Unit of the two values above, slots or seconds"))
+                .arg(Arg::new("allowed-taker").long("allowed-taker").takes_value(true).help("Only key allowed to take the escrow"))
+                .arg(Arg::new("auction-end-price").long("auction-end-price").takes_value(true).help("Sell by dutch auction, the price falling from --expected-amount at unlock to this at timeout"))
+                .arg(Arg::new("oracle").long("oracle").takes_value(true).requires_all(&["min-price", "max-price"]).help("Price feed the escrow only settles against while its price is in --min-price..=--max-price"))
+                .arg(Arg::new("min-price").long("min-price").takes_value(true).requires("oracle").help("Lowest reference price the escrow settles at"))
+                .arg(Arg::new("max-price").long("max-price").takes_value(true).requires("oracle").help("Highest reference price the escrow settles at")),
+        )
+        .subcommand(
+            Command::new("exchange")
+                .about("Pay the expected tokens and take the deposit")
+                .arg(escrow_arg.clone())
+                .arg(Arg::new("amount").long("amount").takes_value(true).help("Amount of the deposit to take [default: all of it]"))
+                .arg(Arg::new("max-payment").long("max-payment").takes_value(true).help("Most of the expected mint to be debited, transfer fees included [default: the escrow's current price]"))
+                .arg(Arg::new("min-receive").long("min-receive").takes_value(true).help("Least of the deposit to receive, after transfer fees [default: the amount taken]"))
+                .arg(Arg::new("from").long("from").takes_value(true).help("Token account paying the expected mint [default: associated token account]"))
+                .arg(Arg::new("to").long("to").takes_value(true).help("Token account receiving the deposit [default: associated token account]"))
+                .arg(Arg::new("treasury").long("treasury").takes_value(true).help("Fee treasury account, required with --offline [default: the configured one]"))
+                .arg(escrow_dump_arg.clone()),
+        )
+        .subcommand(
+            Command::new("cancel")
+                .about("Close the escrow and return the deposit")
+                .arg(escrow_arg.clone())
+                .arg(Arg::new("to").long("to").takes_value(true).help("Token account receiving the deposit [default: the escrow's refund account]"))
+                .arg(escrow_dump_arg),
+        )
+        .subcommand(Command::new("reset-timelock").about("Restart the escrow's unlock delay and timeout window").arg(escrow_arg.clone()))
+        .subcommand(Command::new("show").about("Print the escrow's state").arg(escrow_arg))
+}
+
+fn pubkey_of(matches: &ArgMatches, name: &str) -> CliResult<Option<Pubkey>> {
+    matches
+        .value_of(name)
+        .map(|value| Pubkey::from_str(value).map_err(|_| format!("--{}: invalid pubkey {}", name, value).into()))
+        .transpose()
+}
+
+fn required_pubkey_of(matches: &ArgMatches, name: &str) -> CliResult<Pubkey> {
+    pubkey_of(matches, name)?.ok_or_else(|| format!("--{} is required", name).into())
+}
+
+fn value_of<T: FromStr>(matches: &ArgMatches, name: &str) -> CliResult<Option<T>> {
+    matches
+        .value_of(name)
+        .map(|value| value.parse().map_err(|_| format!("--{}: invalid value {}", name, value).into()))
+        .transpose()
+}
+
+fn required_value_of<T: FromStr>(matches: &ArgMatches, name: &str) -> CliResult<T> {
+    value_of(matches, name)?.ok_or_else(|| format!("--{} is required", name).into())
+}
+
+fn keypair(matches: &ArgMatches) -> CliResult<Keypair> {
+    let path = match matches.value_of("keypair") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env::var("HOME")?).join(".config/solana/id.json"),
+    };
+    read_keypair_file(&path).map_err(|err| format!("reading keypair {}: {}", path.display(), err).into())
+}
+
+fn run(matches: &ArgMatches) -> CliResult<()> {
+    let (name, sub_matches) = matches.subcommand().ok_or("a subcommand is required")?;
+    let program_id = required_pubkey_of(sub_matches, "program-id")?;
+    let client = if sub_matches.is_present("offline") {
+        Client::Offline {
+            blockhash: Hash::from_str(sub_matches.value_of("blockhash").ok_or("--blockhash is required")?)
+                .map_err(|_| "--blockhash: invalid hash")?,
+            token_program: pubkey_of(sub_matches, "token-program")?.unwrap_or_else(spl_token::id),
+        }
+    } else {
+        Client::new(sub_matches.value_of("url").ok_or("--url is required")?)
+    };
+    let output = match name {
+        "init" => {
+            let clock_mode = match sub_matches.value_of("clock-mode") {
+                Some("unix") => ClockMode::UnixTimestamp,
+                _ => ClockMode::Slot,
+            };
+            let args = InitArgs {
+                mint: required_pubkey_of(sub_matches, "mint")?,
+                from: pubkey_of(sub_matches, "from")?,
+                amount: required_value_of(sub_matches, "amount")?,
+                receive: required_pubkey_of(sub_matches, "receive")?,
+                expected_amount: required_value_of(sub_matches, "expected-amount")?,
+                refund: pubkey_of(sub_matches, "refund")?,
+                unlock_delay: required_value_of(sub_matches, "unlock-delay")?,
+                timeout_window: required_value_of(sub_matches, "timeout-window")?,
+                clock_mode,
+                allowed_taker: pubkey_of(sub_matches, "allowed-taker")?,
+                auction_end_price: value_of(sub_matches, "auction-end-price")?,
+                price_band: match pubkey_of(sub_matches, "oracle")? {
+                    Some(oracle) => Some(PriceBand {
+                        oracle,
+                        min_price: required_value_of(sub_matches, "min-price")?,
+                        max_price: required_value_of(sub_matches, "max-price")?,
+                    }),
+                    None => None,
+                },
+            };
+            command::init(&client, &program_id, &keypair(sub_matches)?, &args)?
+        },
+        "exchange" => {
+            let args = ExchangeArgs {
+                escrow: required_pubkey_of(sub_matches, "escrow")?,
+                amount: value_of(sub_matches, "amount")?,
+                max_payment: value_of(sub_matches, "max-payment")?,
+                min_receive: value_of(sub_matches, "min-receive")?,
+                from: pubkey_of(sub_matches, "from")?,
+                to: pubkey_of(sub_matches, "to")?,
+                treasury: pubkey_of(sub_matches, "treasury")?,
+            };
+            let escrow_dump = sub_matches.value_of("escrow-dump").map(Path::new);
+            command::exchange(&client, &program_id, &keypair(sub_matches)?, &args, escrow_dump)?
+        },
+        "cancel" => {
+            let escrow = required_pubkey_of(sub_matches, "escrow")?;
+            let escrow_dump = sub_matches.value_of("escrow-dump").map(Path::new);
+            command::cancel(&client, &program_id, &keypair(sub_matches)?, &escrow, pubkey_of(sub_matches, "to")?, escrow_dump)?
+        },
+        "reset-timelock" => command::reset_time_lock(&client, &program_id, &keypair(sub_matches)?, &required_pubkey_of(sub_matches, "escrow")?)?,
+        "show" => {
+            if client.is_offline() {
+                return Err("show needs a cluster connection".into());
+            }
+            command::show(&client, &program_id, &required_pubkey_of(sub_matches, "escrow")?)?
+        },
+        _ => unreachable!("clap only accepts the subcommands above"),
+    };
+    println!("{}", output);
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run(&app().get_matches()) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_app() {
+        app().debug_assert();
+    }
+
+    #[test]
+    fn test_offline_requires_blockhash() {
+        let program_id = Pubkey::new_unique().to_string();
+        let args = ["escrow", "reset-timelock", "--program-id", &program_id, "--escrow", &program_id];
+        assert!(app().try_get_matches_from(args.iter().chain(&["--offline"])).is_err());
+        assert!(app().try_get_matches_from(args.iter().chain(&["--blockhash", "11111111111111111111111111111111"])).is_err());
+        assert!(app().try_get_matches_from(args.iter().chain(&["--offline", "--blockhash", "11111111111111111111111111111111"])).is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+use std::{cmp::Ordering, fmt};
+use chrono::DateTime;
+use paulx_escrow_contract::state::{ClockMode, Escrow};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+
+use crate::dump::RawAccount;
+
+// slot deadlines are turned into wall clock estimates at the target slot time
+const MS_PER_SLOT: i64 = 400;
+
+// the cluster's clock when the dump was taken, deadlines are compared against it
+#[derive(Clone, Copy, Debug)]
+pub struct Now {
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+impl Now {
+    pub fn in_mode(&self, clock_mode: ClockMode) -> u64 {
+        match clock_mode {
+            ClockMode::Slot => self.slot,
+            ClockMode::UnixTimestamp => u64::try_from(self.unix_timestamp).unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    // before unlock_time
+    Locked,
+    // can be taken
+    Open,
+    // past time_out, waiting for Cancel or Expire
+    Expired,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Locked => write!(f, "locked"),
+            Status::Open => write!(f, "open"),
+            Status::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+pub struct Listing {
+    pub pubkey: Pubkey,
+    pub escrow: Escrow,
+}
+
+impl Listing {
+    // same window as the program's Exchange check, both ends inclusive
+    pub fn status(&self, now: &Now) -> Status {
+        let now = now.in_mode(self.escrow.clock_mode);
+        if now < self.escrow.unlock_time {
+            Status::Locked
+        } else if now > self.escrow.time_out {
+            Status::Expired
+        } else {
+            Status::Open
+        }
+    }
+
+    // what the whole remaining deposit costs now, an auction's price having fallen since unlock
+    pub fn price(&self, now: &Now) -> u64 {
+        self.escrow.price_at(now.in_mode(self.escrow.clock_mode))
+    }
+
+    pub fn describe(&self, now: &Now) -> String {
+        let escrow = &self.escrow;
+        format!(
+            "{} by {}: {} of {} for {} of {}, {}, unlocks {}, times out {}",
+            self.pubkey,
+            escrow.initializer_pubkey,
+            escrow.deposit_amount,
+            escrow.deposit_mint,
+            self.price(now),
+            escrow.expected_mint,
+            self.status(now),
+            format_deadline(escrow.clock_mode, escrow.unlock_time, now),
+            format_deadline(escrow.clock_mode, escrow.time_out, now),
+        )
+    }
+
+    // cheaper asks first, price / deposit compared without rounding
+    fn cmp_price(&self, other: &Listing, now: &Now) -> Ordering {
+        let price = self.price(now) as u128 * other.escrow.deposit_amount as u128;
+        let other_price = other.price(now) as u128 * self.escrow.deposit_amount as u128;
+        price.cmp(&other_price)
+    }
+}
+
+#[derive(Default)]
+pub struct Filter {
+    pub initializer: Option<Pubkey>,
+    // matches either leg
+    pub mint: Option<Pubkey>,
+    pub status: Option<Status>,
+}
+
+impl Filter {
+    pub fn matches(&self, listing: &Listing, now: &Now) -> bool {
+        let escrow = &listing.escrow;
+        self.initializer.is_none_or(|initializer| escrow.initializer_pubkey == initializer)
+            && self.mint.is_none_or(|mint| escrow.deposit_mint == mint || escrow.expected_mint == mint)
+            && self.status.is_none_or(|status| listing.status(now) == status)
+    }
+}
+
+// every initialized escrow among the accounts, anything else (baskets, config, token accounts) is skipped.
+// with a program id only accounts owned by it are considered
+pub fn decode(accounts: &[RawAccount], program_id: Option<&Pubkey>) -> Vec<Listing> {
+    accounts
+        .iter()
+        .filter(|account| program_id.is_none_or(|program_id| account.owner == *program_id))
+        .filter(|account| account.data.len() == Escrow::LEN)
+        .filter_map(|account| {
+            Escrow::unpack(&account.data).ok().map(|escrow| Listing { pubkey: account.pubkey, escrow })
+        })
+        .collect()
+}
+
+// the matching escrows grouped by (deposit mint, expected mint), cheapest first, then soonest to time out
+pub fn order_book(accounts: &[RawAccount], program_id: Option<&Pubkey>, filter: &Filter, now: &Now) -> Vec<Listing> {
+    let mut listings: Vec<_> = decode(accounts, program_id)
+        .into_iter()
+        .filter(|listing| filter.matches(listing, now))
+        .collect();
+    listings.sort_by(|a, b| {
+        a.escrow.deposit_mint.cmp(&b.escrow.deposit_mint)
+            .then(a.escrow.expected_mint.cmp(&b.escrow.expected_mint))
+            .then_with(|| a.cmp_price(b, now))
+            .then(a.escrow.time_out.cmp(&b.escrow.time_out))
+            .then(a.pubkey.cmp(&b.pubkey))
+    });
+    listings
+}
+
+// "2023-03-01 12:00:00 UTC (in 2h 5m)" or "slot 1234 (in ~8m)"
+pub fn format_deadline(clock_mode: ClockMode, deadline: u64, now: &Now) -> String {
+    match clock_mode {
+        ClockMode::UnixTimestamp => {
+            let deadline = i64::try_from(deadline).unwrap_or(i64::MAX);
+            let date = DateTime::from_timestamp(deadline, 0)
+                .map_or_else(|| deadline.to_string(), |date| date.format("%Y-%m-%d %H:%M:%S UTC").to_string());
+            format!("{} ({})", date, format_relative(deadline.saturating_sub(now.unix_timestamp), ""))
+        },
+        ClockMode::Slot => {
+            let slots = i64::try_from(deadline).unwrap_or(i64::MAX).saturating_sub(i64::try_from(now.slot).unwrap_or(i64::MAX));
+            format!("slot {} ({})", deadline, format_relative(slots.saturating_mul(MS_PER_SLOT) / 1000, "~"))
+        },
+    }
+}
+
+// the two largest units of a signed duration in seconds
+fn format_relative(seconds: i64, approximate: &str) -> String {
+    if seconds == 0 {
+        return "now".to_string();
+    }
+    let mut remaining = seconds.unsigned_abs();
+    let mut parts = Vec::new();
+    for (unit, length) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if remaining >= length && parts.len() < 2 {
+            parts.push(format!("{}{}", remaining / length, unit));
+            remaining %= length;
+        } else if !parts.is_empty() {
+            break;
+        }
+    }
+    if seconds > 0 {
+        format!("in {}{}", approximate, parts.join(" "))
+    } else {
+        format!("{}{} ago", approximate, parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_relative() {
+        assert_eq!(format_relative(0, ""), "now");
+        assert_eq!(format_relative(7_500, ""), "in 2h 5m");
+        assert_eq!(format_relative(-90, "~"), "~1m 30s ago");
+        // units below the largest two are dropped, as are empty ones in between
+        assert_eq!(format_relative(86_400 + 59, ""), "in 1d");
+    }
+
+    #[test]
+    fn test_format_deadline() {
+        let now = Now { slot: 100, unix_timestamp: 1_677_672_000 };
+        assert_eq!(format_deadline(ClockMode::UnixTimestamp, 1_677_679_500, &now), "2023-03-01 14:05:00 UTC (in 2h 5m)");
+        assert_eq!(format_deadline(ClockMode::Slot, 1_300, &now), "slot 1300 (in ~8m)");
+        assert_eq!(format_deadline(ClockMode::Slot, 50, &now), "slot 50 (~20s ago)");
+    }
+}
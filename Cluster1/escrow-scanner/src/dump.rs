@@ -0,0 +1,83 @@
+use std::{fs, path::Path, str::FromStr};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::ScanError;
+
+// an account as written by `solana account <pubkey> --output json`
+#[derive(Deserialize)]
+struct KeyedAccount {
+    pubkey: String,
+    account: UiAccount,
+}
+
+#[derive(Deserialize)]
+struct UiAccount {
+    lamports: u64,
+    // [data, encoding]
+    data: (String, String),
+    owner: String,
+}
+
+// a dump holds either one account or a list of them, as returned by getProgramAccounts
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Dump {
+    One(KeyedAccount),
+    Many(Vec<KeyedAccount>),
+}
+
+pub struct RawAccount {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+}
+
+pub fn parse(json: &str) -> Result<Vec<RawAccount>, ScanError> {
+    let accounts = match serde_json::from_str(json)? {
+        Dump::One(account) => vec![account],
+        Dump::Many(accounts) => accounts,
+    };
+    accounts.into_iter().map(decode_account).collect()
+}
+
+// a single dump file, or every .json file of a directory in file name order
+pub fn load(path: &Path) -> Result<Vec<RawAccount>, ScanError> {
+    if !path.is_dir() {
+        let json = fs::read_to_string(path).map_err(|err| ScanError::Io(path.to_path_buf(), err))?;
+        return parse(&json);
+    }
+
+    let mut files = fs::read_dir(path)
+        .map_err(|err| ScanError::Io(path.to_path_buf(), err))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| ScanError::Io(path.to_path_buf(), err))?;
+    files.retain(|file| file.extension().is_some_and(|extension| extension == "json"));
+    files.sort();
+
+    let mut accounts = Vec::new();
+    for file in files {
+        accounts.extend(load(&file)?);
+    }
+    Ok(accounts)
+}
+
+fn decode_account(keyed_account: KeyedAccount) -> Result<RawAccount, ScanError> {
+    let (data, encoding) = keyed_account.account.data;
+    if encoding != "base64" {
+        return Err(ScanError::UnsupportedEncoding(encoding));
+    }
+    Ok(RawAccount {
+        pubkey: parse_pubkey(&keyed_account.pubkey)?,
+        owner: parse_pubkey(&keyed_account.account.owner)?,
+        lamports: keyed_account.account.lamports,
+        data: STANDARD.decode(data)?,
+    })
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, ScanError> {
+    Pubkey::from_str(pubkey).map_err(|_| ScanError::InvalidPubkey(pubkey.to_string()))
+}
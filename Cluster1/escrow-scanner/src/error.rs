@@ -0,0 +1,20 @@
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ScanError {
+    #[error("Failed to read {0}: {1}")]
+    Io(PathBuf, io::Error),
+
+    #[error("Invalid account dump: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unsupported account data encoding {0}, dump with --output json")]
+    UnsupportedEncoding(String),
+
+    #[error("Invalid base64 account data: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("Invalid pubkey {0}")]
+    InvalidPubkey(String),
+}
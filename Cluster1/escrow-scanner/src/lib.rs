@@ -0,0 +1,4 @@
+// lists the escrows found in `solana account --output json` dumps of the escrow program's accounts
+pub mod book;
+pub mod dump;
+pub mod error;
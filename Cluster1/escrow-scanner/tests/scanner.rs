@@ -0,0 +1,114 @@
+use std::{path::PathBuf, str::FromStr};
+use escrow_scanner::{
+    book::{decode, order_book, Filter, Now, Status},
+    dump::{load, parse},
+    error::ScanError,
+};
+use paulx_escrow_contract::state::{DutchAuction, Escrow};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+
+// the fixtures use keys made of a single repeated byte: escrows 21-25, initializers 1-3,
+// mints 11 and 12, the escrow program 9 and another program 8
+const PROGRAM: &str = "cGfHiC6Kgg3FpFZvgwGcswsCRtp4aBP2fzuXRQPizuN";
+const ALICE: &str = "4vJ9JU1bJJE96FWSJKvHsmmFADCg4gpZQff4P3bkLKi";
+const MINT_X: &str = "k7FaK87WHGVXzkaoHb7CdVPgkKDQhZ29VLDeBVbDfYn";
+const ESCROW_A: &str = "2RJD1KnDRGEkvuFfAGrJ7PD28LRE9LRDjZznDywagzmr";
+const ESCROW_B: &str = "2VDW9dFE1ZXz4zWAbaBDQFynNVdRpQ73HyfSHMzBSL6Z";
+const ESCROW_C: &str = "2Z8oHviEbrqDD5kg2sW8h8kYceqdVTnrrPL6Lk2nBfRG";
+const ESCROW_D: &str = "2d46SEBFCA8SMB1BUAq3z1XJrp3qAXUgQnzkQ85Nvzjy";
+const ESCROW_E: &str = "2gyPaXeFnTRfVGFguU9yGtJ56yG2qbAVyCfQTW7ygL4g";
+
+// ten minutes after escrow B unlocks
+const NOW: Now = Now { slot: 500, unix_timestamp: 1_677_672_600 };
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn key(pubkey: &str) -> Pubkey {
+    Pubkey::from_str(pubkey).unwrap()
+}
+
+fn keys(filter: &Filter, program_id: Option<&Pubkey>) -> Vec<Pubkey> {
+    let accounts = load(&fixtures()).unwrap();
+    order_book(&accounts, program_id, filter, &NOW).iter().map(|listing| listing.pubkey).collect()
+}
+
+#[test]
+fn test_load() {
+    // one account per file plus a getProgramAccounts style list, README.txt is skipped
+    let accounts = load(&fixtures()).unwrap();
+    assert_eq!(accounts.len(), 7);
+    let accounts = load(&fixtures().join("escrow_a.json")).unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].pubkey, key(ESCROW_A));
+    assert_eq!(accounts[0].owner, key(PROGRAM));
+
+    // the config account, the zeroed account and the foreign escrow are left out
+    let accounts = load(&fixtures()).unwrap();
+    assert_eq!(decode(&accounts, Some(&key(PROGRAM))).len(), 4);
+    assert_eq!(decode(&accounts, None).len(), 5);
+}
+
+#[test]
+fn test_load_rejects_bad_dumps() {
+    assert!(matches!(load(&fixtures().join("missing.json")), Err(ScanError::Io(..))));
+    assert!(matches!(parse("{}"), Err(ScanError::Json(_))));
+    let dump = format!(r#"{{"pubkey": "{ESCROW_A}", "account": {{"lamports": 1, "data": ["3yZe7d", "base58"], "owner": "{PROGRAM}"}}}}"#);
+    assert!(matches!(parse(&dump), Err(ScanError::UnsupportedEncoding(encoding)) if encoding == "base58"));
+    let dump = format!(r#"{{"pubkey": "not a key", "account": {{"lamports": 1, "data": ["", "base64"], "owner": "{PROGRAM}"}}}}"#);
+    assert!(matches!(parse(&dump), Err(ScanError::InvalidPubkey(_))));
+}
+
+#[test]
+fn test_order_book_sorting() {
+    // X for Y first: B asks 0.5, D and A both 0.6 with D timing out sooner, then Y for X
+    assert_eq!(keys(&Filter::default(), Some(&key(PROGRAM))), [ESCROW_B, ESCROW_D, ESCROW_A, ESCROW_C].map(key));
+    // the escrow of the other program asks 1 for 1
+    assert_eq!(keys(&Filter::default(), None), [ESCROW_B, ESCROW_D, ESCROW_A, ESCROW_E, ESCROW_C].map(key));
+}
+
+#[test]
+fn test_order_book_filters() {
+    let program_id = key(PROGRAM);
+    let by_alice = Filter { initializer: Some(key(ALICE)), ..Filter::default() };
+    assert_eq!(keys(&by_alice, Some(&program_id)), [ESCROW_A, ESCROW_C].map(key));
+
+    let expired = Filter { status: Some(Status::Expired), ..Filter::default() };
+    assert_eq!(keys(&expired, Some(&program_id)), [ESCROW_C].map(key));
+
+    let open_x = Filter { mint: Some(key(MINT_X)), status: Some(Status::Open), ..Filter::default() };
+    assert_eq!(keys(&open_x, Some(&program_id)), [ESCROW_B, ESCROW_D, ESCROW_A].map(key));
+
+    let unknown_mint = Filter { mint: Some(Pubkey::new_unique()), ..Filter::default() };
+    assert!(keys(&unknown_mint, Some(&program_id)).is_empty());
+}
+
+#[test]
+fn test_describe() {
+    let accounts = load(&fixtures()).unwrap();
+    let book = order_book(&accounts, Some(&key(PROGRAM)), &Filter::default(), &NOW);
+    assert_eq!(
+        book[0].describe(&NOW),
+        format!(
+            "{ESCROW_B} by 8qbHbw2BbbTHBW1sbeqakYXVKRQM8Ne7pLK7m6CVfeR: 10 of {MINT_X} for 5 of p2Yicb86aZig616Eav2VWG9vuXR5mEqhtzshZYBxzsV, open, \
+             unlocks 2023-03-01 12:00:00 UTC (10m ago), times out 2023-03-01 14:05:00 UTC (in 1h 55m)"
+        )
+    );
+    assert!(book[3].describe(&NOW).contains("expired, unlocks slot 10 (~3m 16s ago), times out slot 50 (~3m ago)"));
+}
+
+#[test]
+fn test_auction_priced_at_now() {
+    // D asks 60 for 100 between slots 100 and 900, as an auction falling to 20 it asks 40 at slot 500
+    let mut accounts = load(&fixtures()).unwrap();
+    let account = accounts.iter_mut().find(|account| account.pubkey == key(ESCROW_D)).unwrap();
+    let mut escrow = Escrow::unpack(&account.data).unwrap();
+    escrow.auction = Some(DutchAuction { start_price: 60, end_price: 20 });
+    Escrow::pack(escrow, &mut account.data).unwrap();
+
+    let book = order_book(&accounts, Some(&key(PROGRAM)), &Filter::default(), &NOW);
+    assert_eq!(book.iter().map(|listing| listing.pubkey).collect::<Vec<_>>(), [ESCROW_D, ESCROW_B, ESCROW_A, ESCROW_C].map(key));
+    assert_eq!(book[0].price(&NOW), 40);
+    assert!(book[0].describe(&NOW).contains(&format!("100 of {MINT_X} for 40 of")));
+}
@@ -0,0 +1,1060 @@
+use arrayref::{array_ref, array_refs};
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    bpf_loader_upgradeable,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{rent::Rent, Sysvar},
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Account as TokenAccount};
+
+use crate::{error::EscrowError, state::{AssetKind, BasketEscrow, Config, CounterOffer, Escrow}};
+
+// UpgradeableLoaderState::ProgramData is the fourth variant, followed by the slot and an Option<Pubkey>
+const PROGRAM_DATA_TAG: u32 = 3;
+const PROGRAM_DATA_METADATA_LEN: usize = 45;
+
+// the accounts of each instruction, checked for signers, writability, owners, expected keys and
+// duplicates before the processor touches them. what is left to the processor are amounts and deadlines
+
+pub struct InitEscrowAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub temp_token_account: &'a AccountInfo<'info>,
+    pub token_to_receive_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub rent: Rent,
+    pub token_program: &'a AccountInfo<'info>,
+    pub refund_token_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> InitEscrowAccounts<'a, 'info> {
+    pub fn validate(
+        accounts: &'a [AccountInfo<'info>],
+        deposit_kind: AssetKind,
+        expected_kind: AssetKind,
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+        let temp_token_account = next_account_info(account_info_iter)?;
+        let token_to_receive_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let rent = Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let refund_token_account = next_account_info(account_info_iter)?;
+
+        check_signer(initializer)?;
+        check_program_owned(escrow_account, program_id)?;
+        check_writable(escrow_account)?;
+        if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
+            return Err(EscrowError::NotRentExempt.into());
+        }
+        check_distinct(temp_token_account, &[token_to_receive_account, refund_token_account])?;
+
+        match deposit_kind {
+            AssetKind::Token => {
+                check_writable(temp_token_account)?;
+                check_token_program(token_program, temp_token_account)?;
+                let temp_token_account_info = unpack_token_account(temp_token_account)?;
+                // only the initializer's own tokens can be handed to the escrow
+                if temp_token_account_info.owner != *initializer.key {
+                    return Err(EscrowError::WrongTempAccount.into());
+                }
+                check_refund_account(refund_token_account, &temp_token_account_info.mint, initializer)?;
+            },
+            AssetKind::Native => {
+                // the escrow account is its own lamport vault, funded above rent by the initializer
+                check_key(temp_token_account, escrow_account.key, EscrowError::WrongTempAccount)?;
+                check_key(refund_token_account, initializer.key, EscrowError::WrongReceiveAccount)?;
+            },
+        }
+
+        check_token_to_receive_account(token_to_receive_account, expected_kind)?;
+
+        Ok(Self { initializer, temp_token_account, token_to_receive_account, escrow_account, rent, token_program, refund_token_account })
+    }
+}
+
+pub struct InitEscrowWithVaultAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub deposit_token_account: &'a AccountInfo<'info>,
+    pub vault: &'a AccountInfo<'info>,
+    pub token_to_receive_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub refund_token_account: &'a AccountInfo<'info>,
+    pub deposit_mint: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub system_program_account: &'a AccountInfo<'info>,
+    pub associated_token_program: &'a AccountInfo<'info>,
+    // whatever is left is passed on to the deposit mint's transfer hook
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub bump_seed: u8,
+}
+
+impl<'a, 'info> InitEscrowWithVaultAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], expected_kind: AssetKind, program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+        let deposit_token_account = next_account_info(account_info_iter)?;
+        let vault = next_account_info(account_info_iter)?;
+        let token_to_receive_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let refund_token_account = next_account_info(account_info_iter)?;
+        let deposit_mint = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let associated_token_program = next_account_info(account_info_iter)?;
+        let additional_accounts = account_info_iter.as_slice();
+
+        // the initializer pays for the vault
+        check_signer(initializer)?;
+        check_writable(initializer)?;
+        check_program_owned(escrow_account, program_id)?;
+        check_writable(escrow_account)?;
+        if !Rent::get()?.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
+            return Err(EscrowError::NotRentExempt.into());
+        }
+
+        check_writable(deposit_token_account)?;
+        check_token_program(token_program, deposit_token_account)?;
+        let deposit_mint_pubkey = unpack_token_account(deposit_token_account)?.mint;
+        check_mint_account(deposit_mint, &deposit_mint_pubkey)?;
+        check_refund_account(refund_token_account, &deposit_mint_pubkey, initializer)?;
+        check_token_to_receive_account(token_to_receive_account, expected_kind)?;
+
+        let (pda, bump_seed) = Escrow::find_pda(escrow_account.key, program_id);
+        check_key(pda_account, &pda, EscrowError::WrongPda)?;
+        check_key(vault, &Escrow::find_vault(escrow_account.key, deposit_mint.key, token_program.key, program_id), EscrowError::WrongTempAccount)?;
+        check_writable(vault)?;
+        check_system_program(system_program_account)?;
+        if *associated_token_program.key != spl_associated_token_account::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        Ok(Self {
+            initializer,
+            deposit_token_account,
+            vault,
+            token_to_receive_account,
+            escrow_account,
+            token_program,
+            refund_token_account,
+            deposit_mint,
+            pda_account,
+            system_program_account,
+            associated_token_program,
+            additional_accounts,
+            bump_seed,
+        })
+    }
+}
+
+pub struct ExchangeAccounts<'a, 'info> {
+    pub taker: &'a AccountInfo<'info>,
+    pub takers_sending_token_account: &'a AccountInfo<'info>,
+    pub takers_token_to_receive_account: &'a AccountInfo<'info>,
+    pub pdas_temp_token_account: &'a AccountInfo<'info>,
+    pub initializers_main_account: &'a AccountInfo<'info>,
+    pub initializers_token_to_receive_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub system_program_account: &'a AccountInfo<'info>,
+    pub treasury_account: &'a AccountInfo<'info>,
+    pub deposit_mint: &'a AccountInfo<'info>,
+    pub expected_mint: &'a AccountInfo<'info>,
+    pub expected_token_program: &'a AccountInfo<'info>,
+    // the first account after the fixed ones when the escrow has a price band, checked against its oracle
+    pub price_feed: Option<&'a AccountInfo<'info>>,
+    // whatever is left is passed on to transfer hooks
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub escrow_info: Escrow,
+    // none until the config is created, then the treasury has been checked against it
+    pub config: Option<Config>,
+}
+
+impl<'a, 'info> ExchangeAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let taker = next_account_info(account_info_iter)?;
+        let takers_sending_token_account = next_account_info(account_info_iter)?;
+        let takers_token_to_receive_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let initializers_main_account = next_account_info(account_info_iter)?;
+        let initializers_token_to_receive_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let config_account = next_account_info(account_info_iter)?;
+        let treasury_account = next_account_info(account_info_iter)?;
+        let deposit_mint = next_account_info(account_info_iter)?;
+        let expected_mint = next_account_info(account_info_iter)?;
+        let expected_token_program = next_account_info(account_info_iter)?;
+        let additional_accounts = account_info_iter.as_slice();
+
+        check_signer(taker)?;
+        let escrow_info = unpack_escrow(escrow_account, program_id)?;
+
+        if escrow_info.allowed_taker.is_some_and(|allowed_taker| allowed_taker != *taker.key) {
+            return Err(EscrowError::UnauthorizedTaker.into());
+        }
+
+        let (price_feed, additional_accounts) = match &escrow_info.price_band {
+            Some(price_band) => {
+                let (price_feed, additional_accounts) = additional_accounts.split_first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+                check_key(price_feed, &price_band.oracle, EscrowError::WrongOracle)?;
+                (Some(price_feed), additional_accounts)
+            },
+            None => (None, additional_accounts),
+        };
+
+        check_key(pdas_temp_token_account, &escrow_info.temp_token_account_pubkey, EscrowError::WrongTempAccount)?;
+        check_key(initializers_main_account, &escrow_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+        check_key(initializers_token_to_receive_account, &escrow_info.initializer_token_to_receive_account_pubkey, EscrowError::WrongReceiveAccount)?;
+        check_key(pda_account, &Escrow::create_pda(escrow_account.key, escrow_info.bump_seed, program_id)?, EscrowError::WrongPda)?;
+        check_system_program(system_program_account)?;
+        for account in [takers_sending_token_account, takers_token_to_receive_account, pdas_temp_token_account, initializers_main_account, initializers_token_to_receive_account] {
+            check_writable(account)?;
+        }
+        check_distinct(pdas_temp_token_account, &[takers_sending_token_account, takers_token_to_receive_account, initializers_token_to_receive_account])?;
+
+        if escrow_info.deposit_kind == AssetKind::Token {
+            check_mint(takers_token_to_receive_account, &escrow_info.deposit_mint, EscrowError::DepositMintMismatch)?;
+            check_token_program(token_program, pdas_temp_token_account)?;
+            check_mint_account(deposit_mint, &escrow_info.deposit_mint)?;
+        }
+        if escrow_info.expected_kind == AssetKind::Token {
+            check_mint(takers_sending_token_account, &escrow_info.expected_mint, EscrowError::ExpectedMintMismatch)?;
+            check_mint(initializers_token_to_receive_account, &escrow_info.expected_mint, EscrowError::ExpectedMintMismatch)?;
+            check_token_program(expected_token_program, initializers_token_to_receive_account)?;
+            check_mint_account(expected_mint, &escrow_info.expected_mint)?;
+        }
+
+        let config = load_config(config_account, program_id)?;
+        if let Some(config) = &config {
+            check_key(treasury_account, &config.treasury_account(&escrow_info, expected_token_program.key), EscrowError::WrongTreasury)?;
+            check_writable(treasury_account)?;
+        }
+
+        Ok(Self {
+            taker,
+            takers_sending_token_account,
+            takers_token_to_receive_account,
+            pdas_temp_token_account,
+            initializers_main_account,
+            initializers_token_to_receive_account,
+            escrow_account,
+            token_program,
+            pda_account,
+            system_program_account,
+            treasury_account,
+            deposit_mint,
+            expected_mint,
+            expected_token_program,
+            price_feed,
+            additional_accounts,
+            escrow_info,
+            config,
+        })
+    }
+}
+
+pub struct CancelAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub pda_token_account: &'a AccountInfo<'info>,
+    pub initializer_main_account: &'a AccountInfo<'info>,
+    pub initializer_sent_token_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub deposit_mint: &'a AccountInfo<'info>,
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub escrow_info: Escrow,
+}
+
+impl<'a, 'info> CancelAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+        let pda_token_account = next_account_info(account_info_iter)?;
+        let initializer_main_account = next_account_info(account_info_iter)?;
+        let initializer_sent_token_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let deposit_mint = next_account_info(account_info_iter)?;
+        let additional_accounts = account_info_iter.as_slice();
+
+        check_signer(initializer)?;
+        let escrow_info = unpack_escrow(escrow_account, program_id)?;
+
+        check_key(initializer, &escrow_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+        check_key(initializer_main_account, &escrow_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+        check_key(pda_token_account, &escrow_info.temp_token_account_pubkey, EscrowError::WrongTempAccount)?;
+        check_key(pda_account, &Escrow::create_pda(escrow_account.key, escrow_info.bump_seed, program_id)?, EscrowError::WrongPda)?;
+        for account in [pda_token_account, initializer_main_account, initializer_sent_token_account] {
+            check_writable(account)?;
+        }
+        check_distinct(pda_token_account, &[initializer_sent_token_account])?;
+
+        if escrow_info.deposit_kind == AssetKind::Token {
+            check_mint(initializer_sent_token_account, &escrow_info.deposit_mint, EscrowError::DepositMintMismatch)?;
+            check_token_program(token_program, pda_token_account)?;
+            check_mint_account(deposit_mint, &escrow_info.deposit_mint)?;
+        }
+
+        Ok(Self {
+            initializer,
+            pda_token_account,
+            initializer_main_account,
+            initializer_sent_token_account,
+            escrow_account,
+            token_program,
+            pda_account,
+            deposit_mint,
+            additional_accounts,
+            escrow_info,
+        })
+    }
+}
+
+pub struct QuoteAccounts<'a, 'info> {
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub escrow_info: Escrow,
+}
+
+impl<'a, 'info> QuoteAccounts<'a, 'info> {
+    // quoting only reads the escrow, it needn't be writable
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        check_program_owned(escrow_account, program_id)?;
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        Ok(Self { escrow_account, escrow_info })
+    }
+}
+
+pub struct ExpireAccounts<'a, 'info> {
+    pub cranker: &'a AccountInfo<'info>,
+    pub pda_token_account: &'a AccountInfo<'info>,
+    pub initializer_main_account: &'a AccountInfo<'info>,
+    pub refund_token_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub deposit_mint: &'a AccountInfo<'info>,
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub escrow_info: Escrow,
+}
+
+impl<'a, 'info> ExpireAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let cranker = next_account_info(account_info_iter)?;
+        let pda_token_account = next_account_info(account_info_iter)?;
+        let initializer_main_account = next_account_info(account_info_iter)?;
+        let refund_token_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let deposit_mint = next_account_info(account_info_iter)?;
+        let additional_accounts = account_info_iter.as_slice();
+
+        check_signer(cranker)?;
+        let escrow_info = unpack_escrow(escrow_account, program_id)?;
+
+        check_key(initializer_main_account, &escrow_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+        check_key(pda_token_account, &escrow_info.temp_token_account_pubkey, EscrowError::WrongTempAccount)?;
+        check_key(refund_token_account, &escrow_info.refund_token_account_pubkey, EscrowError::WrongReceiveAccount)?;
+        check_key(pda_account, &Escrow::create_pda(escrow_account.key, escrow_info.bump_seed, program_id)?, EscrowError::WrongPda)?;
+        for account in [cranker, pda_token_account, initializer_main_account, refund_token_account] {
+            check_writable(account)?;
+        }
+
+        if escrow_info.deposit_kind == AssetKind::Token {
+            check_token_program(token_program, pda_token_account)?;
+            check_mint_account(deposit_mint, &escrow_info.deposit_mint)?;
+        }
+
+        Ok(Self {
+            cranker,
+            pda_token_account,
+            initializer_main_account,
+            refund_token_account,
+            escrow_account,
+            token_program,
+            pda_account,
+            deposit_mint,
+            additional_accounts,
+            escrow_info,
+        })
+    }
+}
+
+// shared by the time lock instructions
+pub struct TimeLockAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub escrow_info: Escrow,
+}
+
+impl<'a, 'info> TimeLockAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+
+        check_signer(initializer)?;
+        let escrow_info = unpack_escrow(escrow_account, program_id)?;
+        check_key(initializer, &escrow_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+
+        Ok(Self { initializer, escrow_account, escrow_info })
+    }
+}
+
+pub struct InitBasketAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub basket_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub vaults: Vec<&'a AccountInfo<'info>>,
+    pub token_to_receive_accounts: Vec<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> InitBasketAccounts<'a, 'info> {
+    pub fn validate(
+        accounts: &'a [AccountInfo<'info>],
+        deposit_count: usize,
+        payment_count: usize,
+        program_id: &Pubkey,
+    ) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+        let basket_account = next_account_info(account_info_iter)?;
+        let rent = Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let vaults = (0..deposit_count)
+            .map(|_| next_account_info(account_info_iter))
+            .collect::<Result<Vec<_>, _>>()?;
+        let token_to_receive_accounts = (0..payment_count)
+            .map(|_| next_account_info(account_info_iter))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        check_signer(initializer)?;
+        check_program_owned(basket_account, program_id)?;
+        check_writable(basket_account)?;
+        if basket_account.data_len() != BasketEscrow::len(deposit_count, payment_count) {
+            return Err(EscrowError::InvalidBasket.into());
+        }
+        if !rent.is_exempt(basket_account.lamports(), basket_account.data_len()) {
+            return Err(EscrowError::NotRentExempt.into());
+        }
+        if !BasketEscrow::is_uninitialized(&basket_account.try_borrow_data()?) {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        for (index, vault) in vaults.iter().enumerate() {
+            // the same vault twice would be paid out twice
+            check_distinct(vault, &vaults[index + 1..])?;
+            check_distinct(vault, &token_to_receive_accounts)?;
+            check_writable(vault)?;
+            check_token_program(token_program, vault)?;
+            if unpack_token_account(vault)?.owner != *initializer.key {
+                return Err(EscrowError::WrongTempAccount.into());
+            }
+        }
+        for token_to_receive_account in &token_to_receive_accounts {
+            check_token_program(token_program, token_to_receive_account)?;
+        }
+
+        Ok(Self { initializer, basket_account, token_program, vaults, token_to_receive_accounts })
+    }
+}
+
+pub struct ExchangeBasketAccounts<'a, 'info> {
+    pub taker: &'a AccountInfo<'info>,
+    pub basket_account: &'a AccountInfo<'info>,
+    pub initializers_main_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    // (vault, taker's receive account, mint) per deposit leg
+    pub releases: Vec<(&'a AccountInfo<'info>, &'a AccountInfo<'info>, &'a AccountInfo<'info>)>,
+    // (taker's sending account, initializer's receive account, mint) per payment leg
+    pub payments: Vec<(&'a AccountInfo<'info>, &'a AccountInfo<'info>, &'a AccountInfo<'info>)>,
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub basket_info: BasketEscrow,
+}
+
+impl<'a, 'info> ExchangeBasketAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let taker = next_account_info(account_info_iter)?;
+        let basket_account = next_account_info(account_info_iter)?;
+        let initializers_main_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+
+        check_signer(taker)?;
+        let basket_info = unpack_basket(basket_account, program_id)?;
+        check_key(initializers_main_account, &basket_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+        check_writable(initializers_main_account)?;
+        check_key(pda_account, &Escrow::create_pda(basket_account.key, basket_info.bump_seed, program_id)?, EscrowError::WrongPda)?;
+
+        let mut releases = Vec::with_capacity(basket_info.deposits.len());
+        for leg in &basket_info.deposits {
+            let vault = next_account_info(account_info_iter)?;
+            let takers_token_to_receive_account = next_account_info(account_info_iter)?;
+            let mint = next_account_info(account_info_iter)?;
+            check_key(vault, &leg.token_account_pubkey, EscrowError::WrongTempAccount)?;
+            check_distinct(vault, &[takers_token_to_receive_account])?;
+            check_writable(vault)?;
+            check_writable(takers_token_to_receive_account)?;
+            check_token_program(token_program, vault)?;
+            check_mint_account(mint, &leg.mint)?;
+            check_mint(takers_token_to_receive_account, &leg.mint, EscrowError::DepositMintMismatch)?;
+            releases.push((vault, takers_token_to_receive_account, mint));
+        }
+
+        let mut payments = Vec::with_capacity(basket_info.payments.len());
+        for leg in &basket_info.payments {
+            let takers_sending_token_account = next_account_info(account_info_iter)?;
+            let initializers_token_to_receive_account = next_account_info(account_info_iter)?;
+            let mint = next_account_info(account_info_iter)?;
+            check_key(initializers_token_to_receive_account, &leg.token_account_pubkey, EscrowError::WrongReceiveAccount)?;
+            check_writable(takers_sending_token_account)?;
+            check_writable(initializers_token_to_receive_account)?;
+            check_mint_account(mint, &leg.mint)?;
+            check_mint(takers_sending_token_account, &leg.mint, EscrowError::ExpectedMintMismatch)?;
+            payments.push((takers_sending_token_account, initializers_token_to_receive_account, mint));
+        }
+        let additional_accounts = account_info_iter.as_slice();
+
+        // a payment sent into one of the vaults would be released right back to the taker
+        for (takers_sending_token_account, initializers_token_to_receive_account, _mint) in &payments {
+            for (vault, _takers_token_to_receive_account, _mint) in &releases {
+                check_distinct(vault, &[takers_sending_token_account, initializers_token_to_receive_account])?;
+            }
+        }
+
+        Ok(Self { taker, basket_account, initializers_main_account, token_program, pda_account, releases, payments, additional_accounts, basket_info })
+    }
+}
+
+pub struct CancelBasketAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub basket_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    // (vault, refund account, mint) per deposit leg
+    pub refunds: Vec<(&'a AccountInfo<'info>, &'a AccountInfo<'info>, &'a AccountInfo<'info>)>,
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub basket_info: BasketEscrow,
+}
+
+impl<'a, 'info> CancelBasketAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+        let basket_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+
+        check_signer(initializer)?;
+        let basket_info = unpack_basket(basket_account, program_id)?;
+        check_key(initializer, &basket_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+        check_writable(initializer)?;
+        check_key(pda_account, &Escrow::create_pda(basket_account.key, basket_info.bump_seed, program_id)?, EscrowError::WrongPda)?;
+
+        let mut refunds = Vec::with_capacity(basket_info.deposits.len());
+        for leg in &basket_info.deposits {
+            let vault = next_account_info(account_info_iter)?;
+            let refund_token_account = next_account_info(account_info_iter)?;
+            let mint = next_account_info(account_info_iter)?;
+            check_key(vault, &leg.token_account_pubkey, EscrowError::WrongTempAccount)?;
+            check_distinct(vault, &[refund_token_account])?;
+            check_writable(vault)?;
+            check_writable(refund_token_account)?;
+            check_token_program(token_program, vault)?;
+            check_mint_account(mint, &leg.mint)?;
+            check_mint(refund_token_account, &leg.mint, EscrowError::DepositMintMismatch)?;
+            refunds.push((vault, refund_token_account, mint));
+        }
+        let additional_accounts = account_info_iter.as_slice();
+
+        Ok(Self { initializer, basket_account, token_program, pda_account, refunds, additional_accounts, basket_info })
+    }
+}
+
+pub struct InitConfigAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub config_account: &'a AccountInfo<'info>,
+    pub treasury_owner: &'a AccountInfo<'info>,
+    pub system_program_account: &'a AccountInfo<'info>,
+    pub bump_seed: u8,
+}
+
+impl<'a, 'info> InitConfigAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let admin = next_account_info(account_info_iter)?;
+        let config_account = next_account_info(account_info_iter)?;
+        let treasury_owner = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let program_data = next_account_info(account_info_iter)?;
+
+        check_signer(admin)?;
+        let (config_pda, bump_seed) = Config::find_pda(program_id);
+        check_key(config_account, &config_pda, EscrowError::WrongPda)?;
+        // the config is created once and only by the program's upgrade authority, who becomes its admin
+        if config_account.owner == program_id {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        check_upgrade_authority(program_data, admin, program_id)?;
+        check_writable(admin)?;
+        check_writable(config_account)?;
+        check_system_program(system_program_account)?;
+
+        Ok(Self { admin, config_account, treasury_owner, system_program_account, bump_seed })
+    }
+}
+
+pub struct UpdateConfigAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub config_account: &'a AccountInfo<'info>,
+    pub treasury_owner: &'a AccountInfo<'info>,
+    pub new_admin: &'a AccountInfo<'info>,
+    pub config_info: Config,
+}
+
+impl<'a, 'info> UpdateConfigAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let admin = next_account_info(account_info_iter)?;
+        let config_account = next_account_info(account_info_iter)?;
+        let treasury_owner = next_account_info(account_info_iter)?;
+        let new_admin = next_account_info(account_info_iter)?;
+
+        check_signer(admin)?;
+        // a new admin has to sign so the config can't be handed to a key nobody holds
+        check_signer(new_admin)?;
+
+        let config_info = load_config(config_account, program_id)?.ok_or(ProgramError::UninitializedAccount)?;
+        check_writable(config_account)?;
+        if config_info.admin_pubkey != *admin.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self { admin, config_account, treasury_owner, new_admin, config_info })
+    }
+}
+
+pub struct ProposeCounterAccounts<'a, 'info> {
+    pub taker: &'a AccountInfo<'info>,
+    pub takers_sending_token_account: &'a AccountInfo<'info>,
+    pub takers_token_to_receive_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub counter_account: &'a AccountInfo<'info>,
+    pub counter_vault: &'a AccountInfo<'info>,
+    pub expected_mint: &'a AccountInfo<'info>,
+    pub expected_token_program: &'a AccountInfo<'info>,
+    pub system_program_account: &'a AccountInfo<'info>,
+    pub associated_token_program: &'a AccountInfo<'info>,
+    // whatever is left is passed on to the expected mint's transfer hook
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub escrow_info: Escrow,
+    pub bump_seed: u8,
+}
+
+impl<'a, 'info> ProposeCounterAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let taker = next_account_info(account_info_iter)?;
+        let takers_sending_token_account = next_account_info(account_info_iter)?;
+        let takers_token_to_receive_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let counter_account = next_account_info(account_info_iter)?;
+        let counter_vault = next_account_info(account_info_iter)?;
+        let expected_mint = next_account_info(account_info_iter)?;
+        let expected_token_program = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let associated_token_program = next_account_info(account_info_iter)?;
+        let additional_accounts = account_info_iter.as_slice();
+
+        // the taker pays for the counter-offer and its vault
+        check_signer(taker)?;
+        check_writable(taker)?;
+        let escrow_info = unpack_escrow(escrow_account, program_id)?;
+
+        if escrow_info.allowed_taker.is_some_and(|allowed_taker| allowed_taker != *taker.key) {
+            return Err(EscrowError::UnauthorizedTaker.into());
+        }
+        // the payment is locked in a token vault, a lamport payment has nowhere to wait
+        if escrow_info.expected_kind != AssetKind::Token {
+            return Err(EscrowError::InvalidCounter.into());
+        }
+
+        check_writable(takers_sending_token_account)?;
+        check_token_program(expected_token_program, takers_sending_token_account)?;
+        check_mint(takers_sending_token_account, &escrow_info.expected_mint, EscrowError::ExpectedMintMismatch)?;
+        check_mint_account(expected_mint, &escrow_info.expected_mint)?;
+        check_distinct(takers_token_to_receive_account, &[escrow_account])?;
+        if escrow_info.deposit_kind == AssetKind::Token {
+            check_mint(takers_token_to_receive_account, &escrow_info.deposit_mint, EscrowError::DepositMintMismatch)?;
+            check_distinct(takers_token_to_receive_account, &[takers_sending_token_account])?;
+        }
+
+        let (counter, bump_seed) = CounterOffer::find_pda(escrow_account.key, taker.key, program_id);
+        check_key(counter_account, &counter, EscrowError::WrongCounterAccount)?;
+        if counter_account.owner == program_id {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        let vault = CounterOffer::find_vault(escrow_account.key, taker.key, expected_mint.key, expected_token_program.key, program_id);
+        check_key(counter_vault, &vault, EscrowError::WrongCounterAccount)?;
+        check_writable(counter_account)?;
+        check_writable(counter_vault)?;
+        check_system_program(system_program_account)?;
+        if *associated_token_program.key != spl_associated_token_account::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        Ok(Self {
+            taker,
+            takers_sending_token_account,
+            takers_token_to_receive_account,
+            escrow_account,
+            counter_account,
+            counter_vault,
+            expected_mint,
+            expected_token_program,
+            system_program_account,
+            associated_token_program,
+            additional_accounts,
+            escrow_info,
+            bump_seed,
+        })
+    }
+}
+
+pub struct AcceptCounterAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub pdas_temp_token_account: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub initializers_token_to_receive_account: &'a AccountInfo<'info>,
+    pub counter_account: &'a AccountInfo<'info>,
+    pub counter_vault: &'a AccountInfo<'info>,
+    pub taker: &'a AccountInfo<'info>,
+    pub takers_token_to_receive_account: &'a AccountInfo<'info>,
+    pub deposit_mint: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub expected_mint: &'a AccountInfo<'info>,
+    pub expected_token_program: &'a AccountInfo<'info>,
+    pub treasury_account: &'a AccountInfo<'info>,
+    // whatever is left is passed on to transfer hooks
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub escrow_info: Escrow,
+    pub counter_info: CounterOffer,
+    // none until the config is created, then the treasury has been checked against it
+    pub config: Option<Config>,
+}
+
+impl<'a, 'info> AcceptCounterAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let initializers_token_to_receive_account = next_account_info(account_info_iter)?;
+        let counter_account = next_account_info(account_info_iter)?;
+        let counter_vault = next_account_info(account_info_iter)?;
+        let taker = next_account_info(account_info_iter)?;
+        let takers_token_to_receive_account = next_account_info(account_info_iter)?;
+        let deposit_mint = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let expected_mint = next_account_info(account_info_iter)?;
+        let expected_token_program = next_account_info(account_info_iter)?;
+        let config_account = next_account_info(account_info_iter)?;
+        let treasury_account = next_account_info(account_info_iter)?;
+        let additional_accounts = account_info_iter.as_slice();
+
+        check_signer(initializer)?;
+        let escrow_info = unpack_escrow(escrow_account, program_id)?;
+        let counter_info = unpack_counter(counter_account, program_id)?;
+
+        check_key(initializer, &escrow_info.initializer_pubkey, EscrowError::WrongInitializer)?;
+        check_key(pdas_temp_token_account, &escrow_info.temp_token_account_pubkey, EscrowError::WrongTempAccount)?;
+        check_key(pda_account, &Escrow::create_pda(escrow_account.key, escrow_info.bump_seed, program_id)?, EscrowError::WrongPda)?;
+        check_key(initializers_token_to_receive_account, &escrow_info.initializer_token_to_receive_account_pubkey, EscrowError::WrongReceiveAccount)?;
+        if counter_info.escrow_pubkey != *escrow_account.key {
+            return Err(EscrowError::WrongCounterAccount.into());
+        }
+        check_key(counter_vault, &counter_info.vault_pubkey, EscrowError::WrongCounterAccount)?;
+        check_key(taker, &counter_info.taker_pubkey, EscrowError::WrongCounterAccount)?;
+        check_key(takers_token_to_receive_account, &counter_info.takers_token_to_receive_account_pubkey, EscrowError::WrongReceiveAccount)?;
+        for account in [initializer, pdas_temp_token_account, initializers_token_to_receive_account, counter_vault, taker, takers_token_to_receive_account] {
+            check_writable(account)?;
+        }
+        check_distinct(pdas_temp_token_account, &[takers_token_to_receive_account, initializers_token_to_receive_account])?;
+        check_distinct(counter_vault, &[initializers_token_to_receive_account, treasury_account])?;
+
+        if escrow_info.deposit_kind == AssetKind::Token {
+            check_token_program(token_program, pdas_temp_token_account)?;
+            check_mint_account(deposit_mint, &escrow_info.deposit_mint)?;
+        }
+        check_token_program(expected_token_program, counter_vault)?;
+        check_mint_account(expected_mint, &escrow_info.expected_mint)?;
+
+        let config = load_config(config_account, program_id)?;
+        if let Some(config) = &config {
+            check_key(treasury_account, &config.treasury_account(&escrow_info, expected_token_program.key), EscrowError::WrongTreasury)?;
+            check_writable(treasury_account)?;
+        }
+
+        Ok(Self {
+            initializer,
+            escrow_account,
+            pdas_temp_token_account,
+            pda_account,
+            initializers_token_to_receive_account,
+            counter_account,
+            counter_vault,
+            taker,
+            takers_token_to_receive_account,
+            deposit_mint,
+            token_program,
+            expected_mint,
+            expected_token_program,
+            treasury_account,
+            additional_accounts,
+            escrow_info,
+            counter_info,
+            config,
+        })
+    }
+}
+
+pub struct WithdrawCounterAccounts<'a, 'info> {
+    pub taker: &'a AccountInfo<'info>,
+    pub counter_account: &'a AccountInfo<'info>,
+    pub counter_vault: &'a AccountInfo<'info>,
+    pub takers_refund_token_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub expected_mint: &'a AccountInfo<'info>,
+    pub expected_token_program: &'a AccountInfo<'info>,
+    // whatever is left is passed on to the expected mint's transfer hook
+    pub additional_accounts: &'a [AccountInfo<'info>],
+    pub counter_info: CounterOffer,
+    // none once the escrow has been closed, its counter-offers can still be withdrawn
+    pub escrow_info: Option<Escrow>,
+}
+
+impl<'a, 'info> WithdrawCounterAccounts<'a, 'info> {
+    pub fn validate(accounts: &'a [AccountInfo<'info>], program_id: &Pubkey) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let taker = next_account_info(account_info_iter)?;
+        let counter_account = next_account_info(account_info_iter)?;
+        let counter_vault = next_account_info(account_info_iter)?;
+        let takers_refund_token_account = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        let expected_mint = next_account_info(account_info_iter)?;
+        let expected_token_program = next_account_info(account_info_iter)?;
+        let additional_accounts = account_info_iter.as_slice();
+
+        check_signer(taker)?;
+        let counter_info = unpack_counter(counter_account, program_id)?;
+        check_key(taker, &counter_info.taker_pubkey, EscrowError::WrongCounterAccount)?;
+        check_key(counter_vault, &counter_info.vault_pubkey, EscrowError::WrongCounterAccount)?;
+        check_key(escrow_account, &counter_info.escrow_pubkey, EscrowError::WrongCounterAccount)?;
+        for account in [taker, counter_vault, takers_refund_token_account] {
+            check_writable(account)?;
+        }
+        check_distinct(counter_vault, &[takers_refund_token_account])?;
+
+        check_token_program(expected_token_program, counter_vault)?;
+        let expected_mint_pubkey = unpack_token_account(counter_vault)?.mint;
+        check_mint_account(expected_mint, &expected_mint_pubkey)?;
+        check_mint(takers_refund_token_account, &expected_mint_pubkey, EscrowError::ExpectedMintMismatch)?;
+
+        let escrow_info = if escrow_account.owner == program_id {
+            check_writable(escrow_account)?;
+            Some(unpack_escrow(escrow_account, program_id)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            taker,
+            counter_account,
+            counter_vault,
+            takers_refund_token_account,
+            escrow_account,
+            expected_mint,
+            expected_token_program,
+            additional_accounts,
+            counter_info,
+            escrow_info,
+        })
+    }
+}
+
+pub(crate) fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == spl_token_2022::id()
+}
+
+// a token account of either token program, with any Token-2022 extensions skipped
+pub(crate) fn unpack_token_account(token_account: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    if !is_token_program(token_account.owner) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_account_data = token_account.try_borrow_data()?;
+    Ok(StateWithExtensions::<TokenAccount>::unpack(&token_account_data)?.base)
+}
+
+// the config account must be the program's config PDA, until it is created no fee is charged
+fn load_config(config_account: &AccountInfo, program_id: &Pubkey) -> Result<Option<Config>, ProgramError> {
+    check_key(config_account, &Config::find_pda(program_id).0, EscrowError::WrongPda)?;
+    if config_account.owner != program_id {
+        return Ok(None);
+    }
+    Config::unpack(&config_account.try_borrow_data()?).map(Some)
+}
+
+fn unpack_escrow(escrow_account: &AccountInfo, program_id: &Pubkey) -> Result<Escrow, ProgramError> {
+    check_program_owned(escrow_account, program_id)?;
+    check_writable(escrow_account)?;
+    Escrow::unpack(&escrow_account.try_borrow_data()?)
+}
+
+fn unpack_counter(counter_account: &AccountInfo, program_id: &Pubkey) -> Result<CounterOffer, ProgramError> {
+    check_program_owned(counter_account, program_id)?;
+    check_writable(counter_account)?;
+    CounterOffer::unpack(&counter_account.try_borrow_data()?)
+}
+
+fn unpack_basket(basket_account: &AccountInfo, program_id: &Pubkey) -> Result<BasketEscrow, ProgramError> {
+    check_program_owned(basket_account, program_id)?;
+    check_writable(basket_account)?;
+    let basket_info = BasketEscrow::unpack(&basket_account.try_borrow_data()?)?;
+    if !basket_info.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(basket_info)
+}
+
+fn check_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+fn check_writable(account: &AccountInfo) -> ProgramResult {
+    if !account.is_writable {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+fn check_program_owned(account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    if account.owner != program_id {
+        return Err(EscrowError::NotProgramOwned.into());
+    }
+    Ok(())
+}
+
+fn check_key(account: &AccountInfo, expected: &Pubkey, error: EscrowError) -> ProgramResult {
+    if account.key != expected {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+// the ProgramData account starts with the loader's enum tag, the deployment slot and the optional authority
+fn check_upgrade_authority(program_data: &AccountInfo, authority: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    check_key(program_data, &Config::find_program_data(program_id), EscrowError::NotUpgradeAuthority)?;
+    if *program_data.owner != bpf_loader_upgradeable::id() {
+        return Err(EscrowError::NotUpgradeAuthority.into());
+    }
+    let data = program_data.try_borrow_data()?;
+    if data.len() < PROGRAM_DATA_METADATA_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let (tag, _slot, has_authority, upgrade_authority) = array_refs![array_ref![data, 0, PROGRAM_DATA_METADATA_LEN], 4, 8, 1, 32];
+    if u32::from_le_bytes(*tag) != PROGRAM_DATA_TAG {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if *has_authority != [1] || upgrade_authority != authority.key.as_ref() {
+        return Err(EscrowError::NotUpgradeAuthority.into());
+    }
+    Ok(())
+}
+
+fn check_system_program(account: &AccountInfo) -> ProgramResult {
+    if *account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+// the token program passed for a leg must be the one that owns its token account
+fn check_token_program(token_program: &AccountInfo, token_account: &AccountInfo) -> ProgramResult {
+    if !is_token_program(token_program.key) || token_program.key != token_account.owner {
+        return Err(EscrowError::WrongTokenProgram.into());
+    }
+    Ok(())
+}
+
+// a vault passed again in another token slot would have the program transfer it into itself
+fn check_distinct(vault: &AccountInfo, others: &[&AccountInfo]) -> ProgramResult {
+    if others.iter().any(|other| other.key == vault.key) {
+        return Err(EscrowError::DuplicateAccount.into());
+    }
+    Ok(())
+}
+
+fn check_mint(token_account: &AccountInfo, mint: &Pubkey, error: EscrowError) -> ProgramResult {
+    let token_account_info = unpack_token_account(token_account)?;
+    if token_account_info.mint != *mint {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+// Expire returns the deposit to the initializer's own account of the deposit mint
+fn check_refund_account(refund_token_account: &AccountInfo, deposit_mint: &Pubkey, initializer: &AccountInfo) -> ProgramResult {
+    let refund_token_account_info = unpack_token_account(refund_token_account)?;
+    if refund_token_account_info.mint != *deposit_mint {
+        return Err(EscrowError::DepositMintMismatch.into());
+    }
+    if refund_token_account_info.owner != *initializer.key {
+        return Err(EscrowError::WrongReceiveAccount.into());
+    }
+    Ok(())
+}
+
+// a token payment is received into a token account, a native one into a wallet
+fn check_token_to_receive_account(token_to_receive_account: &AccountInfo, expected_kind: AssetKind) -> ProgramResult {
+    let owned_as_expected = match expected_kind {
+        AssetKind::Token => is_token_program(token_to_receive_account.owner),
+        AssetKind::Native => *token_to_receive_account.owner == system_program::id(),
+    };
+    if !owned_as_expected {
+        return Err(EscrowError::WrongReceiveAccountOwner.into());
+    }
+    Ok(())
+}
+
+fn check_mint_account(mint: &AccountInfo, expected_mint: &Pubkey) -> ProgramResult {
+    if mint.key != expected_mint || !is_token_program(mint.owner) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
@@ -0,0 +1,678 @@
+// CPI wrappers for programs calling into the escrow, built with the `cpi` feature.
+// Each takes the escrow program's account, the accounts of its instruction and the signer seeds
+// of any PDA signing for the caller, with no seeds the instruction is invoked as is
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{instruction, state::{AssetKind, ClockMode, PriceBand}};
+
+pub struct InitEscrow<'a> {
+    pub initializer: AccountInfo<'a>,
+    pub temp_token_account: AccountInfo<'a>,
+    pub token_to_receive_account: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub rent: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub refund_token_account: AccountInfo<'a>,
+}
+
+pub struct InitEscrowWithVault<'a> {
+    pub initializer: AccountInfo<'a>,
+    pub deposit_token_account: AccountInfo<'a>,
+    pub vault: AccountInfo<'a>,
+    pub token_to_receive_account: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub refund_token_account: AccountInfo<'a>,
+    pub deposit_mint: AccountInfo<'a>,
+    pub pda: AccountInfo<'a>,
+    pub system_program: AccountInfo<'a>,
+    pub associated_token_program: AccountInfo<'a>,
+    // accounts needed by the deposit mint's transfer hook, appended in order
+    pub additional_accounts: Vec<AccountInfo<'a>>,
+}
+
+pub struct Exchange<'a> {
+    pub taker: AccountInfo<'a>,
+    pub takers_sending_token_account: AccountInfo<'a>,
+    pub takers_token_to_receive_account: AccountInfo<'a>,
+    pub pdas_temp_token_account: AccountInfo<'a>,
+    pub initializer: AccountInfo<'a>,
+    pub initializers_token_to_receive_account: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub deposit_token_program: AccountInfo<'a>,
+    pub pda: AccountInfo<'a>,
+    pub system_program: AccountInfo<'a>,
+    pub config: AccountInfo<'a>,
+    pub treasury_account: AccountInfo<'a>,
+    pub deposit_mint: AccountInfo<'a>,
+    pub expected_mint: AccountInfo<'a>,
+    pub expected_token_program: AccountInfo<'a>,
+    // an oracle-gated escrow's price feed first, then accounts needed by transfer hooks, appended in order
+    pub additional_accounts: Vec<AccountInfo<'a>>,
+}
+
+pub struct Cancel<'a> {
+    pub initializer: AccountInfo<'a>,
+    pub pdas_temp_token_account: AccountInfo<'a>,
+    pub initializers_sent_token_account: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub pda: AccountInfo<'a>,
+    pub deposit_mint: AccountInfo<'a>,
+}
+
+pub struct Expire<'a> {
+    pub cranker: AccountInfo<'a>,
+    pub pdas_temp_token_account: AccountInfo<'a>,
+    pub initializer: AccountInfo<'a>,
+    pub refund_token_account: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub pda: AccountInfo<'a>,
+    pub deposit_mint: AccountInfo<'a>,
+}
+
+// shared by ResetTimeLock, ExtendTimeout, AdvanceUnlock and SetTimeLock
+pub struct TimeLock<'a> {
+    pub initializer: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+}
+
+pub struct InitBasket<'a> {
+    pub initializer: AccountInfo<'a>,
+    pub basket_account: AccountInfo<'a>,
+    pub rent: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub deposit_vaults: Vec<AccountInfo<'a>>,
+    pub token_to_receive_accounts: Vec<AccountInfo<'a>>,
+}
+
+// legs are (source, destination, mint) in the order the basket stores them
+pub struct ExchangeBasket<'a> {
+    pub taker: AccountInfo<'a>,
+    pub basket_account: AccountInfo<'a>,
+    pub initializer: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub pda: AccountInfo<'a>,
+    pub deposit_legs: Vec<(AccountInfo<'a>, AccountInfo<'a>, AccountInfo<'a>)>,
+    pub payment_legs: Vec<(AccountInfo<'a>, AccountInfo<'a>, AccountInfo<'a>)>,
+}
+
+// refund legs are (vault, initializer's token account to refund, mint)
+pub struct CancelBasket<'a> {
+    pub initializer: AccountInfo<'a>,
+    pub basket_account: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub pda: AccountInfo<'a>,
+    pub refund_legs: Vec<(AccountInfo<'a>, AccountInfo<'a>, AccountInfo<'a>)>,
+}
+
+pub struct InitConfig<'a> {
+    pub admin: AccountInfo<'a>,
+    pub config: AccountInfo<'a>,
+    pub treasury_owner: AccountInfo<'a>,
+    pub system_program: AccountInfo<'a>,
+    pub program_data: AccountInfo<'a>,
+}
+
+pub struct Quote<'a> {
+    pub escrow_account: AccountInfo<'a>,
+}
+
+pub struct ProposeCounter<'a> {
+    pub taker: AccountInfo<'a>,
+    pub takers_sending_token_account: AccountInfo<'a>,
+    pub takers_token_to_receive_account: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub counter: AccountInfo<'a>,
+    pub counter_vault: AccountInfo<'a>,
+    pub expected_mint: AccountInfo<'a>,
+    pub expected_token_program: AccountInfo<'a>,
+    pub system_program: AccountInfo<'a>,
+    pub associated_token_program: AccountInfo<'a>,
+    // accounts needed by the expected mint's transfer hook, appended in order
+    pub additional_accounts: Vec<AccountInfo<'a>>,
+}
+
+pub struct AcceptCounter<'a> {
+    pub initializer: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub pdas_temp_token_account: AccountInfo<'a>,
+    pub pda: AccountInfo<'a>,
+    pub initializers_token_to_receive_account: AccountInfo<'a>,
+    pub counter: AccountInfo<'a>,
+    pub counter_vault: AccountInfo<'a>,
+    pub taker: AccountInfo<'a>,
+    pub takers_token_to_receive_account: AccountInfo<'a>,
+    pub deposit_mint: AccountInfo<'a>,
+    pub deposit_token_program: AccountInfo<'a>,
+    pub expected_mint: AccountInfo<'a>,
+    pub expected_token_program: AccountInfo<'a>,
+    pub config: AccountInfo<'a>,
+    pub treasury_account: AccountInfo<'a>,
+    // accounts needed by transfer hooks, appended in order
+    pub additional_accounts: Vec<AccountInfo<'a>>,
+}
+
+pub struct WithdrawCounter<'a> {
+    pub taker: AccountInfo<'a>,
+    pub counter: AccountInfo<'a>,
+    pub counter_vault: AccountInfo<'a>,
+    pub takers_refund_token_account: AccountInfo<'a>,
+    pub escrow_account: AccountInfo<'a>,
+    pub expected_mint: AccountInfo<'a>,
+    pub expected_token_program: AccountInfo<'a>,
+    // accounts needed by the expected mint's transfer hook, appended in order
+    pub additional_accounts: Vec<AccountInfo<'a>>,
+}
+
+pub struct UpdateConfig<'a> {
+    pub admin: AccountInfo<'a>,
+    pub config: AccountInfo<'a>,
+    pub treasury_owner: AccountInfo<'a>,
+    pub new_admin: AccountInfo<'a>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &InitEscrow<'a>,
+    amount: u64,
+    unlock_delay: u64,
+    timeout_window: u64,
+    clock_mode: ClockMode,
+    deposit_kind: AssetKind,
+    expected_kind: AssetKind,
+    allowed_taker: Option<Pubkey>,
+    auction_end_price: Option<u64>,
+    price_band: Option<PriceBand>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::init_escrow(
+        escrow_program.key,
+        accounts.initializer.key,
+        accounts.temp_token_account.key,
+        accounts.token_to_receive_account.key,
+        accounts.escrow_account.key,
+        accounts.refund_token_account.key,
+        accounts.token_program.key,
+        amount,
+        unlock_delay,
+        timeout_window,
+        clock_mode,
+        deposit_kind,
+        expected_kind,
+        allowed_taker,
+        auction_end_price,
+        price_band,
+    );
+    let account_infos = vec![
+        accounts.initializer.clone(),
+        accounts.temp_token_account.clone(),
+        accounts.token_to_receive_account.clone(),
+        accounts.escrow_account.clone(),
+        accounts.rent.clone(),
+        accounts.token_program.clone(),
+        accounts.refund_token_account.clone(),
+    ];
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_with_vault<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &InitEscrowWithVault<'a>,
+    deposit_amount: u64,
+    expected_amount: u64,
+    unlock_delay: u64,
+    timeout_window: u64,
+    clock_mode: ClockMode,
+    expected_kind: AssetKind,
+    allowed_taker: Option<Pubkey>,
+    auction_end_price: Option<u64>,
+    price_band: Option<PriceBand>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let mut ix = instruction::init_escrow_with_vault(
+        escrow_program.key,
+        accounts.initializer.key,
+        accounts.deposit_token_account.key,
+        accounts.token_to_receive_account.key,
+        accounts.escrow_account.key,
+        accounts.refund_token_account.key,
+        accounts.deposit_mint.key,
+        accounts.token_program.key,
+        deposit_amount,
+        expected_amount,
+        unlock_delay,
+        timeout_window,
+        clock_mode,
+        expected_kind,
+        allowed_taker,
+        auction_end_price,
+        price_band,
+    );
+    ix.accounts.extend(accounts.additional_accounts.iter().map(account_meta));
+    let mut account_infos = vec![
+        accounts.initializer.clone(),
+        accounts.deposit_token_account.clone(),
+        accounts.vault.clone(),
+        accounts.token_to_receive_account.clone(),
+        accounts.escrow_account.clone(),
+        accounts.token_program.clone(),
+        accounts.refund_token_account.clone(),
+        accounts.deposit_mint.clone(),
+        accounts.pda.clone(),
+        accounts.system_program.clone(),
+        accounts.associated_token_program.clone(),
+    ];
+    account_infos.extend(accounts.additional_accounts.iter().cloned());
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn exchange<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &Exchange<'a>,
+    amount: u64,
+    max_payment: u64,
+    min_receive: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let mut ix = instruction::exchange(
+        escrow_program.key,
+        accounts.taker.key,
+        accounts.takers_sending_token_account.key,
+        accounts.takers_token_to_receive_account.key,
+        accounts.pdas_temp_token_account.key,
+        accounts.initializer.key,
+        accounts.initializers_token_to_receive_account.key,
+        accounts.escrow_account.key,
+        accounts.treasury_account.key,
+        accounts.deposit_mint.key,
+        accounts.expected_mint.key,
+        accounts.deposit_token_program.key,
+        accounts.expected_token_program.key,
+        amount,
+        max_payment,
+        min_receive,
+    );
+    ix.accounts.extend(accounts.additional_accounts.iter().map(account_meta));
+    let mut account_infos = vec![
+        accounts.taker.clone(),
+        accounts.takers_sending_token_account.clone(),
+        accounts.takers_token_to_receive_account.clone(),
+        accounts.pdas_temp_token_account.clone(),
+        accounts.initializer.clone(),
+        accounts.initializers_token_to_receive_account.clone(),
+        accounts.escrow_account.clone(),
+        accounts.deposit_token_program.clone(),
+        accounts.pda.clone(),
+        accounts.system_program.clone(),
+        accounts.config.clone(),
+        accounts.treasury_account.clone(),
+        accounts.deposit_mint.clone(),
+        accounts.expected_mint.clone(),
+        accounts.expected_token_program.clone(),
+    ];
+    account_infos.extend(accounts.additional_accounts.iter().cloned());
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn cancel<'a>(escrow_program: &AccountInfo<'a>, accounts: &Cancel<'a>, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let ix = instruction::cancel(
+        escrow_program.key,
+        accounts.initializer.key,
+        accounts.pdas_temp_token_account.key,
+        accounts.initializers_sent_token_account.key,
+        accounts.escrow_account.key,
+        accounts.deposit_mint.key,
+        accounts.token_program.key,
+    );
+    let account_infos = vec![
+        accounts.initializer.clone(),
+        accounts.pdas_temp_token_account.clone(),
+        accounts.initializers_sent_token_account.clone(),
+        accounts.escrow_account.clone(),
+        accounts.token_program.clone(),
+        accounts.pda.clone(),
+        accounts.deposit_mint.clone(),
+    ];
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn expire<'a>(escrow_program: &AccountInfo<'a>, accounts: &Expire<'a>, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let ix = instruction::expire(
+        escrow_program.key,
+        accounts.cranker.key,
+        accounts.pdas_temp_token_account.key,
+        accounts.initializer.key,
+        accounts.refund_token_account.key,
+        accounts.escrow_account.key,
+        accounts.deposit_mint.key,
+        accounts.token_program.key,
+    );
+    let account_infos = vec![
+        accounts.cranker.clone(),
+        accounts.pdas_temp_token_account.clone(),
+        accounts.initializer.clone(),
+        accounts.refund_token_account.clone(),
+        accounts.escrow_account.clone(),
+        accounts.token_program.clone(),
+        accounts.pda.clone(),
+        accounts.deposit_mint.clone(),
+    ];
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn reset_time_lock<'a>(escrow_program: &AccountInfo<'a>, accounts: &TimeLock<'a>, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let ix = instruction::reset_time_lock(escrow_program.key, accounts.initializer.key, accounts.escrow_account.key);
+    invoke_time_lock(&ix, escrow_program, accounts, signer_seeds)
+}
+
+pub fn extend_timeout<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &TimeLock<'a>,
+    extension: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::extend_timeout(escrow_program.key, accounts.initializer.key, accounts.escrow_account.key, extension);
+    invoke_time_lock(&ix, escrow_program, accounts, signer_seeds)
+}
+
+pub fn advance_unlock<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &TimeLock<'a>,
+    advance: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::advance_unlock(escrow_program.key, accounts.initializer.key, accounts.escrow_account.key, advance);
+    invoke_time_lock(&ix, escrow_program, accounts, signer_seeds)
+}
+
+pub fn set_time_lock<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &TimeLock<'a>,
+    unlock_time: u64,
+    time_out: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::set_time_lock(escrow_program.key, accounts.initializer.key, accounts.escrow_account.key, unlock_time, time_out);
+    invoke_time_lock(&ix, escrow_program, accounts, signer_seeds)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_basket<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &InitBasket<'a>,
+    expected_amounts: &[u64],
+    unlock_delay: u64,
+    timeout_window: u64,
+    clock_mode: ClockMode,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let deposit_vaults: Vec<_> = accounts.deposit_vaults.iter().map(|vault| *vault.key).collect();
+    let token_to_receive_accounts: Vec<_> = accounts.token_to_receive_accounts.iter().map(|account| *account.key).collect();
+    let ix = instruction::init_basket(
+        escrow_program.key,
+        accounts.initializer.key,
+        accounts.basket_account.key,
+        &deposit_vaults,
+        &token_to_receive_accounts,
+        expected_amounts,
+        unlock_delay,
+        timeout_window,
+        clock_mode,
+        accounts.token_program.key,
+    );
+    let mut account_infos = vec![
+        accounts.initializer.clone(),
+        accounts.basket_account.clone(),
+        accounts.rent.clone(),
+        accounts.token_program.clone(),
+    ];
+    account_infos.extend(accounts.deposit_vaults.iter().cloned());
+    account_infos.extend(accounts.token_to_receive_accounts.iter().cloned());
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn exchange_basket<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &ExchangeBasket<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::exchange_basket(
+        escrow_program.key,
+        accounts.taker.key,
+        accounts.basket_account.key,
+        accounts.initializer.key,
+        &leg_keys(&accounts.deposit_legs),
+        &leg_keys(&accounts.payment_legs),
+        accounts.token_program.key,
+    );
+    let mut account_infos = vec![
+        accounts.taker.clone(),
+        accounts.basket_account.clone(),
+        accounts.initializer.clone(),
+        accounts.token_program.clone(),
+        accounts.pda.clone(),
+    ];
+    for (from, to, mint) in accounts.deposit_legs.iter().chain(&accounts.payment_legs) {
+        account_infos.extend([from.clone(), to.clone(), mint.clone()]);
+    }
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn cancel_basket<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &CancelBasket<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::cancel_basket(
+        escrow_program.key,
+        accounts.initializer.key,
+        accounts.basket_account.key,
+        &leg_keys(&accounts.refund_legs),
+        accounts.token_program.key,
+    );
+    let mut account_infos = vec![
+        accounts.initializer.clone(),
+        accounts.basket_account.clone(),
+        accounts.token_program.clone(),
+        accounts.pda.clone(),
+    ];
+    for (vault, refund, mint) in &accounts.refund_legs {
+        account_infos.extend([vault.clone(), refund.clone(), mint.clone()]);
+    }
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn init_config<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &InitConfig<'a>,
+    fee_bps: u16,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::init_config(escrow_program.key, accounts.admin.key, accounts.treasury_owner.key, fee_bps);
+    let account_infos = vec![
+        accounts.admin.clone(),
+        accounts.config.clone(),
+        accounts.treasury_owner.clone(),
+        accounts.system_program.clone(),
+        accounts.program_data.clone(),
+    ];
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn update_config<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &UpdateConfig<'a>,
+    fee_bps: u16,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let ix = instruction::update_config(
+        escrow_program.key,
+        accounts.admin.key,
+        accounts.treasury_owner.key,
+        accounts.new_admin.key,
+        fee_bps,
+    );
+    let account_infos = vec![
+        accounts.admin.clone(),
+        accounts.config.clone(),
+        accounts.treasury_owner.clone(),
+        accounts.new_admin.clone(),
+    ];
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+// the payment for `amount` of the remaining deposit right now, as set in the escrow's return data
+pub fn quote<'a>(escrow_program: &AccountInfo<'a>, accounts: &Quote<'a>, amount: u64) -> Result<u64, ProgramError> {
+    let ix = instruction::quote(escrow_program.key, accounts.escrow_account.key, amount);
+    invoke_escrow(&ix, escrow_program, vec![accounts.escrow_account.clone()], &[])?;
+    match get_return_data() {
+        Some((program_id, data)) if program_id == *escrow_program.key => data
+            .get(..8)
+            .and_then(|price| price.try_into().ok())
+            .map(u64::from_le_bytes)
+            .ok_or(ProgramError::InvalidAccountData),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+pub fn propose_counter<'a>(
+    escrow_program: &AccountInfo<'a>,
+    accounts: &ProposeCounter<'a>,
+    fill_amount: u64,
+    payment: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let mut ix = instruction::propose_counter(
+        escrow_program.key,
+        accounts.taker.key,
+        accounts.takers_sending_token_account.key,
+        accounts.takers_token_to_receive_account.key,
+        accounts.escrow_account.key,
+        accounts.expected_mint.key,
+        accounts.expected_token_program.key,
+        fill_amount,
+        payment,
+    );
+    ix.accounts.extend(accounts.additional_accounts.iter().map(account_meta));
+    let mut account_infos = vec![
+        accounts.taker.clone(),
+        accounts.takers_sending_token_account.clone(),
+        accounts.takers_token_to_receive_account.clone(),
+        accounts.escrow_account.clone(),
+        accounts.counter.clone(),
+        accounts.counter_vault.clone(),
+        accounts.expected_mint.clone(),
+        accounts.expected_token_program.clone(),
+        accounts.system_program.clone(),
+        accounts.associated_token_program.clone(),
+    ];
+    account_infos.extend(accounts.additional_accounts.iter().cloned());
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn accept_counter<'a>(escrow_program: &AccountInfo<'a>, accounts: &AcceptCounter<'a>, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let mut ix = instruction::accept_counter(
+        escrow_program.key,
+        accounts.initializer.key,
+        accounts.escrow_account.key,
+        accounts.pdas_temp_token_account.key,
+        accounts.initializers_token_to_receive_account.key,
+        accounts.taker.key,
+        accounts.takers_token_to_receive_account.key,
+        accounts.treasury_account.key,
+        accounts.deposit_mint.key,
+        accounts.expected_mint.key,
+        accounts.deposit_token_program.key,
+        accounts.expected_token_program.key,
+    );
+    ix.accounts.extend(accounts.additional_accounts.iter().map(account_meta));
+    let mut account_infos = vec![
+        accounts.initializer.clone(),
+        accounts.escrow_account.clone(),
+        accounts.pdas_temp_token_account.clone(),
+        accounts.pda.clone(),
+        accounts.initializers_token_to_receive_account.clone(),
+        accounts.counter.clone(),
+        accounts.counter_vault.clone(),
+        accounts.taker.clone(),
+        accounts.takers_token_to_receive_account.clone(),
+        accounts.deposit_mint.clone(),
+        accounts.deposit_token_program.clone(),
+        accounts.expected_mint.clone(),
+        accounts.expected_token_program.clone(),
+        accounts.config.clone(),
+        accounts.treasury_account.clone(),
+    ];
+    account_infos.extend(accounts.additional_accounts.iter().cloned());
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+pub fn withdraw_counter<'a>(escrow_program: &AccountInfo<'a>, accounts: &WithdrawCounter<'a>, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let mut ix = instruction::withdraw_counter(
+        escrow_program.key,
+        accounts.taker.key,
+        accounts.takers_refund_token_account.key,
+        accounts.escrow_account.key,
+        accounts.expected_mint.key,
+        accounts.expected_token_program.key,
+    );
+    ix.accounts.extend(accounts.additional_accounts.iter().map(account_meta));
+    let mut account_infos = vec![
+        accounts.taker.clone(),
+        accounts.counter.clone(),
+        accounts.counter_vault.clone(),
+        accounts.takers_refund_token_account.clone(),
+        accounts.escrow_account.clone(),
+        accounts.expected_mint.clone(),
+        accounts.expected_token_program.clone(),
+    ];
+    account_infos.extend(accounts.additional_accounts.iter().cloned());
+    invoke_escrow(&ix, escrow_program, account_infos, signer_seeds)
+}
+
+fn invoke_time_lock<'a>(
+    ix: &Instruction,
+    escrow_program: &AccountInfo<'a>,
+    accounts: &TimeLock<'a>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let account_infos = vec![accounts.initializer.clone(), accounts.escrow_account.clone()];
+    invoke_escrow(ix, escrow_program, account_infos, signer_seeds)
+}
+
+fn invoke_escrow<'a>(
+    ix: &Instruction,
+    escrow_program: &AccountInfo<'a>,
+    mut account_infos: Vec<AccountInfo<'a>>,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    account_infos.push(escrow_program.clone());
+    if signer_seeds.is_empty() {
+        invoke(ix, &account_infos)
+    } else {
+        invoke_signed(ix, &account_infos, signer_seeds)
+    }
+}
+
+fn leg_keys(legs: &[(AccountInfo, AccountInfo, AccountInfo)]) -> Vec<(Pubkey, Pubkey, Pubkey)> {
+    legs.iter().map(|(from, to, mint)| (*from.key, *to.key, *mint.key)).collect()
+}
+
+fn account_meta(account: &AccountInfo) -> AccountMeta {
+    if account.is_writable {
+        AccountMeta::new(*account.key, account.is_signer)
+    } else {
+        AccountMeta::new_readonly(*account.key, account.is_signer)
+    }
+}
@@ -2,10 +2,11 @@ use solana_program::{
     account_info::AccountInfo, 
     entrypoint, 
     entrypoint::ProgramResult, 
+    program_error::PrintProgramError,
     pubkey::Pubkey
 };
 
-use crate::processor::Processor;
+use crate::{error::EscrowError, processor::Processor};
 
 entrypoint!(process_instruction);
 fn process_instruction(
@@ -13,5 +14,10 @@ fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    Processor::process(program_id, accounts, instruction_data)
+    if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
+        // logs the escrow error's message, or the program error for the others
+        error.print::<EscrowError>();
+        return Err(error);
+    }
+    Ok(())
 }
@@ -1,7 +1,13 @@
+use num_derive::FromPrimitive;
 use thiserror::Error;
-use solana_program::program_error::ProgramError;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
 
-#[derive(Error, Debug, Copy, Clone)]
+// new variants go at the end, the Custom codes clients already decode must not shift
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
 pub enum EscrowError {
     #[error("Invalid instruction")]
     InvalidInstruction,
@@ -20,10 +26,136 @@ pub enum EscrowError {
 
     #[error("Current slot is greater than timeout time")]
     EscrowTimeout,
+
+    #[error("Unlock delay or timeout window is out of range")]
+    TimeLockOutOfRange,
+
+    #[error("Current unix timestamp is less than unlock time")]
+    EscrowUnlockTimestamp,
+
+    #[error("Current unix timestamp is greater than timeout time")]
+    EscrowTimestampTimeout,
+
+    #[error("Unlock time must be before timeout and a changed deadline cannot be in the past")]
+    InvalidTimeLock,
+
+    #[error("Fill amount is zero or exceeds the remaining deposit")]
+    InvalidFillAmount,
+
+    #[error("Token account mint does not match the escrow's deposit mint")]
+    DepositMintMismatch,
+
+    #[error("Token account mint does not match the escrow's expected mint")]
+    ExpectedMintMismatch,
+
+    #[error("Escrow has not timed out yet")]
+    EscrowNotExpired,
+
+    #[error("Basket legs do not match the escrow account")]
+    InvalidBasket,
+
+    #[error("Fee exceeds the maximum basis points")]
+    FeeTooHigh,
+
+    #[error("Escrow can only be taken by its allowed taker")]
+    UnauthorizedTaker,
+
+    #[error("Vault token account does not match the escrow")]
+    WrongTempAccount,
+
+    #[error("Initializer does not match the escrow")]
+    WrongInitializer,
+
+    #[error("Receive or refund account does not match the escrow")]
+    WrongReceiveAccount,
+
+    #[error("Account is not the program derived address of the escrow")]
+    WrongPda,
+
+    #[error("Token program is not SPL Token or Token-2022, or does not own the token account")]
+    WrongTokenProgram,
+
+    #[error("Account is not owned by the escrow program")]
+    NotProgramOwned,
+
+    #[error("The same account is passed for two different slots")]
+    DuplicateAccount,
+
+    #[error("Payment is above the taker's maximum")]
+    MaxPaymentExceeded,
+
+    #[error("Auction end price is above its start price")]
+    InvalidAuction,
+
+    #[error("Price band minimum is above its maximum")]
+    InvalidPriceBand,
+
+    #[error("Price feed does not match the escrow's oracle")]
+    WrongOracle,
+
+    #[error("Price feed is stale")]
+    StalePriceFeed,
+
+    #[error("Reference price is outside the escrow's band")]
+    PriceOutOfBand,
+
+    #[error("Counter-offer must pay tokens and take part of the deposit")]
+    InvalidCounter,
+
+    #[error("Counter-offer account does not match the escrow and taker")]
+    WrongCounterAccount,
+
+    #[error("Signer is not the program's upgrade authority")]
+    NotUpgradeAuthority,
+
+    #[error("Treasury account does not match the config")]
+    WrongTreasury,
+
+    #[error("Receive account is not owned by the token program or the system program as the payment requires")]
+    WrongReceiveAccountOwner,
+
+    #[error("Deadlines of an auction cannot change while it is running")]
+    AuctionRunning,
+
+    #[error("Taker would receive less than their minimum")]
+    MinReceiveNotMet,
 }
 
 impl From<EscrowError> for ProgramError {
     fn from(e: EscrowError) -> Self {
         ProgramError::Custom(e as u32)
     }
-}
\ No newline at end of file
+}
+
+impl<T> DecodeError<T> for EscrowError {
+    fn type_of() -> &'static str {
+        "EscrowError"
+    }
+}
+
+impl PrintProgramError for EscrowError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + num_traits::FromPrimitive,
+    {
+        msg!("{}", self);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_decode_custom_code() {
+        let ProgramError::Custom(code) = ProgramError::from(EscrowError::DuplicateAccount) else {
+            panic!("escrow errors are custom program errors");
+        };
+        assert_eq!(code, 23);
+        assert_eq!(EscrowError::from_u32(code), Some(EscrowError::DuplicateAccount));
+        assert_eq!(EscrowError::from_u32(0), Some(EscrowError::InvalidInstruction));
+        assert_eq!(EscrowError::from_u32(37), None);
+        assert_eq!(<EscrowError as DecodeError<EscrowError>>::type_of(), "EscrowError");
+    }
+}
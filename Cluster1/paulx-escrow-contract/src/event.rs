@@ -0,0 +1,142 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+use crate::state::ClockMode;
+
+// every event is logged through sol_log_data as a single Borsh-encoded VersionedEvent,
+// new fields go into a new version so older indexers can still decode what they know
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum VersionedEvent {
+    V1(EscrowEvent),
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum EscrowEvent {
+    EscrowCreated {
+        escrow: Pubkey,
+        initializer: Pubkey,
+        allowed_taker: Option<Pubkey>,
+        deposit_mint: Pubkey,
+        expected_mint: Pubkey,
+        deposit_amount: u64,
+        expected_amount: u64,
+        clock_mode: ClockMode,
+        unlock_time: u64,
+        time_out: u64,
+    },
+    // a partial fill leaves remaining_deposit and remaining_expected in the escrow
+    EscrowExchanged {
+        escrow: Pubkey,
+        initializer: Pubkey,
+        taker: Pubkey,
+        deposit_mint: Pubkey,
+        expected_mint: Pubkey,
+        fill_amount: u64,
+        payment: u64,
+        fee: u64,
+        remaining_deposit: u64,
+        remaining_expected: u64,
+        clock_mode: ClockMode,
+        unlock_time: u64,
+        time_out: u64,
+    },
+    EscrowCancelled {
+        escrow: Pubkey,
+        initializer: Pubkey,
+        deposit_mint: Pubkey,
+        expected_mint: Pubkey,
+        refund_amount: u64,
+        clock_mode: ClockMode,
+        unlock_time: u64,
+        time_out: u64,
+    },
+    // emitted by every time lock instruction, not only ResetTimeLock
+    TimeLockReset {
+        escrow: Pubkey,
+        initializer: Pubkey,
+        clock_mode: ClockMode,
+        old_unlock_time: u64,
+        old_time_out: u64,
+        unlock_time: u64,
+        time_out: u64,
+    },
+    EscrowExpired {
+        escrow: Pubkey,
+        initializer: Pubkey,
+        cranker: Pubkey,
+        deposit_mint: Pubkey,
+        expected_mint: Pubkey,
+        refund_amount: u64,
+        tip: u64,
+        clock_mode: ClockMode,
+        unlock_time: u64,
+        time_out: u64,
+    },
+    // open_counters counts this one
+    CounterProposed {
+        escrow: Pubkey,
+        taker: Pubkey,
+        counter: Pubkey,
+        fill_amount: u64,
+        payment: u64,
+        open_counters: u32,
+    },
+    CounterAccepted {
+        escrow: Pubkey,
+        initializer: Pubkey,
+        taker: Pubkey,
+        counter: Pubkey,
+        fill_amount: u64,
+        payment: u64,
+        fee: u64,
+        remaining_deposit: u64,
+        remaining_expected: u64,
+        open_counters: u32,
+    },
+    // open_counters is 0 once the escrow has been closed
+    CounterWithdrawn {
+        escrow: Pubkey,
+        taker: Pubkey,
+        counter: Pubkey,
+        refund_amount: u64,
+        open_counters: u32,
+    },
+}
+
+impl EscrowEvent {
+    pub fn emit(self) {
+        // serializing into a Vec can't fail
+        let data = borsh::to_vec(&VersionedEvent::V1(self)).unwrap();
+        sol_log_data(&[&data]);
+    }
+}
+
+impl VersionedEvent {
+    // decodes one field of a `Program data:` log line, after base64
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        Self::try_from_slice(data).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_round_trip() {
+        let event = VersionedEvent::V1(EscrowEvent::TimeLockReset {
+            escrow: Pubkey::new_unique(),
+            initializer: Pubkey::new_unique(),
+            clock_mode: ClockMode::UnixTimestamp,
+            old_unlock_time: 1,
+            old_time_out: 2,
+            unlock_time: 3,
+            time_out: 4,
+        });
+        let data = borsh::to_vec(&event).unwrap();
+        // the version and the event are the leading enum tags
+        assert_eq!(&data[..2], &[0, 3]);
+        assert_eq!(VersionedEvent::decode(&data), Some(event));
+        assert_eq!(VersionedEvent::decode(&data[..data.len() - 1]), None);
+    }
+}
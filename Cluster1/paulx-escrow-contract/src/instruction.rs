@@ -1,19 +1,45 @@
-use std::convert::TryInto;
+use std::{convert::{TryFrom, TryInto}, mem::size_of};
 use solana_program::{
-    program_error::ProgramError, 
-    // rent::Rent, 
-    // instruction::{Instruction, AccountMeta}, 
-    // sysvar,
+    program_error::ProgramError,
+    instruction::{Instruction, AccountMeta},
+    pubkey::Pubkey,
+    system_program,
+    sysvar,
 };
 
-use crate::error::EscrowError::InvalidInstruction;
+use crate::{error::EscrowError::InvalidInstruction, state::{AssetKind, ClockMode, Config, CounterOffer, Escrow, PriceBand}};
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EscrowInstruction {
     InitEscrow{
-        amount: u64
+        amount: u64,
+        // time from now until the escrow can be taken
+        unlock_delay: u64,
+        // time after unlock during which the escrow can be taken
+        timeout_window: u64,
+        // unit of the two values above, slots or seconds
+        clock_mode: ClockMode,
+        // a native deposit is the escrow account's balance above rent, the temp account is the escrow account
+        deposit_kind: AssetKind,
+        // a native payment goes to the initializer's wallet passed as the account to receive
+        expected_kind: AssetKind,
+        // restricts Exchange to this taker, anyone can take the escrow when absent
+        allowed_taker: Option<Pubkey>,
+        // when set, the escrow is a dutch auction whose price falls from `amount` at unlock to this at time out.
+        // the time lock instructions are rejected while it runs
+        auction_end_price: Option<u64>,
+        // when set, the escrow only settles while the oracle's price is inside the band
+        price_band: Option<PriceBand>,
     },
+    // takes `amount` of the remaining deposit and pays the pro-rata share of the expected amount.
+    // each bound fails the exchange on its own, so a re-priced escrow or a fee mint can't charge more
+    // or deliver less than quoted
     Exchange{
-        amount: u64
+        amount: u64,
+        // the most that leaves the taker's account, the expected mint's transfer fees included
+        max_payment: u64,
+        // the least that arrives in the taker's receive account, after the deposit mint's transfer fee
+        min_receive: u64,
     },
     // resets timelock and timeout
     ResetTimeLock {
@@ -22,7 +48,86 @@ pub enum EscrowInstruction {
     // cancel escrow
     Cancel {
 
-    }
+    },
+    // pushes time_out later by the given amount
+    ExtendTimeout {
+        extension: u64
+    },
+    // moves unlock_time earlier by the given amount
+    AdvanceUnlock {
+        advance: u64
+    },
+    // sets both deadlines to absolute values in the escrow's clock mode
+    SetTimeLock {
+        unlock_time: u64,
+        time_out: u64,
+    },
+    // returns the deposit of a timed out escrow, callable by anyone
+    Expire {
+
+    },
+    // opens a basket escrow over the deposit vaults passed, asking for one payment per receive account
+    InitBasket {
+        unlock_delay: u64,
+        timeout_window: u64,
+        clock_mode: ClockMode,
+        deposit_count: u8,
+        // one per payment leg, in the order of the receive accounts
+        expected_amounts: Vec<u64>,
+    },
+    // pays every payment leg and takes every deposit of a basket escrow
+    ExchangeBasket {
+
+    },
+    // returns every deposit of a basket escrow to the initializer
+    CancelBasket {
+
+    },
+    // creates the program config, signed by the program's upgrade authority who becomes its admin
+    InitConfig {
+        fee_bps: u16,
+    },
+    // sets the fee, treasury owner and admin, signed by the current and the new admin
+    UpdateConfig {
+        fee_bps: u16,
+    },
+    // sets the return data to what taking `amount` of the remaining deposit costs right now, as a u64
+    Quote {
+        amount: u64,
+    },
+    // InitEscrow for a token deposit without a client-made temp account: the program creates the vault as
+    // the associated token account of the escrow's PDA and moves `deposit_amount` into it from the
+    // initializer's token account. the other fields are InitEscrow's, with `expected_amount` as its `amount`
+    InitEscrowWithVault {
+        deposit_amount: u64,
+        expected_amount: u64,
+        unlock_delay: u64,
+        timeout_window: u64,
+        clock_mode: ClockMode,
+        expected_kind: AssetKind,
+        allowed_taker: Option<Pubkey>,
+        auction_end_price: Option<u64>,
+        price_band: Option<PriceBand>,
+    },
+    // offers `payment` of the escrow's expected tokens for `fill_amount` of its deposit, locking the payment
+    // in a vault owned by the taker's counter-offer PDA until it is accepted or withdrawn. rejected once the
+    // escrow has timed out. the initializer can ignore it: the escrow still closes and is taken as usual
+    ProposeCounter {
+        fill_amount: u64,
+        payment: u64,
+    },
+    // settles a counter-offer against the escrow, signed by the initializer, inside the escrow's window.
+    // the initializer agrees to the counter-offer's price, so neither the auction nor the price band
+    // applies and no price feed is passed. a counter-offer for more than is left after partial fills is
+    // stale and can only be withdrawn
+    AcceptCounter {
+
+    },
+    // returns everything in a counter-offer's vault to the taker, at any time and whether or not the
+    // escrow is still open
+    WithdrawCounter {
+
+    },
 }
 
 impl EscrowInstruction {
@@ -30,24 +135,903 @@ impl EscrowInstruction {
         let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
 
         Ok(match tag {
-            0 => Self::InitEscrow {
-                amount: Self::unpack_amount(rest)?,
+            0 => {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                let (unlock_delay, rest) = Self::unpack_u64(rest)?;
+                let (timeout_window, rest) = Self::unpack_u64(rest)?;
+                let clock_mode = rest
+                    .first()
+                    .and_then(|mode| ClockMode::try_from(*mode).ok())
+                    .ok_or(InvalidInstruction)?;
+                let deposit_kind = Self::unpack_asset_kind(rest.get(1..).unwrap_or_default())?;
+                let expected_kind = Self::unpack_asset_kind(rest.get(2..).unwrap_or_default())?;
+                let (allowed_taker, rest) = Self::unpack_option_pubkey(rest.get(3..).unwrap_or_default())?;
+                let (auction_end_price, rest) = Self::unpack_option_u64(rest)?;
+                let price_band = Self::unpack_option_price_band(rest)?;
+                Self::InitEscrow {
+                    amount,
+                    unlock_delay,
+                    timeout_window,
+                    clock_mode,
+                    deposit_kind,
+                    expected_kind,
+                    allowed_taker,
+                    auction_end_price,
+                    price_band,
+                }
             },
-            1 => Self::Exchange {
-                amount: Self::unpack_amount(rest)?
+            1 => {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                let (max_payment, rest) = Self::unpack_u64(rest)?;
+                let (min_receive, _rest) = Self::unpack_u64(rest)?;
+                Self::Exchange {
+                    amount,
+                    max_payment,
+                    min_receive,
+                }
             },
             2 => Self::ResetTimeLock {  },
             3 => Self::Cancel {  },
+            4 => Self::ExtendTimeout {
+                extension: Self::unpack_amount(rest)?
+            },
+            5 => Self::AdvanceUnlock {
+                advance: Self::unpack_amount(rest)?
+            },
+            6 => {
+                let (unlock_time, rest) = Self::unpack_u64(rest)?;
+                let (time_out, _rest) = Self::unpack_u64(rest)?;
+                Self::SetTimeLock {
+                    unlock_time,
+                    time_out,
+                }
+            },
+            7 => Self::Expire {  },
+            8 => {
+                let (unlock_delay, rest) = Self::unpack_u64(rest)?;
+                let (timeout_window, rest) = Self::unpack_u64(rest)?;
+                let (&clock_mode, rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                let clock_mode = ClockMode::try_from(clock_mode).map_err(|_| InvalidInstruction)?;
+                let (&deposit_count, rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                let (&payment_count, mut rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                let mut expected_amounts = Vec::with_capacity(payment_count as usize);
+                for _ in 0..payment_count {
+                    let (amount, next) = Self::unpack_u64(rest)?;
+                    expected_amounts.push(amount);
+                    rest = next;
+                }
+                Self::InitBasket {
+                    unlock_delay,
+                    timeout_window,
+                    clock_mode,
+                    deposit_count,
+                    expected_amounts,
+                }
+            },
+            9 => Self::ExchangeBasket {  },
+            10 => Self::CancelBasket {  },
+            11 => Self::InitConfig {
+                fee_bps: Self::unpack_u16(rest)?
+            },
+            12 => Self::UpdateConfig {
+                fee_bps: Self::unpack_u16(rest)?
+            },
+            13 => Self::Quote {
+                amount: Self::unpack_amount(rest)?
+            },
+            14 => {
+                let (deposit_amount, rest) = Self::unpack_u64(rest)?;
+                let (expected_amount, rest) = Self::unpack_u64(rest)?;
+                let (unlock_delay, rest) = Self::unpack_u64(rest)?;
+                let (timeout_window, rest) = Self::unpack_u64(rest)?;
+                let (&clock_mode, rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                let clock_mode = ClockMode::try_from(clock_mode).map_err(|_| InvalidInstruction)?;
+                let expected_kind = Self::unpack_asset_kind(rest)?;
+                let (allowed_taker, rest) = Self::unpack_option_pubkey(&rest[1..])?;
+                let (auction_end_price, rest) = Self::unpack_option_u64(rest)?;
+                let price_band = Self::unpack_option_price_band(rest)?;
+                Self::InitEscrowWithVault {
+                    deposit_amount,
+                    expected_amount,
+                    unlock_delay,
+                    timeout_window,
+                    clock_mode,
+                    expected_kind,
+                    allowed_taker,
+                    auction_end_price,
+                    price_band,
+                }
+            },
+            15 => {
+                let (fill_amount, rest) = Self::unpack_u64(rest)?;
+                let (payment, _rest) = Self::unpack_u64(rest)?;
+                Self::ProposeCounter {
+                    fill_amount,
+                    payment,
+                }
+            },
+            16 => Self::AcceptCounter {  },
+            17 => Self::WithdrawCounter {  },
             _ => return Err(InvalidInstruction.into()),
         })
     }
 
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(size_of::<Self>());
+        match self {
+            Self::InitEscrow { amount, unlock_delay, timeout_window, clock_mode, deposit_kind, expected_kind, allowed_taker, auction_end_price, price_band } => {
+                buf.push(0);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&unlock_delay.to_le_bytes());
+                buf.extend_from_slice(&timeout_window.to_le_bytes());
+                buf.push(*clock_mode as u8);
+                buf.push(*deposit_kind as u8);
+                buf.push(*expected_kind as u8);
+                Self::pack_options(&mut buf, allowed_taker, auction_end_price, price_band);
+            },
+            Self::Exchange { amount, max_payment, min_receive } => {
+                buf.push(1);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&max_payment.to_le_bytes());
+                buf.extend_from_slice(&min_receive.to_le_bytes());
+            },
+            Self::ResetTimeLock {  } => buf.push(2),
+            Self::Cancel {  } => buf.push(3),
+            Self::ExtendTimeout { extension } => {
+                buf.push(4);
+                buf.extend_from_slice(&extension.to_le_bytes());
+            },
+            Self::AdvanceUnlock { advance } => {
+                buf.push(5);
+                buf.extend_from_slice(&advance.to_le_bytes());
+            },
+            Self::SetTimeLock { unlock_time, time_out } => {
+                buf.push(6);
+                buf.extend_from_slice(&unlock_time.to_le_bytes());
+                buf.extend_from_slice(&time_out.to_le_bytes());
+            },
+            Self::Expire {  } => buf.push(7),
+            Self::InitBasket { unlock_delay, timeout_window, clock_mode, deposit_count, expected_amounts } => {
+                buf.push(8);
+                buf.extend_from_slice(&unlock_delay.to_le_bytes());
+                buf.extend_from_slice(&timeout_window.to_le_bytes());
+                buf.push(*clock_mode as u8);
+                buf.push(*deposit_count);
+                buf.push(expected_amounts.len() as u8);
+                for amount in expected_amounts {
+                    buf.extend_from_slice(&amount.to_le_bytes());
+                }
+            },
+            Self::ExchangeBasket {  } => buf.push(9),
+            Self::CancelBasket {  } => buf.push(10),
+            Self::InitConfig { fee_bps } => {
+                buf.push(11);
+                buf.extend_from_slice(&fee_bps.to_le_bytes());
+            },
+            Self::UpdateConfig { fee_bps } => {
+                buf.push(12);
+                buf.extend_from_slice(&fee_bps.to_le_bytes());
+            },
+            Self::Quote { amount } => {
+                buf.push(13);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            },
+            Self::InitEscrowWithVault {
+                deposit_amount,
+                expected_amount,
+                unlock_delay,
+                timeout_window,
+                clock_mode,
+                expected_kind,
+                allowed_taker,
+                auction_end_price,
+                price_band,
+            } => {
+                buf.push(14);
+                buf.extend_from_slice(&deposit_amount.to_le_bytes());
+                buf.extend_from_slice(&expected_amount.to_le_bytes());
+                buf.extend_from_slice(&unlock_delay.to_le_bytes());
+                buf.extend_from_slice(&timeout_window.to_le_bytes());
+                buf.push(*clock_mode as u8);
+                buf.push(*expected_kind as u8);
+                Self::pack_options(&mut buf, allowed_taker, auction_end_price, price_band);
+            },
+            Self::ProposeCounter { fill_amount, payment } => {
+                buf.push(15);
+                buf.extend_from_slice(&fill_amount.to_le_bytes());
+                buf.extend_from_slice(&payment.to_le_bytes());
+            },
+            Self::AcceptCounter {  } => buf.push(16),
+            Self::WithdrawCounter {  } => buf.push(17),
+        }
+        buf
+    }
+
+    // the optional trailer both escrow inits share, each a 0 tag or a 1 tag and the value
+    fn pack_options(buf: &mut Vec<u8>, allowed_taker: &Option<Pubkey>, auction_end_price: &Option<u64>, price_band: &Option<PriceBand>) {
+        match allowed_taker {
+            Some(taker) => {
+                buf.push(1);
+                buf.extend_from_slice(taker.as_ref());
+            },
+            None => buf.push(0),
+        }
+        match auction_end_price {
+            Some(price) => {
+                buf.push(1);
+                buf.extend_from_slice(&price.to_le_bytes());
+            },
+            None => buf.push(0),
+        }
+        match price_band {
+            Some(price_band) => {
+                buf.push(1);
+                buf.extend_from_slice(price_band.oracle.as_ref());
+                buf.extend_from_slice(&price_band.min_price.to_le_bytes());
+                buf.extend_from_slice(&price_band.max_price.to_le_bytes());
+            },
+            None => buf.push(0),
+        }
+    }
+
     fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
-        let amount = input
+        let (amount, _rest) = Self::unpack_u64(input)?;
+        Ok(amount)
+    }
+
+    fn unpack_asset_kind(input: &[u8]) -> Result<AssetKind, ProgramError> {
+        input
+            .first()
+            .and_then(|kind| AssetKind::try_from(*kind).ok())
+            .ok_or_else(|| InvalidInstruction.into())
+    }
+
+    fn unpack_option_pubkey(input: &[u8]) -> Result<(Option<Pubkey>, &[u8]), ProgramError> {
+        match input.split_first() {
+            Some((0, rest)) => Ok((None, rest)),
+            Some((1, rest)) => rest
+                .get(..32)
+                .and_then(|key| key.try_into().ok())
+                .map(|key| (Some(Pubkey::new_from_array(key)), &rest[32..]))
+                .ok_or_else(|| InvalidInstruction.into()),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+
+    fn unpack_option_u64(input: &[u8]) -> Result<(Option<u64>, &[u8]), ProgramError> {
+        match input.split_first() {
+            Some((0, rest)) => Ok((None, rest)),
+            Some((1, rest)) => {
+                let (value, rest) = Self::unpack_u64(rest)?;
+                Ok((Some(value), rest))
+            },
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+
+    fn unpack_option_price_band(input: &[u8]) -> Result<Option<PriceBand>, ProgramError> {
+        match input.split_first() {
+            Some((0, _)) => Ok(None),
+            Some((1, rest)) => {
+                let oracle = rest
+                    .get(..32)
+                    .and_then(|key| key.try_into().ok())
+                    .map(Pubkey::new_from_array)
+                    .ok_or(InvalidInstruction)?;
+                let (min_price, rest) = Self::unpack_u64(&rest[32..])?;
+                let (max_price, _rest) = Self::unpack_u64(rest)?;
+                Ok(Some(PriceBand { oracle, min_price, max_price }))
+            },
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+
+    fn unpack_u16(input: &[u8]) -> Result<u16, ProgramError> {
+        input
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or_else(|| InvalidInstruction.into())
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        let value = input
             .get(..8)
             .and_then(|slice| slice.try_into().ok())
             .map(u64::from_le_bytes)
             .ok_or(InvalidInstruction)?;
-        Ok(amount)
+        Ok((value, &input[8..]))
+    }
+}
+
+// token_program is the one owning the temp token account, spl_token or Token-2022
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    escrow_account: &Pubkey,
+    refund_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    unlock_delay: u64,
+    timeout_window: u64,
+    clock_mode: ClockMode,
+    deposit_kind: AssetKind,
+    expected_kind: AssetKind,
+    allowed_taker: Option<Pubkey>,
+    auction_end_price: Option<u64>,
+    price_band: Option<PriceBand>,
+) -> Instruction {
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        unlock_delay,
+        timeout_window,
+        clock_mode,
+        deposit_kind,
+        expected_kind,
+        allowed_taker,
+        auction_end_price,
+        price_band,
+    }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*initializer, true),
+        AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new_readonly(*token_to_receive_account, false),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(*refund_token_account, false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// the vault is derived from the escrow, the deposit mint and its token program. accounts needed by the
+// deposit mint's transfer hook can be appended to the returned instruction
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_with_vault(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    deposit_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    escrow_account: &Pubkey,
+    refund_token_account: &Pubkey,
+    deposit_mint: &Pubkey,
+    token_program: &Pubkey,
+    deposit_amount: u64,
+    expected_amount: u64,
+    unlock_delay: u64,
+    timeout_window: u64,
+    clock_mode: ClockMode,
+    expected_kind: AssetKind,
+    allowed_taker: Option<Pubkey>,
+    auction_end_price: Option<u64>,
+    price_band: Option<PriceBand>,
+) -> Instruction {
+    let (pda, _bump_seed) = Escrow::find_pda(escrow_account, program_id);
+    let data = EscrowInstruction::InitEscrowWithVault {
+        deposit_amount,
+        expected_amount,
+        unlock_delay,
+        timeout_window,
+        clock_mode,
+        expected_kind,
+        allowed_taker,
+        auction_end_price,
+        price_band,
+    }.pack();
+    let accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*deposit_token_account, false),
+        AccountMeta::new(Escrow::find_vault(escrow_account, deposit_mint, token_program, program_id), false),
+        AccountMeta::new_readonly(*token_to_receive_account, false),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(*refund_token_account, false),
+        AccountMeta::new_readonly(*deposit_mint, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// each leg names its mint and token program, a native leg passes the native mint and spl_token.
+// an oracle-gated escrow's price feed, then any accounts needed by transfer hooks, can be appended
+// to the returned instruction
+#[allow(clippy::too_many_arguments)]
+pub fn exchange(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    takers_sending_token_account: &Pubkey,
+    takers_token_to_receive_account: &Pubkey,
+    pdas_temp_token_account: &Pubkey,
+    initializer: &Pubkey,
+    initializers_token_to_receive_account: &Pubkey,
+    escrow_account: &Pubkey,
+    treasury_account: &Pubkey,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+    deposit_token_program: &Pubkey,
+    expected_token_program: &Pubkey,
+    amount: u64,
+    max_payment: u64,
+    min_receive: u64,
+) -> Instruction {
+    let (pda, _bump_seed) = Escrow::find_pda(escrow_account, program_id);
+    let (config, _bump_seed) = Config::find_pda(program_id);
+    let data = EscrowInstruction::Exchange { amount, max_payment, min_receive }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(*takers_sending_token_account, false),
+        AccountMeta::new(*takers_token_to_receive_account, false),
+        AccountMeta::new(*pdas_temp_token_account, false),
+        AccountMeta::new(*initializer, false),
+        AccountMeta::new(*initializers_token_to_receive_account, false),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(*deposit_token_program, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new(*treasury_account, false),
+        AccountMeta::new(*deposit_mint, false),
+        AccountMeta::new_readonly(*expected_mint, false),
+        AccountMeta::new_readonly(*expected_token_program, false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+pub fn cancel(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    pdas_temp_token_account: &Pubkey,
+    initializers_sent_token_account: &Pubkey,
+    escrow_account: &Pubkey,
+    deposit_mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (pda, _bump_seed) = Escrow::find_pda(escrow_account, program_id);
+    let data = EscrowInstruction::Cancel {  }.pack();
+    let accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*pdas_temp_token_account, false),
+        AccountMeta::new(*initializer, false),
+        AccountMeta::new(*initializers_sent_token_account, false),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new(*deposit_mint, false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn expire(
+    program_id: &Pubkey,
+    cranker: &Pubkey,
+    pdas_temp_token_account: &Pubkey,
+    initializer: &Pubkey,
+    refund_token_account: &Pubkey,
+    escrow_account: &Pubkey,
+    deposit_mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (pda, _bump_seed) = Escrow::find_pda(escrow_account, program_id);
+    let data = EscrowInstruction::Expire {  }.pack();
+    let accounts = vec![
+        AccountMeta::new(*cranker, true),
+        AccountMeta::new(*pdas_temp_token_account, false),
+        AccountMeta::new(*initializer, false),
+        AccountMeta::new(*refund_token_account, false),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new(*deposit_mint, false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// deposit_vaults are the initializer's funded token accounts, their owner moves to the escrow's PDA
+#[allow(clippy::too_many_arguments)]
+pub fn init_basket(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    basket_account: &Pubkey,
+    deposit_vaults: &[Pubkey],
+    token_to_receive_accounts: &[Pubkey],
+    expected_amounts: &[u64],
+    unlock_delay: u64,
+    timeout_window: u64,
+    clock_mode: ClockMode,
+    token_program: &Pubkey,
+) -> Instruction {
+    let data = EscrowInstruction::InitBasket {
+        unlock_delay,
+        timeout_window,
+        clock_mode,
+        deposit_count: deposit_vaults.len() as u8,
+        expected_amounts: expected_amounts.to_vec(),
+    }.pack();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*initializer, true),
+        AccountMeta::new(*basket_account, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(*token_program, false),
+    ];
+    accounts.extend(deposit_vaults.iter().map(|vault| AccountMeta::new(*vault, false)));
+    accounts.extend(token_to_receive_accounts.iter().map(|account| AccountMeta::new_readonly(*account, false)));
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// deposit_legs are (vault, taker's account to receive, mint) and payment_legs (taker's sending account,
+// initializer's account to receive, mint), each in the order the basket stores them
+pub fn exchange_basket(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    basket_account: &Pubkey,
+    initializer: &Pubkey,
+    deposit_legs: &[(Pubkey, Pubkey, Pubkey)],
+    payment_legs: &[(Pubkey, Pubkey, Pubkey)],
+    token_program: &Pubkey,
+) -> Instruction {
+    let (pda, _bump_seed) = Escrow::find_pda(basket_account, program_id);
+    let data = EscrowInstruction::ExchangeBasket {  }.pack();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(*basket_account, false),
+        AccountMeta::new(*initializer, false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(pda, false),
+    ];
+    for (from, to, mint) in deposit_legs.iter().chain(payment_legs) {
+        accounts.push(AccountMeta::new(*from, false));
+        accounts.push(AccountMeta::new(*to, false));
+        accounts.push(AccountMeta::new(*mint, false));
+    }
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// refund_legs are (vault, initializer's token account to refund, mint) in the order the basket stores them
+pub fn cancel_basket(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    basket_account: &Pubkey,
+    refund_legs: &[(Pubkey, Pubkey, Pubkey)],
+    token_program: &Pubkey,
+) -> Instruction {
+    let (pda, _bump_seed) = Escrow::find_pda(basket_account, program_id);
+    let data = EscrowInstruction::CancelBasket {  }.pack();
+    let mut accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*basket_account, false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(pda, false),
+    ];
+    for (vault, refund, mint) in refund_legs {
+        accounts.push(AccountMeta::new(*vault, false));
+        accounts.push(AccountMeta::new(*refund, false));
+        accounts.push(AccountMeta::new(*mint, false));
+    }
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+pub fn init_config(program_id: &Pubkey, admin: &Pubkey, treasury_owner: &Pubkey, fee_bps: u16) -> Instruction {
+    let (config, _bump_seed) = Config::find_pda(program_id);
+    let data = EscrowInstruction::InitConfig { fee_bps }.pack();
+    let accounts = vec![
+        AccountMeta::new(*admin, true),
+        AccountMeta::new(config, false),
+        AccountMeta::new_readonly(*treasury_owner, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(Config::find_program_data(program_id), false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// pass the current admin as new_admin to keep it
+pub fn update_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    treasury_owner: &Pubkey,
+    new_admin: &Pubkey,
+    fee_bps: u16,
+) -> Instruction {
+    let (config, _bump_seed) = Config::find_pda(program_id);
+    let data = EscrowInstruction::UpdateConfig { fee_bps }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(config, false),
+        AccountMeta::new_readonly(*treasury_owner, false),
+        AccountMeta::new_readonly(*new_admin, true),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+pub fn quote(program_id: &Pubkey, escrow_account: &Pubkey, amount: u64) -> Instruction {
+    let data = EscrowInstruction::Quote { amount }.pack();
+    let accounts = vec![AccountMeta::new_readonly(*escrow_account, false)];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// the counter-offer and its vault are derived from the escrow, the taker and the expected mint. accounts
+// needed by the expected mint's transfer hook can be appended to the returned instruction
+#[allow(clippy::too_many_arguments)]
+pub fn propose_counter(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    takers_sending_token_account: &Pubkey,
+    takers_token_to_receive_account: &Pubkey,
+    escrow_account: &Pubkey,
+    expected_mint: &Pubkey,
+    expected_token_program: &Pubkey,
+    fill_amount: u64,
+    payment: u64,
+) -> Instruction {
+    let (counter, _bump_seed) = CounterOffer::find_pda(escrow_account, taker, program_id);
+    let data = EscrowInstruction::ProposeCounter { fill_amount, payment }.pack();
+    let accounts = vec![
+        AccountMeta::new(*taker, true),
+        AccountMeta::new(*takers_sending_token_account, false),
+        AccountMeta::new_readonly(*takers_token_to_receive_account, false),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new(counter, false),
+        AccountMeta::new(CounterOffer::find_vault(escrow_account, taker, expected_mint, expected_token_program, program_id), false),
+        AccountMeta::new_readonly(*expected_mint, false),
+        AccountMeta::new_readonly(*expected_token_program, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// the fee is charged on the counter-offer's payment as on an Exchange. accounts needed by transfer
+// hooks can be appended to the returned instruction
+#[allow(clippy::too_many_arguments)]
+pub fn accept_counter(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    escrow_account: &Pubkey,
+    pdas_temp_token_account: &Pubkey,
+    initializers_token_to_receive_account: &Pubkey,
+    taker: &Pubkey,
+    takers_token_to_receive_account: &Pubkey,
+    treasury_account: &Pubkey,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+    deposit_token_program: &Pubkey,
+    expected_token_program: &Pubkey,
+) -> Instruction {
+    let (pda, _bump_seed) = Escrow::find_pda(escrow_account, program_id);
+    let (counter, _bump_seed) = CounterOffer::find_pda(escrow_account, taker, program_id);
+    let (config, _bump_seed) = Config::find_pda(program_id);
+    let data = EscrowInstruction::AcceptCounter {  }.pack();
+    let accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new(*pdas_temp_token_account, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new(*initializers_token_to_receive_account, false),
+        AccountMeta::new(counter, false),
+        AccountMeta::new(CounterOffer::find_vault(escrow_account, taker, expected_mint, expected_token_program, program_id), false),
+        AccountMeta::new(*taker, false),
+        AccountMeta::new(*takers_token_to_receive_account, false),
+        AccountMeta::new(*deposit_mint, false),
+        AccountMeta::new_readonly(*deposit_token_program, false),
+        AccountMeta::new(*expected_mint, false),
+        AccountMeta::new_readonly(*expected_token_program, false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new(*treasury_account, false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+// accounts needed by the expected mint's transfer hook can be appended to the returned instruction
+pub fn withdraw_counter(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    takers_refund_token_account: &Pubkey,
+    escrow_account: &Pubkey,
+    expected_mint: &Pubkey,
+    expected_token_program: &Pubkey,
+) -> Instruction {
+    let (counter, _bump_seed) = CounterOffer::find_pda(escrow_account, taker, program_id);
+    let data = EscrowInstruction::WithdrawCounter {  }.pack();
+    let accounts = vec![
+        AccountMeta::new(*taker, true),
+        AccountMeta::new(counter, false),
+        AccountMeta::new(CounterOffer::find_vault(escrow_account, taker, expected_mint, expected_token_program, program_id), false),
+        AccountMeta::new(*takers_refund_token_account, false),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new(*expected_mint, false),
+        AccountMeta::new_readonly(*expected_token_program, false),
+    ];
+    Instruction { program_id: *program_id, accounts, data }
+}
+
+pub fn reset_time_lock(program_id: &Pubkey, initializer: &Pubkey, escrow_account: &Pubkey) -> Instruction {
+    time_lock_instruction(program_id, initializer, escrow_account, EscrowInstruction::ResetTimeLock {  })
+}
+
+pub fn extend_timeout(program_id: &Pubkey, initializer: &Pubkey, escrow_account: &Pubkey, extension: u64) -> Instruction {
+    time_lock_instruction(program_id, initializer, escrow_account, EscrowInstruction::ExtendTimeout { extension })
+}
+
+pub fn advance_unlock(program_id: &Pubkey, initializer: &Pubkey, escrow_account: &Pubkey, advance: u64) -> Instruction {
+    time_lock_instruction(program_id, initializer, escrow_account, EscrowInstruction::AdvanceUnlock { advance })
+}
+
+pub fn set_time_lock(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    escrow_account: &Pubkey,
+    unlock_time: u64,
+    time_out: u64,
+) -> Instruction {
+    time_lock_instruction(program_id, initializer, escrow_account, EscrowInstruction::SetTimeLock { unlock_time, time_out })
+}
+
+// the time lock instructions all take the initializer and the escrow account
+fn time_lock_instruction(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    escrow_account: &Pubkey,
+    instruction: EscrowInstruction,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*initializer, true),
+        AccountMeta::new(*escrow_account, false),
+    ];
+    Instruction { program_id: *program_id, accounts, data: instruction.pack() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pack_unpack_round_trip() {
+        let instructions = [
+            EscrowInstruction::InitEscrow {
+                amount: 5,
+                unlock_delay: 100,
+                timeout_window: 1000,
+                clock_mode: ClockMode::Slot,
+                deposit_kind: AssetKind::Token,
+                expected_kind: AssetKind::Token,
+                allowed_taker: None,
+                auction_end_price: None,
+                price_band: None,
+            },
+            EscrowInstruction::InitEscrow {
+                amount: u64::MAX,
+                unlock_delay: 0,
+                timeout_window: 60,
+                clock_mode: ClockMode::UnixTimestamp,
+                deposit_kind: AssetKind::Native,
+                expected_kind: AssetKind::Token,
+                allowed_taker: Some(Pubkey::new_unique()),
+                auction_end_price: Some(1),
+                price_band: Some(PriceBand { oracle: Pubkey::new_unique(), min_price: 2, max_price: 3 }),
+            },
+            EscrowInstruction::Exchange { amount: 3, max_payment: 2, min_receive: 1 },
+            EscrowInstruction::ResetTimeLock {  },
+            EscrowInstruction::Cancel {  },
+            EscrowInstruction::ExtendTimeout { extension: 42 },
+            EscrowInstruction::AdvanceUnlock { advance: 7 },
+            EscrowInstruction::SetTimeLock { unlock_time: 1, time_out: 2 },
+            EscrowInstruction::Expire {  },
+            EscrowInstruction::InitBasket {
+                unlock_delay: 0,
+                timeout_window: 150,
+                clock_mode: ClockMode::Slot,
+                deposit_count: 3,
+                expected_amounts: vec![1, 2],
+            },
+            EscrowInstruction::ExchangeBasket {  },
+            EscrowInstruction::CancelBasket {  },
+            EscrowInstruction::InitConfig { fee_bps: 30 },
+            EscrowInstruction::UpdateConfig { fee_bps: u16::MAX },
+            EscrowInstruction::Quote { amount: 9 },
+            EscrowInstruction::InitEscrowWithVault {
+                deposit_amount: 50,
+                expected_amount: 30,
+                unlock_delay: 100,
+                timeout_window: 1000,
+                clock_mode: ClockMode::Slot,
+                expected_kind: AssetKind::Native,
+                allowed_taker: None,
+                auction_end_price: Some(10),
+                price_band: Some(PriceBand { oracle: Pubkey::new_unique(), min_price: 0, max_price: u64::MAX }),
+            },
+            EscrowInstruction::ProposeCounter { fill_amount: 4, payment: 11 },
+            EscrowInstruction::AcceptCounter {  },
+            EscrowInstruction::WithdrawCounter {  },
+        ];
+        for instruction in instructions {
+            let packed = instruction.pack();
+            assert_eq!(EscrowInstruction::unpack(&packed).unwrap(), instruction);
+        }
+    }
+
+    #[test]
+    fn test_unpack_rejects_bad_input() {
+        assert!(EscrowInstruction::unpack(&[]).is_err());
+        assert!(EscrowInstruction::unpack(&[99]).is_err());
+        // Exchange without its amount, and without its max payment
+        assert!(EscrowInstruction::unpack(&[1, 0, 0]).is_err());
+        assert!(EscrowInstruction::unpack(&[1, 3, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        // ProposeCounter without its payment
+        assert!(EscrowInstruction::unpack(&[15, 3, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        // InitEscrow with an unknown clock mode
+        let mut packed = EscrowInstruction::InitEscrow {
+            amount: 1,
+            unlock_delay: 0,
+            timeout_window: 150,
+            clock_mode: ClockMode::Slot,
+            deposit_kind: AssetKind::Token,
+            expected_kind: AssetKind::Native,
+            allowed_taker: Some(Pubkey::new_unique()),
+            auction_end_price: Some(1),
+            price_band: None,
+        }.pack();
+        packed[25] = 9;
+        assert!(EscrowInstruction::unpack(&packed).is_err());
+        // and with an unknown asset kind
+        packed[25] = ClockMode::Slot as u8;
+        packed[26] = 9;
+        assert!(EscrowInstruction::unpack(&packed).is_err());
+        // and without its price band tag, with an auction end price cut short, or the allowed taker before it
+        packed[26] = AssetKind::Native as u8;
+        assert!(EscrowInstruction::unpack(&packed[..packed.len() - 1]).is_err());
+        assert!(EscrowInstruction::unpack(&packed[..packed.len() - 2]).is_err());
+        assert!(EscrowInstruction::unpack(&packed[..packed.len() - 11]).is_err());
+        // InitBasket with fewer expected amounts than its payment count
+        let mut packed = EscrowInstruction::InitBasket {
+            unlock_delay: 0,
+            timeout_window: 150,
+            clock_mode: ClockMode::Slot,
+            deposit_count: 1,
+            expected_amounts: vec![1, 2],
+        }.pack();
+        packed.truncate(packed.len() - 1);
+        assert!(EscrowInstruction::unpack(&packed).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_exchange_account_flags() {
+        let program_id = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let escrow_account = Pubkey::new_unique();
+        let ix = exchange(
+            &program_id,
+            &taker,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &escrow_account,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &spl_token::id(),
+            &spl_token_2022::id(),
+            3,
+            2,
+            1,
+        );
+        assert_eq!(ix.accounts.len(), 15);
+        assert!(ix.accounts[0].is_signer);
+        assert!(ix.accounts.iter().skip(1).all(|meta| !meta.is_signer));
+        assert_eq!(ix.accounts[6].pubkey, escrow_account);
+        assert!(ix.accounts[6].is_writable);
+        assert_eq!(ix.accounts[8].pubkey, Escrow::find_pda(&escrow_account, &program_id).0);
+        assert!(!ix.accounts[8].is_writable);
+        assert_eq!(ix.accounts[10].pubkey, Config::find_pda(&program_id).0);
+        assert!(!ix.accounts[10].is_writable);
+        assert!(ix.accounts[11].is_writable);
+        assert_eq!(ix.accounts[7].pubkey, spl_token::id());
+        assert_eq!(ix.accounts[14].pubkey, spl_token_2022::id());
+    }
+}
@@ -1,5 +1,10 @@
+#[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 pub mod instruction;
 pub mod processor;
+pub mod accounts;
 pub mod error;
-pub mod state;
\ No newline at end of file
+pub mod state;
+pub mod event;
+#[cfg(feature = "cpi")]
+pub mod cpi;
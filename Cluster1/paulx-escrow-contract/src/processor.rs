@@ -1,15 +1,37 @@
 use solana_program::{
-    account_info::{AccountInfo, next_account_info},
+    account_info::AccountInfo,
     entrypoint::ProgramResult,
     program_error::ProgramError,
     msg,
     pubkey::Pubkey,
     program_pack::{Pack, IsInitialized},
     sysvar::{rent::Rent, Sysvar, clock::Clock},
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
+    system_instruction,
+};
+use spl_token_2022::{
+    extension::{
+        transfer_fee::{instruction::harvest_withheld_tokens_to_mint, TransferFeeAmount, TransferFeeConfig},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+    instruction::AuthorityType,
+    onchain::invoke_transfer_checked,
+    state::{Account as TokenAccount, Mint},
+};
+use crate::{
+    accounts::{
+        unpack_token_account, AcceptCounterAccounts, CancelAccounts, CancelBasketAccounts, ExchangeAccounts, ExchangeBasketAccounts, ExpireAccounts,
+        InitBasketAccounts, InitConfigAccounts, InitEscrowAccounts, InitEscrowWithVaultAccounts, ProposeCounterAccounts, QuoteAccounts, TimeLockAccounts,
+        UpdateConfigAccounts, WithdrawCounterAccounts,
+    },
+    error::EscrowError,
+    event::EscrowEvent,
+    instruction::EscrowInstruction,
+    state::{
+        AssetKind, BasketEscrow, BasketLeg, Config, CounterOffer, DutchAuction, Escrow, ClockMode, PriceBand, PriceFeed,
+        CONFIG_PDA_SEED, COUNTER_PDA_SEED, ESCROW_PDA_SEED, EXPIRE_TIP_LAMPORTS, MAX_FEE_BPS,
+    },
 };
-use spl_token::state::Account as TokenAccount;
-use crate::{instruction::EscrowInstruction, error::EscrowError, state::Escrow};
 
 pub struct Processor;
 
@@ -18,13 +40,13 @@ impl Processor {
         let instruction = EscrowInstruction::unpack(instruction_data)?;
 
         match instruction {
-            EscrowInstruction::InitEscrow { amount } => {
+            EscrowInstruction::InitEscrow { amount, unlock_delay, timeout_window, clock_mode, deposit_kind, expected_kind, allowed_taker, auction_end_price, price_band } => {
                 msg!("Instruction: InitEscrow");
-                Self::process_init_escrow(accounts, amount, program_id)
+                Self::process_init_escrow(accounts, amount, unlock_delay, timeout_window, clock_mode, deposit_kind, expected_kind, allowed_taker, auction_end_price, price_band, program_id)
             },
-            EscrowInstruction::Exchange { amount } => {
+            EscrowInstruction::Exchange { amount, max_payment, min_receive } => {
                 msg!("Instruction: Exchange");
-                Self::process_exchange(accounts, amount, program_id)
+                Self::process_exchange(accounts, amount, max_payment, min_receive, program_id)
             },
             //resets time lock and time_out
             EscrowInstruction::ResetTimeLock {  } => {
@@ -35,61 +57,149 @@ impl Processor {
                 msg!("Instruction: Cancel");
                 Self::process_cancel(accounts, program_id)
             },
+            EscrowInstruction::ExtendTimeout { extension } => {
+                msg!("Instruction: Extend Timeout");
+                Self::process_extend_timeout(accounts, extension, program_id)
+            },
+            EscrowInstruction::AdvanceUnlock { advance } => {
+                msg!("Instruction: Advance Unlock");
+                Self::process_advance_unlock(accounts, advance, program_id)
+            },
+            EscrowInstruction::SetTimeLock { unlock_time, time_out } => {
+                msg!("Instruction: Set Time Lock");
+                Self::process_set_time_lock(accounts, unlock_time, time_out, program_id)
+            },
+            EscrowInstruction::Expire {  } => {
+                msg!("Instruction: Expire");
+                Self::process_expire(accounts, program_id)
+            },
+            EscrowInstruction::InitBasket { unlock_delay, timeout_window, clock_mode, deposit_count, expected_amounts } => {
+                msg!("Instruction: InitBasket");
+                Self::process_init_basket(accounts, unlock_delay, timeout_window, clock_mode, deposit_count as usize, &expected_amounts, program_id)
+            },
+            EscrowInstruction::ExchangeBasket {  } => {
+                msg!("Instruction: ExchangeBasket");
+                Self::process_exchange_basket(accounts, program_id)
+            },
+            EscrowInstruction::CancelBasket {  } => {
+                msg!("Instruction: CancelBasket");
+                Self::process_cancel_basket(accounts, program_id)
+            },
+            EscrowInstruction::InitConfig { fee_bps } => {
+                msg!("Instruction: InitConfig");
+                Self::process_init_config(accounts, fee_bps, program_id)
+            },
+            EscrowInstruction::UpdateConfig { fee_bps } => {
+                msg!("Instruction: UpdateConfig");
+                Self::process_update_config(accounts, fee_bps, program_id)
+            },
+            EscrowInstruction::Quote { amount } => {
+                msg!("Instruction: Quote");
+                Self::process_quote(accounts, amount, program_id)
+            },
+            EscrowInstruction::InitEscrowWithVault {
+                deposit_amount,
+                expected_amount,
+                unlock_delay,
+                timeout_window,
+                clock_mode,
+                expected_kind,
+                allowed_taker,
+                auction_end_price,
+                price_band,
+            } => {
+                msg!("Instruction: InitEscrowWithVault");
+                Self::process_init_escrow_with_vault(accounts, deposit_amount, expected_amount, unlock_delay, timeout_window, clock_mode, expected_kind, allowed_taker, auction_end_price, price_band, program_id)
+            },
+            EscrowInstruction::ProposeCounter { fill_amount, payment } => {
+                msg!("Instruction: ProposeCounter");
+                Self::process_propose_counter(accounts, fill_amount, payment, program_id)
+            },
+            EscrowInstruction::AcceptCounter {  } => {
+                msg!("Instruction: AcceptCounter");
+                Self::process_accept_counter(accounts, program_id)
+            },
+            EscrowInstruction::WithdrawCounter {  } => {
+                msg!("Instruction: WithdrawCounter");
+                Self::process_withdraw_counter(accounts, program_id)
+            },
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn process_init_escrow(
         accounts: &[AccountInfo],
         amount: u64,
+        unlock_delay: u64,
+        timeout_window: u64,
+        clock_mode: ClockMode,
+        deposit_kind: AssetKind,
+        expected_kind: AssetKind,
+        allowed_taker: Option<Pubkey>,
+        auction_end_price: Option<u64>,
+        price_band: Option<PriceBand>,
         program_id: &Pubkey,
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let initializer = next_account_info(account_info_iter)?;
-
-        if !initializer.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
-        }
+        Self::check_escrow_terms(amount, unlock_delay, timeout_window, clock_mode, auction_end_price, price_band)?;
 
-        let temp_token_account = next_account_info(account_info_iter)?;
-
-        let token_to_receive_account = next_account_info(account_info_iter)?;
-        if *token_to_receive_account.owner != spl_token::id() {
-            return Err(ProgramError::IncorrectProgramId);
-        }
-
-        let escrow_account = next_account_info(account_info_iter)?;
-        let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
-
-        if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
-            return Err(EscrowError::NotRentExempt.into());
-        }
+        let InitEscrowAccounts {
+            initializer,
+            temp_token_account,
+            token_to_receive_account,
+            escrow_account,
+            rent,
+            token_program,
+            refund_token_account,
+        } = InitEscrowAccounts::validate(accounts, deposit_kind, expected_kind, program_id)?;
 
         let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
         if escrow_info.is_initialized() {
             return Err(ProgramError::AccountAlreadyInitialized);
         }
 
-        let clock = Clock::get()?;
-        escrow_info.unlock_time = clock.slot + 100;
-        escrow_info.time_out = escrow_info.unlock_time + 1000;
-
-        escrow_info.is_initialized = true;
-        escrow_info.initializer_pubkey = *initializer.key;
+        match deposit_kind {
+            AssetKind::Token => {
+                let temp_token_account_info = unpack_token_account(temp_token_account)?;
+                escrow_info.deposit_amount = temp_token_account_info.amount;
+                escrow_info.deposit_mint = temp_token_account_info.mint;
+            },
+            AssetKind::Native => {
+                escrow_info.deposit_amount = escrow_account.lamports() - rent.minimum_balance(escrow_account.data_len());
+                escrow_info.deposit_mint = spl_token::native_mint::id();
+            },
+        }
         escrow_info.temp_token_account_pubkey = *temp_token_account.key;
-        escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
-        escrow_info.expected_amount = amount;
+        escrow_info.deposit_kind = deposit_kind;
 
-        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
-        let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        let (pda, bump_seed) = Escrow::find_pda(escrow_account.key, program_id);
+        Self::open_escrow(
+            escrow_info,
+            escrow_account,
+            initializer,
+            token_to_receive_account,
+            refund_token_account,
+            amount,
+            unlock_delay,
+            timeout_window,
+            clock_mode,
+            expected_kind,
+            allowed_taker,
+            auction_end_price,
+            price_band,
+            bump_seed,
+        )?;
+
+        if deposit_kind == AssetKind::Native {
+            return Ok(());
+        }
 
-        let token_program = next_account_info(account_info_iter)?;
-        let owner_change_ix = spl_token::instruction::set_authority(
+        let owner_change_ix = spl_token_2022::instruction::set_authority(
             token_program.key,
             temp_token_account.key,
             Some(&pda),
-            spl_token::instruction::AuthorityType::AccountOwner,
+            AuthorityType::AccountOwner,
             initializer.key,
-            &[&initializer.key],
+            &[initializer.key],
         )?;
 
         msg!("Calling the token program to transfer token account ownership...");
@@ -105,237 +215,1224 @@ impl Processor {
         Ok(())
     }
 
-    fn process_exchange(
+    #[allow(clippy::too_many_arguments)]
+    fn process_init_escrow_with_vault(
         accounts: &[AccountInfo],
-        amount_expected_by_taker: u64,
+        deposit_amount: u64,
+        expected_amount: u64,
+        unlock_delay: u64,
+        timeout_window: u64,
+        clock_mode: ClockMode,
+        expected_kind: AssetKind,
+        allowed_taker: Option<Pubkey>,
+        auction_end_price: Option<u64>,
+        price_band: Option<PriceBand>,
         program_id: &Pubkey,
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let taker = next_account_info(account_info_iter)?;
-    
-        if !taker.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        Self::check_escrow_terms(expected_amount, unlock_delay, timeout_window, clock_mode, auction_end_price, price_band)?;
+
+        let InitEscrowWithVaultAccounts {
+            initializer,
+            deposit_token_account,
+            vault,
+            token_to_receive_account,
+            escrow_account,
+            token_program,
+            refund_token_account,
+            deposit_mint,
+            pda_account,
+            system_program_account,
+            associated_token_program,
+            additional_accounts,
+            bump_seed,
+        } = InitEscrowWithVaultAccounts::validate(accounts, expected_kind, program_id)?;
+
+        let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
+        if escrow_info.is_initialized() {
+            return Err(ProgramError::AccountAlreadyInitialized);
         }
-    
-        let takers_sending_token_account = next_account_info(account_info_iter)?;
-    
-        let takers_token_to_receive_account = next_account_info(account_info_iter)?;
-    
-        let pdas_temp_token_account = next_account_info(account_info_iter)?;
-        let pdas_temp_token_account_info =
-            TokenAccount::unpack(&pdas_temp_token_account.try_borrow_data()?)?;
-        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
-    
-        if amount_expected_by_taker != pdas_temp_token_account_info.amount {
-            return Err(EscrowError::ExpectedAmountMismatch.into());
+
+        // idempotent so a vault created ahead of time can't block the escrow, whatever it already
+        // holds is not counted as the deposit
+        let create_vault_ix = spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            initializer.key,
+            pda_account.key,
+            deposit_mint.key,
+            token_program.key,
+        );
+        msg!("Calling the associated token program to create the vault...");
+        invoke(
+            &create_vault_ix,
+            &[
+                initializer.clone(),
+                vault.clone(),
+                pda_account.clone(),
+                deposit_mint.clone(),
+                system_program_account.clone(),
+                token_program.clone(),
+                associated_token_program.clone(),
+            ],
+        )?;
+
+        let balance_before = unpack_token_account(vault)?.amount;
+        msg!("Calling the token program to move {} of the initializer's tokens into the vault...", deposit_amount);
+        Self::transfer_tokens(deposit_amount, deposit_token_account, vault, initializer, deposit_mint, token_program, &[], additional_accounts)?;
+
+        // a transfer fee leaves less in the vault than was sent, the escrow holds what arrived
+        escrow_info.deposit_amount = unpack_token_account(vault)?.amount
+            .checked_sub(balance_before)
+            .ok_or(EscrowError::AmountOverflow)?;
+        escrow_info.deposit_mint = *deposit_mint.key;
+        escrow_info.temp_token_account_pubkey = *vault.key;
+        escrow_info.deposit_kind = AssetKind::Token;
+
+        Self::open_escrow(
+            escrow_info,
+            escrow_account,
+            initializer,
+            token_to_receive_account,
+            refund_token_account,
+            expected_amount,
+            unlock_delay,
+            timeout_window,
+            clock_mode,
+            expected_kind,
+            allowed_taker,
+            auction_end_price,
+            price_band,
+            bump_seed,
+        )
+    }
+
+    fn check_escrow_terms(
+        expected_amount: u64,
+        unlock_delay: u64,
+        timeout_window: u64,
+        clock_mode: ClockMode,
+        auction_end_price: Option<u64>,
+        price_band: Option<PriceBand>,
+    ) -> ProgramResult {
+        let (max_unlock_delay, min_timeout_window, max_timeout_window) = clock_mode.time_lock_bounds();
+        if unlock_delay > max_unlock_delay
+            || !(min_timeout_window..=max_timeout_window).contains(&timeout_window)
+        {
+            return Err(EscrowError::TimeLockOutOfRange.into());
         }
-    
-        let initializers_main_account = next_account_info(account_info_iter)?;
-        let initializers_token_to_receive_account = next_account_info(account_info_iter)?;
-        let escrow_account = next_account_info(account_info_iter)?;
-    
-        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
-    
-        if escrow_info.temp_token_account_pubkey != *pdas_temp_token_account.key {
-            return Err(ProgramError::InvalidAccountData);
+        // the auction starts at the expected amount and can only fall from there
+        if auction_end_price.is_some_and(|end_price| end_price > expected_amount) {
+            return Err(EscrowError::InvalidAuction.into());
         }
+        if price_band.is_some_and(|price_band| price_band.min_price > price_band.max_price) {
+            return Err(EscrowError::InvalidPriceBand.into());
+        }
+        Ok(())
+    }
+
+    // records a new escrow whose deposit side is already filled in, then stores and announces it
+    #[allow(clippy::too_many_arguments)]
+    fn open_escrow(
+        mut escrow_info: Escrow,
+        escrow_account: &AccountInfo,
+        initializer: &AccountInfo,
+        token_to_receive_account: &AccountInfo,
+        refund_token_account: &AccountInfo,
+        expected_amount: u64,
+        unlock_delay: u64,
+        timeout_window: u64,
+        clock_mode: ClockMode,
+        expected_kind: AssetKind,
+        allowed_taker: Option<Pubkey>,
+        auction_end_price: Option<u64>,
+        price_band: Option<PriceBand>,
+        bump_seed: u8,
+    ) -> ProgramResult {
+        escrow_info.expected_mint = match expected_kind {
+            AssetKind::Token => unpack_token_account(token_to_receive_account)?.mint,
+            AssetKind::Native => spl_token::native_mint::id(),
+        };
+
+        let clock = Clock::get()?;
+        escrow_info.clock_mode = clock_mode;
+        escrow_info.unlock_time = clock_mode.now(&clock)
+            .checked_add(unlock_delay)
+            .ok_or(EscrowError::AmountOverflow)?;
+        escrow_info.time_out = escrow_info.unlock_time
+            .checked_add(timeout_window)
+            .ok_or(EscrowError::AmountOverflow)?;
+
+        escrow_info.is_initialized = true;
+        escrow_info.initializer_pubkey = *initializer.key;
+        escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
+        escrow_info.refund_token_account_pubkey = *refund_token_account.key;
+        escrow_info.expected_amount = expected_amount;
+        escrow_info.expected_kind = expected_kind;
+        escrow_info.allowed_taker = allowed_taker;
+        escrow_info.auction = auction_end_price.map(|end_price| DutchAuction { start_price: expected_amount, end_price });
+        escrow_info.price_band = price_band;
+        escrow_info.bump_seed = bump_seed;
 
-        if escrow_info.initializer_pubkey != *initializers_main_account.key {
-            return Err(ProgramError::InvalidAccountData);
+        let event = EscrowEvent::EscrowCreated {
+            escrow: *escrow_account.key,
+            initializer: *initializer.key,
+            allowed_taker,
+            deposit_mint: escrow_info.deposit_mint,
+            expected_mint: escrow_info.expected_mint,
+            deposit_amount: escrow_info.deposit_amount,
+            expected_amount,
+            clock_mode,
+            unlock_time: escrow_info.unlock_time,
+            time_out: escrow_info.time_out,
+        };
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        event.emit();
+        Ok(())
+    }
+
+    fn process_exchange(
+        accounts: &[AccountInfo],
+        fill_amount: u64,
+        max_payment: u64,
+        min_receive: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let ExchangeAccounts {
+            taker,
+            takers_sending_token_account,
+            takers_token_to_receive_account,
+            pdas_temp_token_account,
+            initializers_main_account,
+            initializers_token_to_receive_account,
+            escrow_account,
+            token_program,
+            pda_account,
+            system_program_account,
+            treasury_account,
+            deposit_mint,
+            expected_mint,
+            expected_token_program,
+            price_feed,
+            additional_accounts,
+            mut escrow_info,
+            config,
+        } = ExchangeAccounts::validate(accounts, program_id)?;
+
+        let vault_balance = Self::vault_balance(&escrow_info, pdas_temp_token_account)?;
+        let signer_seeds: &[&[u8]] = &[ESCROW_PDA_SEED, escrow_account.key.as_ref(), &[escrow_info.bump_seed]];
+
+        let now = Self::check_exchange_window(escrow_info.clock_mode, escrow_info.unlock_time, escrow_info.time_out)?;
+        if let (Some(price_band), Some(price_feed)) = (&escrow_info.price_band, price_feed) {
+            Self::check_price_band(price_band, price_feed)?;
         }
     
-        if escrow_info.initializer_token_to_receive_account_pubkey != *initializers_token_to_receive_account.key {
-            return Err(ProgramError::InvalidAccountData);
+        let payment = escrow_info.payment_at(fill_amount, now).ok_or(EscrowError::InvalidFillAmount)?;
+        if vault_balance < escrow_info.deposit_amount {
+            return Err(EscrowError::ExpectedAmountMismatch.into());
         }
+        let is_full_fill = fill_amount == escrow_info.deposit_amount;
+        msg!("Filling {} of {} deposited for {} of {} expected", fill_amount, escrow_info.deposit_amount, payment, escrow_info.expected_amount);
 
-        let current_slot = Clock::get()?.slot;
+        // the fee comes out of the initializer's side of the payment, the taker pays the quoted price
+        let fee = match config {
+            Some(config) => config.fee_for(payment).ok_or(EscrowError::AmountOverflow)?,
+            None => 0,
+        };
+        msg!("Settling {} deposited for {} paid, {} to the initializer and {} fee to the treasury", fill_amount, payment, payment - fee, fee);
 
-        if current_slot < escrow_info.unlock_time {
-            return Err(EscrowError::EscrowUnlockTime.into());
+        // each leg of the payment is grossed up by the expected mint's transfer fee on its own
+        let debit = match escrow_info.expected_kind {
+            AssetKind::Token => {
+                let fee_debit = if fee > 0 { Self::amount_with_transfer_fee(expected_mint, fee)? } else { 0 };
+                Self::amount_with_transfer_fee(expected_mint, payment - fee)?
+                    .checked_add(fee_debit)
+                    .ok_or(EscrowError::AmountOverflow)?
+            },
+            AssetKind::Native => payment,
+        };
+        if debit > max_payment {
+            msg!("Payment of {} debits {}, above the taker's maximum of {}", payment, debit, max_payment);
+            return Err(EscrowError::MaxPaymentExceeded.into());
         }
-        if current_slot > escrow_info.time_out {
-            return Err(EscrowError::EscrowTimeout.into());
+
+        Self::transfer_payment(escrow_info.expected_kind, payment - fee, takers_sending_token_account, initializers_token_to_receive_account, taker, expected_mint, expected_token_program, system_program_account, additional_accounts)?;
+        if fee > 0 {
+            Self::transfer_payment(escrow_info.expected_kind, fee, takers_sending_token_account, treasury_account, taker, expected_mint, expected_token_program, system_program_account, additional_accounts)?;
         }
-    
-        let token_program = next_account_info(account_info_iter)?;
-    
-        let transfer_to_initializer_ix = spl_token::instruction::transfer(
-            token_program.key,
-            takers_sending_token_account.key,
-            initializers_token_to_receive_account.key,
-            taker.key,
-            &[&taker.key],
-            escrow_info.expected_amount,
-        )?;
-        msg!("Calling the token program to transfer tokens to the escrow's initializer...");
-        invoke(
-            &transfer_to_initializer_ix,
-            &[
-                takers_sending_token_account.clone(),
-                initializers_token_to_receive_account.clone(),
-                taker.clone(),
-                token_program.clone(),
-            ],
-        )?;
 
-        let pda_account = next_account_info(account_info_iter)?;
+        // a full fill of a token vault also sweeps anything sent to it on top of the deposit so it can be closed
+        let release_amount = if is_full_fill && escrow_info.deposit_kind == AssetKind::Token { vault_balance } else { fill_amount };
+        let balance_before = Self::receive_balance(escrow_info.deposit_kind, takers_token_to_receive_account)?;
+        Self::release_deposit(escrow_info.deposit_kind, release_amount, pdas_temp_token_account, takers_token_to_receive_account, pda_account, deposit_mint, token_program, signer_seeds, additional_accounts)?;
 
-        let transfer_to_taker_ix = spl_token::instruction::transfer(
-            token_program.key,
-            pdas_temp_token_account.key,
-            takers_token_to_receive_account.key,
-            &pda,
-            &[&pda],
-            pdas_temp_token_account_info.amount,
-        )?;
-        msg!("Calling the token program to transfer tokens to the taker...");
-        invoke_signed(
-            &transfer_to_taker_ix,
-            &[
-                pdas_temp_token_account.clone(),
-                takers_token_to_receive_account.clone(),
-                pda_account.clone(),
-                token_program.clone(),
-            ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
-        )?;
+        // a deposit mint's transfer fee leaves the taker with less than the fill, what counts is what arrived
+        let received = Self::receive_balance(escrow_info.deposit_kind, takers_token_to_receive_account)?.saturating_sub(balance_before);
+        if received < min_receive {
+            msg!("Taker receives {} of the {} taken, below their minimum of {}", received, fill_amount, min_receive);
+            return Err(EscrowError::MinReceiveNotMet.into());
+        }
 
-        let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
-            token_program.key,
-            pdas_temp_token_account.key,
-            initializers_main_account.key,
-            &pda,
-            &[&pda]
-        )?;
-        msg!("Calling the token program to close pda's temp account...");
-        invoke_signed(
-            &close_pdas_temp_acc_ix,
-            &[
-                pdas_temp_token_account.clone(),
-                initializers_main_account.clone(),
-                pda_account.clone(),
-                token_program.clone(),
-            ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
-        )?;
+        // an auction keeps its shape over what is left, whatever the fill was paid
+        match escrow_info.auction {
+            Some(auction) => {
+                let auction = auction.after_fill(fill_amount, escrow_info.deposit_amount).ok_or(EscrowError::AmountOverflow)?;
+                escrow_info.expected_amount = auction.start_price;
+                escrow_info.auction = Some(auction);
+            },
+            None => escrow_info.expected_amount -= payment,
+        }
+        escrow_info.deposit_amount -= fill_amount;
+        EscrowEvent::EscrowExchanged {
+            escrow: *escrow_account.key,
+            initializer: escrow_info.initializer_pubkey,
+            taker: *taker.key,
+            deposit_mint: escrow_info.deposit_mint,
+            expected_mint: escrow_info.expected_mint,
+            fill_amount,
+            payment,
+            fee,
+            remaining_deposit: escrow_info.deposit_amount,
+            remaining_expected: escrow_info.expected_amount,
+            clock_mode: escrow_info.clock_mode,
+            unlock_time: escrow_info.unlock_time,
+            time_out: escrow_info.time_out,
+        }.emit();
+
+        if !is_full_fill {
+            Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+            return Ok(());
+        }
+
+        Self::close_vault(escrow_info.deposit_kind, pdas_temp_token_account, initializers_main_account, pda_account, deposit_mint, token_program, signer_seeds)?;
 
         msg!("Closing the escrow account...");
-        **initializers_main_account.lamports.borrow_mut() = initializers_main_account.lamports()
-            .checked_add(escrow_account.lamports())
-            .ok_or(EscrowError::AmountOverflow)?;
-        **escrow_account.lamports.borrow_mut() = 0;
-        *escrow_account.try_borrow_mut_data()? = &mut [];
-        Ok(())
+        Self::close_escrow_account(escrow_account, initializers_main_account)
     }
 
     fn process_cancel(
         accounts: &[AccountInfo], 
         program_id: &Pubkey
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let initializer = next_account_info(account_info_iter)?;
+        let CancelAccounts {
+            initializer: _,
+            pda_token_account,
+            initializer_main_account,
+            initializer_sent_token_account,
+            escrow_account,
+            token_program,
+            pda_account: pda_account_info,
+            deposit_mint,
+            additional_accounts,
+            escrow_info,
+        } = CancelAccounts::validate(accounts, program_id)?;
+
+        let vault_balance = Self::vault_balance(&escrow_info, pda_token_account)?;
+        let signer_seeds: &[&[u8]] = &[ESCROW_PDA_SEED, escrow_account.key.as_ref(), &[escrow_info.bump_seed]];
 
-        if !initializer.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        Self::release_deposit(escrow_info.deposit_kind, vault_balance, pda_token_account, initializer_sent_token_account, pda_account_info, deposit_mint, token_program, signer_seeds, additional_accounts)?;
+        Self::close_vault(escrow_info.deposit_kind, pda_token_account, initializer_main_account, pda_account_info, deposit_mint, token_program, signer_seeds)?;
+        EscrowEvent::EscrowCancelled {
+            escrow: *escrow_account.key,
+            initializer: escrow_info.initializer_pubkey,
+            deposit_mint: escrow_info.deposit_mint,
+            expected_mint: escrow_info.expected_mint,
+            refund_amount: vault_balance,
+            clock_mode: escrow_info.clock_mode,
+            unlock_time: escrow_info.unlock_time,
+            time_out: escrow_info.time_out,
+        }.emit();
+        Self::close_escrow_account(escrow_account, initializer_main_account)
+    }
+
+    fn process_expire(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey
+    ) -> ProgramResult {
+        let ExpireAccounts {
+            cranker,
+            pda_token_account,
+            initializer_main_account,
+            refund_token_account,
+            escrow_account,
+            token_program,
+            pda_account: pda_account_info,
+            deposit_mint,
+            additional_accounts,
+            escrow_info,
+        } = ExpireAccounts::validate(accounts, program_id)?;
+
+        let now = escrow_info.clock_mode.now(&Clock::get()?);
+        if now <= escrow_info.time_out {
+            msg!("Escrow times out at {} {}, current {} is {}", escrow_info.clock_mode, escrow_info.time_out, escrow_info.clock_mode, now);
+            return Err(EscrowError::EscrowNotExpired.into());
         }
 
-        let pda_token_account = next_account_info(account_info_iter)?;
-        let initializer_main_account = next_account_info(account_info_iter)?;
-        let initializer_sent_token_account = next_account_info(account_info_iter)?;
-        let escrow_account = next_account_info(account_info_iter)?;
+        let vault_balance = Self::vault_balance(&escrow_info, pda_token_account)?;
+        let signer_seeds: &[&[u8]] = &[ESCROW_PDA_SEED, escrow_account.key.as_ref(), &[escrow_info.bump_seed]];
+
+        msg!("Returning the deposit to the initializer...");
+        Self::release_deposit(escrow_info.deposit_kind, vault_balance, pda_token_account, refund_token_account, pda_account_info, deposit_mint, token_program, signer_seeds, additional_accounts)?;
+        Self::close_vault(escrow_info.deposit_kind, pda_token_account, initializer_main_account, pda_account_info, deposit_mint, token_program, signer_seeds)?;
+
+        // the initializer cranking their own escrow gets everything back
+        let tip = if cranker.key == initializer_main_account.key {
+            0
+        } else {
+            EXPIRE_TIP_LAMPORTS.min(escrow_account.lamports())
+        };
+        msg!("Closing the escrow account, tipping the cranker {} lamports...", tip);
+        **escrow_account.try_borrow_mut_lamports()? -= tip;
+        **cranker.try_borrow_mut_lamports()? = cranker
+            .lamports()
+            .checked_add(tip)
+            .ok_or(EscrowError::AmountOverflow)?;
+        EscrowEvent::EscrowExpired {
+            escrow: *escrow_account.key,
+            initializer: escrow_info.initializer_pubkey,
+            cranker: *cranker.key,
+            deposit_mint: escrow_info.deposit_mint,
+            expected_mint: escrow_info.expected_mint,
+            refund_amount: vault_balance,
+            tip,
+            clock_mode: escrow_info.clock_mode,
+            unlock_time: escrow_info.unlock_time,
+            time_out: escrow_info.time_out,
+        }.emit();
+        Self::close_escrow_account(escrow_account, initializer_main_account)
+    }
+
+    fn process_reset_time_lock (
+        accounts: &[AccountInfo],
+        program_id: &Pubkey
+    ) -> ProgramResult {
+        // resets the unlock_time and time_out, 100/1000 slots or the equivalent in seconds
+        Self::update_time_lock(accounts, program_id, |escrow_info, now| {
+            let (unlock_delay, timeout_window) = match escrow_info.clock_mode {
+                ClockMode::Slot => (100, 1000),
+                ClockMode::UnixTimestamp => (40, 400),
+            };
+            let unlock_time = now.checked_add(unlock_delay).ok_or(EscrowError::AmountOverflow)?;
+            let time_out = unlock_time.checked_add(timeout_window).ok_or(EscrowError::AmountOverflow)?;
+            Ok((unlock_time, time_out))
+        })
+    }
 
-        if escrow_account.owner != program_id || escrow_account.is_writable == false {
-            return Err(ProgramError::IllegalOwner);
+    fn process_extend_timeout(
+        accounts: &[AccountInfo],
+        extension: u64,
+        program_id: &Pubkey
+    ) -> ProgramResult {
+        Self::update_time_lock(accounts, program_id, |escrow_info, _now| {
+            let time_out = escrow_info.time_out
+                .checked_add(extension)
+                .ok_or(EscrowError::AmountOverflow)?;
+            Ok((escrow_info.unlock_time, time_out))
+        })
+    }
+
+    fn process_advance_unlock(
+        accounts: &[AccountInfo],
+        advance: u64,
+        program_id: &Pubkey
+    ) -> ProgramResult {
+        Self::update_time_lock(accounts, program_id, |escrow_info, _now| {
+            let unlock_time = escrow_info.unlock_time
+                .checked_sub(advance)
+                .ok_or(EscrowError::InvalidTimeLock)?;
+            Ok((unlock_time, escrow_info.time_out))
+        })
+    }
+
+    fn process_set_time_lock(
+        accounts: &[AccountInfo],
+        unlock_time: u64,
+        time_out: u64,
+        program_id: &Pubkey
+    ) -> ProgramResult {
+        Self::update_time_lock(accounts, program_id, |_escrow_info, _now| Ok((unlock_time, time_out)))
+    }
+
+    // shared by the time lock instructions: checks the initializer, validates the
+    // (unlock_time, time_out) pair computed by `update` and persists it.
+    // an auction's price is drawn between its deadlines, so they are fixed from unlock until it times out
+    fn update_time_lock<F>(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        update: F,
+    ) -> ProgramResult
+    where
+        F: FnOnce(&Escrow, u64) -> Result<(u64, u64), ProgramError>,
+    {
+        let TimeLockAccounts { initializer, escrow_account, mut escrow_info } = TimeLockAccounts::validate(accounts, program_id)?;
+
+        let now = escrow_info.clock_mode.now(&Clock::get()?);
+        if escrow_info.auction.is_some() && (escrow_info.unlock_time..=escrow_info.time_out).contains(&now) {
+            msg!("Auction runs from {} {} to {}, current {} is {}", escrow_info.clock_mode, escrow_info.unlock_time, escrow_info.time_out, escrow_info.clock_mode, now);
+            return Err(EscrowError::AuctionRunning.into());
         }
+        let (unlock_time, time_out) = update(&escrow_info, now)?;
 
-        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        if unlock_time >= time_out
+            || (unlock_time != escrow_info.unlock_time && unlock_time < now)
+            || (time_out != escrow_info.time_out && time_out < now)
+        {
+            return Err(EscrowError::InvalidTimeLock.into());
+        }
 
-        if escrow_info.initializer_pubkey != *initializer.key {
-            return Err(ProgramError::InvalidAccountData);
+        let (max_unlock_delay, _min_timeout_window, max_timeout_window) = escrow_info.clock_mode.time_lock_bounds();
+        if unlock_time.saturating_sub(now) > max_unlock_delay
+            || time_out - unlock_time > max_timeout_window
+        {
+            return Err(EscrowError::TimeLockOutOfRange.into());
         }
 
-        let token_program = next_account_info(account_info_iter)?;
-        let pda_account_info = next_account_info(account_info_iter)?;
-        let pda_token_account_info = TokenAccount::unpack(&pda_token_account.try_borrow_data()?)?;
+        msg!("unlock_time ({}): {} -> {}", escrow_info.clock_mode, escrow_info.unlock_time, unlock_time);
+        msg!("time_out ({}): {} -> {}", escrow_info.clock_mode, escrow_info.time_out, time_out);
 
-        let (pda, nonce) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        EscrowEvent::TimeLockReset {
+            escrow: *escrow_account.key,
+            initializer: *initializer.key,
+            clock_mode: escrow_info.clock_mode,
+            old_unlock_time: escrow_info.unlock_time,
+            old_time_out: escrow_info.time_out,
+            unlock_time,
+            time_out,
+        }.emit();
 
-        let transfer_to_initializer_ix = spl_token::instruction::transfer(
-            token_program.key,
-            pda_token_account.key,
-            initializer_sent_token_account.key,
-            &pda,
-            &[&pda],
-            pda_token_account_info.amount
-        )?;
+        escrow_info.unlock_time = unlock_time;
+        escrow_info.time_out = time_out;
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
 
-        invoke_signed(
-            &transfer_to_initializer_ix,
-            &[
-                pda_token_account.clone(),
-                initializer_sent_token_account.clone(),
-                pda_account_info.clone(),
-                token_program.clone(),
-            ],
-            &[&[&b"escrow"[..], &[nonce]]],
+        Ok(())
+    }
+
+    fn process_init_basket(
+        accounts: &[AccountInfo],
+        unlock_delay: u64,
+        timeout_window: u64,
+        clock_mode: ClockMode,
+        deposit_count: usize,
+        expected_amounts: &[u64],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let (max_unlock_delay, min_timeout_window, max_timeout_window) = clock_mode.time_lock_bounds();
+        if unlock_delay > max_unlock_delay
+            || !(min_timeout_window..=max_timeout_window).contains(&timeout_window)
+        {
+            return Err(EscrowError::TimeLockOutOfRange.into());
+        }
+        if !BasketEscrow::valid_leg_count(deposit_count) || !BasketEscrow::valid_leg_count(expected_amounts.len()) {
+            return Err(EscrowError::InvalidBasket.into());
+        }
+
+        let InitBasketAccounts {
+            initializer,
+            basket_account,
+            token_program,
+            vaults,
+            token_to_receive_accounts,
+        } = InitBasketAccounts::validate(accounts, deposit_count, expected_amounts.len(), program_id)?;
+
+        let (pda, bump_seed) = Escrow::find_pda(basket_account.key, program_id);
+
+        let mut deposits = Vec::with_capacity(deposit_count);
+        for vault in vaults {
+            let vault_info = unpack_token_account(vault)?;
+            deposits.push(BasketLeg { token_account_pubkey: *vault.key, mint: vault_info.mint, amount: vault_info.amount });
+
+            let owner_change_ix = spl_token_2022::instruction::set_authority(
+                token_program.key,
+                vault.key,
+                Some(&pda),
+                AuthorityType::AccountOwner,
+                initializer.key,
+                &[initializer.key],
+            )?;
+            msg!("Calling the token program to transfer token account ownership...");
+            invoke(
+                &owner_change_ix,
+                &[
+                    vault.clone(),
+                    initializer.clone(),
+                    token_program.clone(),
+                ],
+            )?;
+        }
+
+        let mut payments = Vec::with_capacity(expected_amounts.len());
+        for (token_to_receive_account, &amount) in token_to_receive_accounts.into_iter().zip(expected_amounts) {
+            let token_to_receive_account_info = unpack_token_account(token_to_receive_account)?;
+            payments.push(BasketLeg { token_account_pubkey: *token_to_receive_account.key, mint: token_to_receive_account_info.mint, amount });
+        }
+
+        let clock = Clock::get()?;
+        let unlock_time = clock_mode.now(&clock)
+            .checked_add(unlock_delay)
+            .ok_or(EscrowError::AmountOverflow)?;
+        let time_out = unlock_time
+            .checked_add(timeout_window)
+            .ok_or(EscrowError::AmountOverflow)?;
+
+        let basket_info = BasketEscrow {
+            is_initialized: true,
+            initializer_pubkey: *initializer.key,
+            unlock_time,
+            time_out,
+            bump_seed,
+            clock_mode,
+            deposits,
+            payments,
+        };
+        basket_info.pack(&mut basket_account.try_borrow_mut_data()?)
+    }
+
+    fn process_exchange_basket(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let ExchangeBasketAccounts {
+            taker,
+            basket_account,
+            initializers_main_account,
+            token_program,
+            pda_account,
+            releases,
+            payments,
+            additional_accounts,
+            basket_info,
+        } = ExchangeBasketAccounts::validate(accounts, program_id)?;
+        let signer_seeds: &[&[u8]] = &[ESCROW_PDA_SEED, basket_account.key.as_ref(), &[basket_info.bump_seed]];
+
+        Self::check_exchange_window(basket_info.clock_mode, basket_info.unlock_time, basket_info.time_out)?;
+
+        // every vault is checked before anything moves so the basket settles whole or not at all
+        let mut vault_balances = Vec::with_capacity(releases.len());
+        for ((vault, _takers_token_to_receive_account, _mint), leg) in releases.iter().zip(&basket_info.deposits) {
+            let vault_balance = unpack_token_account(vault)?.amount;
+            if vault_balance < leg.amount {
+                return Err(EscrowError::ExpectedAmountMismatch.into());
+            }
+            vault_balances.push(vault_balance);
+        }
+
+        for ((takers_sending_token_account, initializers_token_to_receive_account, mint), leg) in payments.into_iter().zip(&basket_info.payments) {
+            Self::transfer_payment(AssetKind::Token, leg.amount, takers_sending_token_account, initializers_token_to_receive_account, taker, mint, token_program, token_program, additional_accounts)?;
+        }
+
+        for ((vault, takers_token_to_receive_account, mint), amount) in releases.into_iter().zip(vault_balances) {
+            Self::release_deposit(AssetKind::Token, amount, vault, takers_token_to_receive_account, pda_account, mint, token_program, signer_seeds, additional_accounts)?;
+            Self::close_vault(AssetKind::Token, vault, initializers_main_account, pda_account, mint, token_program, signer_seeds)?;
+        }
+
+        msg!("Closing the basket escrow account...");
+        Self::close_escrow_account(basket_account, initializers_main_account)
+    }
+
+    fn process_cancel_basket(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let CancelBasketAccounts {
+            initializer,
+            basket_account,
+            token_program,
+            pda_account,
+            refunds,
+            additional_accounts,
+            basket_info,
+        } = CancelBasketAccounts::validate(accounts, program_id)?;
+        let signer_seeds: &[&[u8]] = &[ESCROW_PDA_SEED, basket_account.key.as_ref(), &[basket_info.bump_seed]];
+
+        for (vault, refund_token_account, mint) in refunds {
+            let vault_balance = unpack_token_account(vault)?.amount;
+            Self::release_deposit(AssetKind::Token, vault_balance, vault, refund_token_account, pda_account, mint, token_program, signer_seeds, additional_accounts)?;
+            Self::close_vault(AssetKind::Token, vault, initializer, pda_account, mint, token_program, signer_seeds)?;
+        }
+
+        Self::close_escrow_account(basket_account, initializer)
+    }
+
+    fn process_init_config(
+        accounts: &[AccountInfo],
+        fee_bps: u16,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        if fee_bps > MAX_FEE_BPS {
+            return Err(EscrowError::FeeTooHigh.into());
+        }
+
+        let InitConfigAccounts {
+            admin,
+            config_account,
+            treasury_owner,
+            system_program_account,
+            bump_seed,
+        } = InitConfigAccounts::validate(accounts, program_id)?;
+
+        msg!("Calling the system program to create the config account...");
+        Self::create_pda_account(
+            admin,
+            config_account,
+            system_program_account,
+            Config::LEN,
+            &[CONFIG_PDA_SEED, &[bump_seed]],
+            program_id,
         )?;
 
-        let close_escrow_token_acct_ix = spl_token::instruction::close_account(
-            token_program.key,
-            pda_token_account.key,
-            initializer_main_account.key,
-            &pda,
-            &[&pda],
+        let config_info = Config {
+            is_initialized: true,
+            admin_pubkey: *admin.key,
+            treasury_owner_pubkey: *treasury_owner.key,
+            fee_bps,
+            bump_seed,
+        };
+        msg!("Fee set to {} bps, treasury owner {}", fee_bps, treasury_owner.key);
+        Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    fn process_update_config(
+        accounts: &[AccountInfo],
+        fee_bps: u16,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        if fee_bps > MAX_FEE_BPS {
+            return Err(EscrowError::FeeTooHigh.into());
+        }
+
+        let UpdateConfigAccounts {
+            admin: _,
+            config_account,
+            treasury_owner,
+            new_admin,
+            mut config_info,
+        } = UpdateConfigAccounts::validate(accounts, program_id)?;
+
+        msg!("Fee changed from {} to {} bps, treasury owner {}", config_info.fee_bps, fee_bps, treasury_owner.key);
+        config_info.fee_bps = fee_bps;
+        config_info.treasury_owner_pubkey = *treasury_owner.key;
+        config_info.admin_pubkey = *new_admin.key;
+        Config::pack(config_info, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    // the quote is the payment Exchange would take in this slot, before any Token-2022 transfer fee
+    fn process_quote(
+        accounts: &[AccountInfo],
+        amount: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let QuoteAccounts { escrow_account: _, escrow_info } = QuoteAccounts::validate(accounts, program_id)?;
+
+        let now = escrow_info.clock_mode.now(&Clock::get()?);
+        let payment = escrow_info.payment_at(amount, now).ok_or(EscrowError::InvalidFillAmount)?;
+        msg!("Taking {} of {} deposited costs {} at {} {}", amount, escrow_info.deposit_amount, payment, escrow_info.clock_mode, now);
+        set_return_data(&payment.to_le_bytes());
+        Ok(())
+    }
+
+    fn process_propose_counter(
+        accounts: &[AccountInfo],
+        fill_amount: u64,
+        payment: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let ProposeCounterAccounts {
+            taker,
+            takers_sending_token_account,
+            takers_token_to_receive_account,
+            escrow_account,
+            counter_account,
+            counter_vault,
+            expected_mint,
+            expected_token_program,
+            system_program_account,
+            associated_token_program,
+            additional_accounts,
+            mut escrow_info,
+            bump_seed,
+        } = ProposeCounterAccounts::validate(accounts, program_id)?;
+
+        if fill_amount == 0 || fill_amount > escrow_info.deposit_amount {
+            return Err(EscrowError::InvalidFillAmount.into());
+        }
+        if payment == 0 {
+            return Err(EscrowError::InvalidCounter.into());
+        }
+        // an offer can be made before the escrow unlocks, but not once it can no longer be accepted
+        Self::check_not_timed_out(escrow_info.clock_mode, escrow_info.time_out)?;
+
+        msg!("Calling the system program to create the counter-offer account...");
+        Self::create_pda_account(
+            taker,
+            counter_account,
+            system_program_account,
+            CounterOffer::LEN,
+            &[COUNTER_PDA_SEED, escrow_account.key.as_ref(), taker.key.as_ref(), &[bump_seed]],
+            program_id,
         )?;
 
-        invoke_signed(
-            &close_escrow_token_acct_ix,
+        // idempotent for the same reason as the escrow's vault, tokens already in it aren't part of the payment
+        let create_vault_ix = spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            taker.key,
+            counter_account.key,
+            expected_mint.key,
+            expected_token_program.key,
+        );
+        msg!("Calling the associated token program to create the counter-offer's vault...");
+        invoke(
+            &create_vault_ix,
             &[
-                pda_token_account.clone(),
-                initializer_main_account.clone(),
-                pda_account_info.clone(),
-                token_program.clone(),
+                taker.clone(),
+                counter_vault.clone(),
+                counter_account.clone(),
+                expected_mint.clone(),
+                system_program_account.clone(),
+                expected_token_program.clone(),
+                associated_token_program.clone(),
             ],
-            &[&[&b"escrow"[..], &[nonce]]],
         )?;
-        
-        **initializer_main_account.try_borrow_mut_lamports()? = initializer_main_account
-            .lamports()
-            .checked_add(escrow_account.lamports())
-            .ok_or(EscrowError::AmountOverflow)?;
-        **escrow_account.try_borrow_mut_lamports()? = 0;
-        *escrow_account.try_borrow_mut_data()? = &mut [];
+
+        let balance_before = unpack_token_account(counter_vault)?.amount;
+        msg!("Calling the token program to lock {} of the taker's tokens in the counter-offer's vault...", payment);
+        Self::transfer_tokens(payment, takers_sending_token_account, counter_vault, taker, expected_mint, expected_token_program, &[], additional_accounts)?;
+
+        // as with a deposit, the counter-offer is worth what arrived after any transfer fee
+        let counter_info = CounterOffer {
+            is_initialized: true,
+            escrow_pubkey: *escrow_account.key,
+            taker_pubkey: *taker.key,
+            vault_pubkey: *counter_vault.key,
+            takers_token_to_receive_account_pubkey: *takers_token_to_receive_account.key,
+            fill_amount,
+            payment: unpack_token_account(counter_vault)?.amount
+                .checked_sub(balance_before)
+                .ok_or(EscrowError::AmountOverflow)?,
+            bump_seed,
+        };
+        escrow_info.open_counters = escrow_info.open_counters.checked_add(1).ok_or(EscrowError::AmountOverflow)?;
+        msg!("Offering {} for {} of {} deposited, {} counter-offers open", counter_info.payment, fill_amount, escrow_info.deposit_amount, escrow_info.open_counters);
+
+        EscrowEvent::CounterProposed {
+            escrow: *escrow_account.key,
+            taker: *taker.key,
+            counter: *counter_account.key,
+            fill_amount,
+            payment: counter_info.payment,
+            open_counters: escrow_info.open_counters,
+        }.emit();
+        CounterOffer::pack(counter_info, &mut counter_account.try_borrow_mut_data()?)?;
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
 
         Ok(())
     }
 
-    fn process_reset_time_lock (
+    // settles like an Exchange of the counter-offer's fill at its payment, inside the same window.
+    // the auction and price band are the initializer's own terms and don't bind what they accept
+    fn process_accept_counter(
         accounts: &[AccountInfo],
-        program_id: &Pubkey
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
+        let AcceptCounterAccounts {
+            initializer,
+            escrow_account,
+            pdas_temp_token_account,
+            pda_account,
+            initializers_token_to_receive_account,
+            counter_account,
+            counter_vault,
+            taker,
+            takers_token_to_receive_account,
+            deposit_mint,
+            token_program,
+            expected_mint,
+            expected_token_program,
+            treasury_account,
+            additional_accounts,
+            mut escrow_info,
+            counter_info,
+            config,
+        } = AcceptCounterAccounts::validate(accounts, program_id)?;
+
+        let vault_balance = Self::vault_balance(&escrow_info, pdas_temp_token_account)?;
+        let signer_seeds: &[&[u8]] = &[ESCROW_PDA_SEED, escrow_account.key.as_ref(), &[escrow_info.bump_seed]];
+        let counter_signer_seeds: &[&[u8]] = &[COUNTER_PDA_SEED, escrow_account.key.as_ref(), taker.key.as_ref(), &[counter_info.bump_seed]];
+
+        Self::check_exchange_window(escrow_info.clock_mode, escrow_info.unlock_time, escrow_info.time_out)?;
 
-        let initializer = next_account_info(account_info_iter)?;
-        if !initializer.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        // the escrow may have been partly taken since the counter-offer was made. one for more than is left
+        // is stale and stays in open_counters until its taker withdraws it
+        let fill_amount = counter_info.fill_amount;
+        let listed_payment = escrow_info.payment_for(fill_amount).ok_or(EscrowError::InvalidFillAmount)?;
+        if vault_balance < escrow_info.deposit_amount {
+            return Err(EscrowError::ExpectedAmountMismatch.into());
         }
+        let is_full_fill = fill_amount == escrow_info.deposit_amount;
+
+        // anything sent to the counter-offer's vault on top of its payment goes to the initializer with it
+        let payment = unpack_token_account(counter_vault)?.amount;
+        let fee = match config {
+            Some(config) => config.fee_for(payment).ok_or(EscrowError::AmountOverflow)?,
+            None => 0,
+        };
+        msg!("Accepting {} for {} of {} deposited, {} to the initializer and {} fee to the treasury", payment, fill_amount, escrow_info.deposit_amount, payment - fee, fee);
 
-        let escrow_account = next_account_info(account_info_iter)?;
-        if escrow_account.owner != program_id || escrow_account.is_writable == false {
-            return Err(ProgramError::IllegalOwner);
+        Self::release_deposit(AssetKind::Token, payment - fee, counter_vault, initializers_token_to_receive_account, counter_account, expected_mint, expected_token_program, counter_signer_seeds, additional_accounts)?;
+        if fee > 0 {
+            Self::release_deposit(AssetKind::Token, fee, counter_vault, treasury_account, counter_account, expected_mint, expected_token_program, counter_signer_seeds, additional_accounts)?;
         }
+        Self::close_vault(AssetKind::Token, counter_vault, taker, counter_account, expected_mint, expected_token_program, counter_signer_seeds)?;
+        Self::close_escrow_account(counter_account, taker)?;
+
+        let release_amount = if is_full_fill && escrow_info.deposit_kind == AssetKind::Token { vault_balance } else { fill_amount };
+        Self::release_deposit(escrow_info.deposit_kind, release_amount, pdas_temp_token_account, takers_token_to_receive_account, pda_account, deposit_mint, token_program, signer_seeds, additional_accounts)?;
 
-        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        // what is left keeps the escrow's own price, not the counter-offer's
+        match escrow_info.auction {
+            Some(auction) => {
+                let auction = auction.after_fill(fill_amount, escrow_info.deposit_amount).ok_or(EscrowError::AmountOverflow)?;
+                escrow_info.expected_amount = auction.start_price;
+                escrow_info.auction = Some(auction);
+            },
+            None => escrow_info.expected_amount -= listed_payment,
+        }
+        escrow_info.deposit_amount -= fill_amount;
+        escrow_info.open_counters = escrow_info.open_counters.saturating_sub(1);
+        EscrowEvent::CounterAccepted {
+            escrow: *escrow_account.key,
+            initializer: *initializer.key,
+            taker: *taker.key,
+            counter: *counter_account.key,
+            fill_amount,
+            payment,
+            fee,
+            remaining_deposit: escrow_info.deposit_amount,
+            remaining_expected: escrow_info.expected_amount,
+            open_counters: escrow_info.open_counters,
+        }.emit();
 
-        if escrow_info.initializer_pubkey != *initializer.key {
-            return Err(ProgramError::InvalidAccountData);
+        if !is_full_fill {
+            Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+            return Ok(());
         }
 
-        // resets the unlock_time and time_out
+        Self::close_vault(escrow_info.deposit_kind, pdas_temp_token_account, initializer, pda_account, deposit_mint, token_program, signer_seeds)?;
+
+        msg!("Closing the escrow account...");
+        Self::close_escrow_account(escrow_account, initializer)
+    }
+
+    fn process_withdraw_counter(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let WithdrawCounterAccounts {
+            taker,
+            counter_account,
+            counter_vault,
+            takers_refund_token_account,
+            escrow_account,
+            expected_mint,
+            expected_token_program,
+            additional_accounts,
+            counter_info,
+            escrow_info,
+        } = WithdrawCounterAccounts::validate(accounts, program_id)?;
+
+        let refund_amount = unpack_token_account(counter_vault)?.amount;
+        let counter_signer_seeds: &[&[u8]] = &[COUNTER_PDA_SEED, escrow_account.key.as_ref(), taker.key.as_ref(), &[counter_info.bump_seed]];
+
+        msg!("Returning {} to the taker...", refund_amount);
+        Self::release_deposit(AssetKind::Token, refund_amount, counter_vault, takers_refund_token_account, counter_account, expected_mint, expected_token_program, counter_signer_seeds, additional_accounts)?;
+        Self::close_vault(AssetKind::Token, counter_vault, taker, counter_account, expected_mint, expected_token_program, counter_signer_seeds)?;
+        Self::close_escrow_account(counter_account, taker)?;
+
+        let open_counters = match escrow_info {
+            Some(mut escrow_info) => {
+                escrow_info.open_counters = escrow_info.open_counters.saturating_sub(1);
+                let open_counters = escrow_info.open_counters;
+                Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+                open_counters
+            },
+            None => 0,
+        };
+        EscrowEvent::CounterWithdrawn {
+            escrow: *escrow_account.key,
+            taker: *taker.key,
+            counter: *counter_account.key,
+            refund_amount,
+            open_counters,
+        }.emit();
+
+        Ok(())
+    }
+
+    // an escrow can be taken from unlock_time up to and including time_out
+    // returns the current time in the escrow's clock mode
+    fn check_exchange_window(clock_mode: ClockMode, unlock_time: u64, time_out: u64) -> Result<u64, ProgramError> {
+        let now = clock_mode.now(&Clock::get()?);
+
+        if now < unlock_time {
+            msg!("Escrow unlocks at {} {}, current {} is {}", clock_mode, unlock_time, clock_mode, now);
+            return Err(match clock_mode {
+                ClockMode::Slot => EscrowError::EscrowUnlockTime,
+                ClockMode::UnixTimestamp => EscrowError::EscrowUnlockTimestamp,
+            }.into());
+        }
+        Self::check_not_timed_out(clock_mode, time_out)?;
+        Ok(now)
+    }
+
+    fn check_not_timed_out(clock_mode: ClockMode, time_out: u64) -> ProgramResult {
+        let now = clock_mode.now(&Clock::get()?);
+
+        if now > time_out {
+            msg!("Escrow timed out at {} {}, current {} is {}", clock_mode, time_out, clock_mode, now);
+            return Err(match clock_mode {
+                ClockMode::Slot => EscrowError::EscrowTimeout,
+                ClockMode::UnixTimestamp => EscrowError::EscrowTimestampTimeout,
+            }.into());
+        }
+        Ok(())
+    }
+
+    fn check_price_band(price_band: &PriceBand, price_feed: &AccountInfo) -> ProgramResult {
+        let feed = PriceFeed::unpack(&price_feed.try_borrow_data()?)?;
         let clock = Clock::get()?;
-        escrow_info.unlock_time = clock.slot + 100;
-        escrow_info.time_out = escrow_info.unlock_time + 1000;
 
+        if feed.is_stale(&clock) {
+            msg!("Price feed was published at slot {}, current slot is {}", feed.publish_slot, clock.slot);
+            return Err(EscrowError::StalePriceFeed.into());
+        }
+        if !price_band.contains(feed.price) {
+            msg!("Reference price {} is outside {}..={}", feed.price, price_band.min_price, price_band.max_price);
+            return Err(EscrowError::PriceOutOfBand.into());
+        }
+        Ok(())
+    }
+
+    // what is left in the vault: the token balance, or for a native deposit the tracked lamports
+    fn vault_balance(escrow_info: &Escrow, vault: &AccountInfo) -> Result<u64, ProgramError> {
+        match escrow_info.deposit_kind {
+            AssetKind::Token => {
+                let vault_info = unpack_token_account(vault)?;
+                if vault_info.mint != escrow_info.deposit_mint {
+                    return Err(EscrowError::DepositMintMismatch.into());
+                }
+                Ok(vault_info.amount)
+            },
+            AssetKind::Native => Ok(escrow_info.deposit_amount),
+        }
+    }
+
+    // what the taker's receive account holds of the deposit: its token balance, or a wallet's lamports
+    fn receive_balance(deposit_kind: AssetKind, receive_account: &AccountInfo) -> Result<u64, ProgramError> {
+        match deposit_kind {
+            AssetKind::Token => Ok(unpack_token_account(receive_account)?.amount),
+            AssetKind::Native => Ok(receive_account.lamports()),
+        }
+    }
+
+    // moves part of the taker's payment, by token transfer or by system transfer for a native leg.
+    // a token payment is grossed up by the mint's transfer fee so `destination` receives `amount`
+    #[allow(clippy::too_many_arguments)]
+    fn transfer_payment<'a>(
+        expected_kind: AssetKind,
+        amount: u64,
+        takers_sending_account: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        taker: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        system_program_account: &AccountInfo<'a>,
+        additional_accounts: &[AccountInfo<'a>],
+    ) -> ProgramResult {
+        match expected_kind {
+            AssetKind::Token => {
+                let gross_amount = Self::amount_with_transfer_fee(mint, amount)?;
+                msg!("Calling the token program to transfer {} of the taker's tokens...", gross_amount);
+                Self::transfer_tokens(gross_amount, takers_sending_account, destination, taker, mint, token_program, &[], additional_accounts)
+            },
+            AssetKind::Native => {
+                let transfer_ix = system_instruction::transfer(taker.key, destination.key, amount);
+                msg!("Calling the system program to transfer the taker's lamports...");
+                invoke(
+                    &transfer_ix,
+                    &[
+                        taker.clone(),
+                        destination.clone(),
+                        system_program_account.clone(),
+                    ],
+                )
+            },
+        }
+    }
+
+    // moves `amount` out of the vault, signing as the escrow's PDA for token vaults
+    #[allow(clippy::too_many_arguments)]
+    fn release_deposit<'a>(
+        deposit_kind: AssetKind,
+        amount: u64,
+        vault: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        signer_seeds: &[&[u8]],
+        additional_accounts: &[AccountInfo<'a>],
+    ) -> ProgramResult {
+        match deposit_kind {
+            AssetKind::Token => {
+                msg!("Calling the token program to transfer tokens out of the vault...");
+                Self::transfer_tokens(amount, vault, destination, pda_account, mint, token_program, &[signer_seeds], additional_accounts)
+            },
+            AssetKind::Native => {
+                // the vault is the program-owned escrow account, so its lamports can be moved directly
+                **vault.try_borrow_mut_lamports()? = vault
+                    .lamports()
+                    .checked_sub(amount)
+                    .ok_or(EscrowError::AmountOverflow)?;
+                **destination.try_borrow_mut_lamports()? = destination
+                    .lamports()
+                    .checked_add(amount)
+                    .ok_or(EscrowError::AmountOverflow)?;
+                Ok(())
+            },
+        }
+    }
+
+    // closes an emptied token vault; a lamport vault is closed along with the escrow account
+    #[allow(clippy::too_many_arguments)]
+    fn close_vault<'a>(
+        deposit_kind: AssetKind,
+        vault: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        signer_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        if deposit_kind == AssetKind::Native {
+            return Ok(());
+        }
+
+        // a Token-2022 account can't be closed while it holds withheld transfer fees, sweep them to the mint first
+        let withheld_amount = {
+            let vault_data = vault.try_borrow_data()?;
+            let vault_info = StateWithExtensions::<TokenAccount>::unpack(&vault_data)?;
+            vault_info
+                .get_extension::<TransferFeeAmount>()
+                .map(|transfer_fee_amount| u64::from(transfer_fee_amount.withheld_amount))
+                .unwrap_or(0)
+        };
+        if withheld_amount > 0 {
+            let harvest_ix = harvest_withheld_tokens_to_mint(token_program.key, mint.key, &[vault.key])?;
+            msg!("Calling the token program to harvest {} withheld fees from pda's temp account...", withheld_amount);
+            invoke(
+                &harvest_ix,
+                &[
+                    mint.clone(),
+                    vault.clone(),
+                    token_program.clone(),
+                ],
+            )?;
+        }
+
+        let close_vault_ix = spl_token_2022::instruction::close_account(
+            token_program.key,
+            vault.key,
+            destination.key,
+            pda_account.key,
+            &[pda_account.key],
+        )?;
+        msg!("Calling the token program to close pda's temp account...");
+        invoke_signed(
+            &close_vault_ix,
+            &[
+                vault.clone(),
+                destination.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[signer_seeds],
+        )
+    }
+
+    // tops the PDA up to rent exemption, then allocates and assigns it. unlike create_account this doesn't
+    // fail when someone has already sent lamports to the address
+    fn create_pda_account<'a>(
+        payer: &AccountInfo<'a>,
+        pda: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        space: usize,
+        signer_seeds: &[&[u8]],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let shortfall = Rent::get()?.minimum_balance(space).saturating_sub(pda.lamports());
+        if shortfall > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, pda.key, shortfall),
+                &[payer.clone(), pda.clone(), system_program.clone()],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(pda.key, space as u64),
+            &[pda.clone(), system_program.clone()],
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(pda.key, program_id),
+            &[pda.clone(), system_program.clone()],
+            &[signer_seeds],
+        )
+    }
+
+    // transfer_checked under either token program, resolving transfer hook accounts from `additional_accounts`
+    #[allow(clippy::too_many_arguments)]
+    fn transfer_tokens<'a>(
+        amount: u64,
+        source: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        authority: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        signer_seeds: &[&[&[u8]]],
+        additional_accounts: &[AccountInfo<'a>],
+    ) -> ProgramResult {
+        let decimals = StateWithExtensions::<Mint>::unpack(&mint.try_borrow_data()?)?.base.decimals;
+        invoke_transfer_checked(
+            token_program.key,
+            source.clone(),
+            mint.clone(),
+            destination.clone(),
+            authority.clone(),
+            additional_accounts,
+            amount,
+            decimals,
+            signer_seeds,
+        )
+    }
+
+    // what has to be sent so that `amount` arrives after the mint's transfer fee, if it has one
+    fn amount_with_transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64, ProgramError> {
+        let mint_data = mint.try_borrow_data()?;
+        let mint_info = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+        let fee = match mint_info.get_extension::<TransferFeeConfig>() {
+            Ok(transfer_fee_config) => transfer_fee_config
+                .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+                .ok_or(EscrowError::AmountOverflow)?,
+            Err(_) => 0,
+        };
+        amount.checked_add(fee).ok_or_else(|| EscrowError::AmountOverflow.into())
+    }
+
+    fn close_escrow_account(escrow_account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+        **destination.try_borrow_mut_lamports()? = destination
+            .lamports()
+            .checked_add(escrow_account.lamports())
+            .ok_or(EscrowError::AmountOverflow)?;
+        **escrow_account.try_borrow_mut_lamports()? = 0;
+        *escrow_account.try_borrow_mut_data()? = &mut [];
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,10 +1,155 @@
+use std::{convert::TryFrom, fmt};
 use solana_program::{
+    bpf_loader_upgradeable,
     pubkey::Pubkey,
     program_error::ProgramError,
-    program_pack::{IsInitialized, Pack, Sealed}
+    program_pack::{IsInitialized, Pack, Sealed},
+    clock::Clock,
 };
 
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+pub const ESCROW_PDA_SEED: &[u8] = b"escrow";
+pub const COUNTER_PDA_SEED: &[u8] = b"counter";
+
+// bounds for the time lock chosen in InitEscrow, in slots (~400ms each)
+pub const MAX_UNLOCK_DELAY_SLOTS: u64 = 30 * 216_000;
+pub const MIN_TIMEOUT_WINDOW_SLOTS: u64 = 150;
+pub const MAX_TIMEOUT_WINDOW_SLOTS: u64 = 30 * 216_000;
+
+// the same bounds in seconds, for escrows locked on the unix timestamp
+pub const MAX_UNLOCK_DELAY_SECONDS: u64 = 30 * 86_400;
+pub const MIN_TIMEOUT_WINDOW_SECONDS: u64 = 60;
+pub const MAX_TIMEOUT_WINDOW_SECONDS: u64 = 30 * 86_400;
+
+// paid from the escrow account's rent to whoever cranks Expire
+pub const EXPIRE_TIP_LAMPORTS: u64 = 5_000;
+
+// the protocol fee is charged in basis points of each payment and can never exceed 10%
+pub const CONFIG_PDA_SEED: &[u8] = b"config";
+pub const MAX_FEE_BPS: u16 = 1_000;
+pub const FEE_BPS_DENOMINATOR: u64 = 10_000;
+
+// most deposit legs and most payment legs a basket escrow can hold
+pub const MAX_BASKET_LEGS: usize = 4;
+
+// a price feed published more than this many slots ago can't settle an oracle-gated escrow
+pub const MAX_PRICE_STALENESS_SLOTS: u64 = 25;
+
+// which Clock field unlock_time and time_out are measured against
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum ClockMode {
+    Slot,
+    UnixTimestamp,
+}
+
+impl ClockMode {
+    pub fn now(&self, clock: &Clock) -> u64 {
+        match self {
+            ClockMode::Slot => clock.slot,
+            // timestamps before the epoch never occur on chain
+            ClockMode::UnixTimestamp => u64::try_from(clock.unix_timestamp).unwrap_or(0),
+        }
+    }
+
+    // (max unlock delay, min timeout window, max timeout window)
+    pub fn time_lock_bounds(&self) -> (u64, u64, u64) {
+        match self {
+            ClockMode::Slot => (MAX_UNLOCK_DELAY_SLOTS, MIN_TIMEOUT_WINDOW_SLOTS, MAX_TIMEOUT_WINDOW_SLOTS),
+            ClockMode::UnixTimestamp => (MAX_UNLOCK_DELAY_SECONDS, MIN_TIMEOUT_WINDOW_SECONDS, MAX_TIMEOUT_WINDOW_SECONDS),
+        }
+    }
+}
+
+impl TryFrom<u8> for ClockMode {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ClockMode::Slot),
+            1 => Ok(ClockMode::UnixTimestamp),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+// what a leg of the escrow is paid in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    // an SPL token, held in a token account owned by the escrow's PDA
+    Token,
+    // lamports, held by the program-owned escrow account itself on the deposit side
+    Native,
+}
+
+impl TryFrom<u8> for AssetKind {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AssetKind::Token),
+            1 => Ok(AssetKind::Native),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl fmt::Display for ClockMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClockMode::Slot => write!(f, "slot"),
+            ClockMode::UnixTimestamp => write!(f, "unix timestamp"),
+        }
+    }
+}
+
+// a dutch auction prices the remaining deposit at start_price at unlock_time, falling linearly to end_price at time_out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DutchAuction {
+    pub start_price: u64,
+    pub end_price: u64,
+}
+
+impl DutchAuction {
+    // the price only ever falls, rounded up in the initializer's favour
+    pub fn price_at(&self, now: u64, unlock_time: u64, time_out: u64) -> u64 {
+        if now <= unlock_time || time_out <= unlock_time {
+            return self.start_price;
+        }
+        if now >= time_out {
+            return self.end_price;
+        }
+        let drop = (self.start_price - self.end_price) as u128 * (now - unlock_time) as u128 / (time_out - unlock_time) as u128;
+        self.start_price - drop as u64
+    }
+
+    // both prices scaled to what is left of `deposit_amount` after taking `fill` of it
+    pub fn after_fill(&self, fill: u64, deposit_amount: u64) -> Option<Self> {
+        let remaining = deposit_amount.checked_sub(fill)?;
+        let scale = |price: u64| u64::try_from((price as u128 * remaining as u128).div_ceil(deposit_amount as u128)).ok();
+        Some(DutchAuction {
+            start_price: scale(self.start_price)?,
+            end_price: scale(self.end_price)?,
+        })
+    }
+}
+
+// an oracle-gated escrow only settles while the price published to `oracle` is within min_price..=max_price
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceBand {
+    pub oracle: Pubkey,
+    pub min_price: u64,
+    pub max_price: u64,
+}
+
+impl PriceBand {
+    pub const LEN: usize = 48;
+
+    pub fn contains(&self, price: u64) -> bool {
+        (self.min_price..=self.max_price).contains(&price)
+    }
+}
 
 pub struct Escrow {
     pub is_initialized: bool,
@@ -14,6 +159,69 @@ pub struct Escrow {
     pub expected_amount: u64,
     pub unlock_time: u64,
     pub time_out: u64,
+    pub bump_seed: u8,
+    pub clock_mode: ClockMode,
+    // deposit still held in the vault, expected_amount is what is still owed for it
+    pub deposit_amount: u64,
+    pub deposit_mint: Pubkey,
+    pub expected_mint: Pubkey,
+    // where Expire returns the deposit
+    pub refund_token_account_pubkey: Pubkey,
+    pub deposit_kind: AssetKind,
+    pub expected_kind: AssetKind,
+    // when set, the only key allowed to take the escrow
+    pub allowed_taker: Option<Pubkey>,
+    // when set, expected_amount is the auction's start price and the taker pays the current price instead
+    pub auction: Option<DutchAuction>,
+    // when set, Exchange reads the oracle's PriceFeed and refuses to settle outside the band
+    pub price_band: Option<PriceBand>,
+    // counter-offers proposed against the escrow and neither accepted nor withdrawn yet
+    pub open_counters: u32,
+}
+
+impl Escrow {
+    // the vault authority is derived per escrow so one PDA never controls more than one deposit
+    pub fn find_pda(escrow_pubkey: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[ESCROW_PDA_SEED, escrow_pubkey.as_ref()], program_id)
+    }
+
+    pub fn create_pda(escrow_pubkey: &Pubkey, bump_seed: u8, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Pubkey::create_program_address(&[ESCROW_PDA_SEED, escrow_pubkey.as_ref(), &[bump_seed]], program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)
+    }
+
+    // the vault InitEscrowWithVault creates, the PDA's associated token account for the deposit mint
+    pub fn find_vault(escrow_pubkey: &Pubkey, deposit_mint: &Pubkey, token_program: &Pubkey, program_id: &Pubkey) -> Pubkey {
+        let (pda, _bump_seed) = Self::find_pda(escrow_pubkey, program_id);
+        spl_associated_token_account::get_associated_token_address_with_program_id(&pda, deposit_mint, token_program)
+    }
+
+    // pro-rata price of taking `fill` of the remaining deposit, rounded up in the initializer's favour.
+    // for an auction this is the start price, see payment_at
+    pub fn payment_for(&self, fill: u64) -> Option<u64> {
+        self.prorate(fill, self.expected_amount)
+    }
+
+    // what the whole remaining deposit costs at `now`, in the escrow's clock mode
+    pub fn price_at(&self, now: u64) -> u64 {
+        match self.auction {
+            Some(auction) => auction.price_at(now, self.unlock_time, self.time_out),
+            None => self.expected_amount,
+        }
+    }
+
+    // pro-rata price of taking `fill` of the remaining deposit at `now`
+    pub fn payment_at(&self, fill: u64, now: u64) -> Option<u64> {
+        self.prorate(fill, self.price_at(now))
+    }
+
+    fn prorate(&self, fill: u64, price: u64) -> Option<u64> {
+        if fill == 0 || fill > self.deposit_amount {
+            return None;
+        }
+        let numerator = (fill as u128).checked_mul(price as u128)?;
+        u64::try_from(numerator.div_ceil(self.deposit_amount as u128)).ok()
+    }
 }
 
 impl Sealed for Escrow {}
@@ -25,7 +233,7 @@ impl IsInitialized for Escrow {
 }
 
 impl Pack for Escrow {
-    const LEN: usize = 121;
+    const LEN: usize = 332;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, Escrow::LEN];
         let (
@@ -36,7 +244,19 @@ impl Pack for Escrow {
             expected_amount,
             unlock_time,
             time_out,
-        ) = array_refs![src, 1, 32, 32, 32, 8, 8, 8];
+            bump_seed,
+            clock_mode,
+            deposit_amount,
+            deposit_mint,
+            expected_mint,
+            refund_token_account_pubkey,
+            deposit_kind,
+            expected_kind,
+            allowed_taker,
+            auction,
+            price_band,
+            open_counters,
+        ) = array_refs![src, 1, 32, 32, 32, 8, 8, 8, 1, 1, 8, 32, 32, 32, 1, 1, 33, 17, 49, 4];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
@@ -51,6 +271,18 @@ impl Pack for Escrow {
             expected_amount: u64::from_le_bytes(*expected_amount),
             unlock_time: u64::from_le_bytes(*unlock_time),
             time_out: u64::from_le_bytes(*time_out),
+            bump_seed: bump_seed[0],
+            clock_mode: ClockMode::try_from(clock_mode[0])?,
+            deposit_amount: u64::from_le_bytes(*deposit_amount),
+            deposit_mint: Pubkey::new_from_array(*deposit_mint),
+            expected_mint: Pubkey::new_from_array(*expected_mint),
+            refund_token_account_pubkey: Pubkey::new_from_array(*refund_token_account_pubkey),
+            deposit_kind: AssetKind::try_from(deposit_kind[0])?,
+            expected_kind: AssetKind::try_from(expected_kind[0])?,
+            allowed_taker: unpack_option_pubkey(allowed_taker)?,
+            auction: unpack_option_auction(auction)?,
+            price_band: unpack_option_price_band(price_band)?,
+            open_counters: u32::from_le_bytes(*open_counters),
         })
     }
 
@@ -64,7 +296,19 @@ impl Pack for Escrow {
             expected_amount_dst,
             unlock_time_dst,
             time_out_dst,
-        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8, 8];
+            bump_seed_dst,
+            clock_mode_dst,
+            deposit_amount_dst,
+            deposit_mint_dst,
+            expected_mint_dst,
+            refund_token_account_pubkey_dst,
+            deposit_kind_dst,
+            expected_kind_dst,
+            allowed_taker_dst,
+            auction_dst,
+            price_band_dst,
+            open_counters_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8, 8, 1, 1, 8, 32, 32, 32, 1, 1, 33, 17, 49, 4];
 
         let Escrow {
             is_initialized,
@@ -73,7 +317,19 @@ impl Pack for Escrow {
             initializer_token_to_receive_account_pubkey,
             expected_amount,
             unlock_time,
-            time_out
+            time_out,
+            bump_seed,
+            clock_mode,
+            deposit_amount,
+            deposit_mint,
+            expected_mint,
+            refund_token_account_pubkey,
+            deposit_kind,
+            expected_kind,
+            allowed_taker,
+            auction,
+            price_band,
+            open_counters,
         } = self;
 
         is_initialized_dst[0] = *is_initialized as u8;
@@ -83,5 +339,461 @@ impl Pack for Escrow {
         *expected_amount_dst = expected_amount.to_le_bytes();
         *unlock_time_dst = unlock_time.to_le_bytes();
         *time_out_dst = time_out.to_le_bytes();
+        bump_seed_dst[0] = *bump_seed;
+        clock_mode_dst[0] = *clock_mode as u8;
+        *deposit_amount_dst = deposit_amount.to_le_bytes();
+        deposit_mint_dst.copy_from_slice(deposit_mint.as_ref());
+        expected_mint_dst.copy_from_slice(expected_mint.as_ref());
+        refund_token_account_pubkey_dst.copy_from_slice(refund_token_account_pubkey.as_ref());
+        deposit_kind_dst[0] = *deposit_kind as u8;
+        expected_kind_dst[0] = *expected_kind as u8;
+        pack_option_pubkey(allowed_taker, allowed_taker_dst);
+        pack_option_auction(auction, auction_dst);
+        pack_option_price_band(price_band, price_band_dst);
+        *open_counters_dst = open_counters.to_le_bytes();
+    }
+}
+
+// an optional key is a 0 or 1 tag followed by the key, zeroed when absent
+fn unpack_option_pubkey(src: &[u8; 33]) -> Result<Option<Pubkey>, ProgramError> {
+    let (tag, key) = array_refs![src, 1, 32];
+    match tag {
+        [0] => Ok(None),
+        [1] => Ok(Some(Pubkey::new_from_array(*key))),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+fn pack_option_pubkey(src: &Option<Pubkey>, dst: &mut [u8; 33]) {
+    let (tag_dst, key_dst) = mut_array_refs![dst, 1, 32];
+    match src {
+        Some(key) => {
+            tag_dst[0] = 1;
+            key_dst.copy_from_slice(key.as_ref());
+        },
+        None => {
+            tag_dst[0] = 0;
+            key_dst.fill(0);
+        },
+    }
+}
+
+// an optional auction is a 0 or 1 tag followed by the start and end price, zeroed when absent
+fn unpack_option_auction(src: &[u8; 17]) -> Result<Option<DutchAuction>, ProgramError> {
+    let (tag, start_price, end_price) = array_refs![src, 1, 8, 8];
+    match tag {
+        [0] => Ok(None),
+        [1] => Ok(Some(DutchAuction {
+            start_price: u64::from_le_bytes(*start_price),
+            end_price: u64::from_le_bytes(*end_price),
+        })),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+fn pack_option_auction(src: &Option<DutchAuction>, dst: &mut [u8; 17]) {
+    let (tag_dst, start_price_dst, end_price_dst) = mut_array_refs![dst, 1, 8, 8];
+    match src {
+        Some(auction) => {
+            tag_dst[0] = 1;
+            *start_price_dst = auction.start_price.to_le_bytes();
+            *end_price_dst = auction.end_price.to_le_bytes();
+        },
+        None => {
+            tag_dst[0] = 0;
+            start_price_dst.fill(0);
+            end_price_dst.fill(0);
+        },
+    }
+}
+
+// an optional price band is a 0 or 1 tag followed by the oracle, min and max price, zeroed when absent
+fn unpack_option_price_band(src: &[u8; 49]) -> Result<Option<PriceBand>, ProgramError> {
+    let (tag, oracle, min_price, max_price) = array_refs![src, 1, 32, 8, 8];
+    match tag {
+        [0] => Ok(None),
+        [1] => Ok(Some(PriceBand {
+            oracle: Pubkey::new_from_array(*oracle),
+            min_price: u64::from_le_bytes(*min_price),
+            max_price: u64::from_le_bytes(*max_price),
+        })),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+fn pack_option_price_band(src: &Option<PriceBand>, dst: &mut [u8; 49]) {
+    let (tag_dst, oracle_dst, min_price_dst, max_price_dst) = mut_array_refs![dst, 1, 32, 8, 8];
+    match src {
+        Some(price_band) => {
+            tag_dst[0] = 1;
+            oracle_dst.copy_from_slice(price_band.oracle.as_ref());
+            *min_price_dst = price_band.min_price.to_le_bytes();
+            *max_price_dst = price_band.max_price.to_le_bytes();
+        },
+        None => {
+            tag_dst[0] = 0;
+            oracle_dst.fill(0);
+            min_price_dst.fill(0);
+            max_price_dst.fill(0);
+        },
+    }
+}
+
+// the account an oracle publishes its reference price to. the escrow only reads it, so any program
+// writing this layout can gate escrows, and tests can set one directly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceFeed {
+    pub is_initialized: bool,
+    pub price: u64,
+    pub publish_slot: u64,
+}
+
+impl PriceFeed {
+    // a price published after the current slot can't be trusted either
+    pub fn is_stale(&self, clock: &Clock) -> bool {
+        self.publish_slot > clock.slot || clock.slot - self.publish_slot > MAX_PRICE_STALENESS_SLOTS
+    }
+}
+
+impl Sealed for PriceFeed {}
+
+impl IsInitialized for PriceFeed {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for PriceFeed {
+    const LEN: usize = 17;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, PriceFeed::LEN];
+        let (is_initialized, price, publish_slot) = array_refs![src, 1, 8, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(PriceFeed {
+            is_initialized,
+            price: u64::from_le_bytes(*price),
+            publish_slot: u64::from_le_bytes(*publish_slot),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, PriceFeed::LEN];
+        let (is_initialized_dst, price_dst, publish_slot_dst) = mut_array_refs![dst, 1, 8, 8];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        *price_dst = self.price.to_le_bytes();
+        *publish_slot_dst = self.publish_slot.to_le_bytes();
+    }
+}
+
+// a taker's proposal to take fill_amount of an escrow's deposit for a payment of their choosing.
+// the counter account is the taker's PDA for the escrow and also owns the vault locking the payment
+pub struct CounterOffer {
+    pub is_initialized: bool,
+    pub escrow_pubkey: Pubkey,
+    pub taker_pubkey: Pubkey,
+    pub vault_pubkey: Pubkey,
+    // where AcceptCounter sends the deposit
+    pub takers_token_to_receive_account_pubkey: Pubkey,
+    pub fill_amount: u64,
+    // what arrived in the vault, after any transfer fee
+    pub payment: u64,
+    pub bump_seed: u8,
+}
+
+impl CounterOffer {
+    // one counter per taker and escrow, proposing again means withdrawing first
+    pub fn find_pda(escrow_pubkey: &Pubkey, taker_pubkey: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[COUNTER_PDA_SEED, escrow_pubkey.as_ref(), taker_pubkey.as_ref()], program_id)
+    }
+
+    // the counter PDA's associated token account for the escrow's expected mint
+    pub fn find_vault(escrow_pubkey: &Pubkey, taker_pubkey: &Pubkey, expected_mint: &Pubkey, token_program: &Pubkey, program_id: &Pubkey) -> Pubkey {
+        let (counter, _bump_seed) = Self::find_pda(escrow_pubkey, taker_pubkey, program_id);
+        spl_associated_token_account::get_associated_token_address_with_program_id(&counter, expected_mint, token_program)
+    }
+}
+
+impl Sealed for CounterOffer {}
+
+impl IsInitialized for CounterOffer {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for CounterOffer {
+    const LEN: usize = 146;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, CounterOffer::LEN];
+        let (is_initialized, escrow_pubkey, taker_pubkey, vault_pubkey, takers_token_to_receive_account_pubkey, fill_amount, payment, bump_seed) =
+            array_refs![src, 1, 32, 32, 32, 32, 8, 8, 1];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(CounterOffer {
+            is_initialized,
+            escrow_pubkey: Pubkey::new_from_array(*escrow_pubkey),
+            taker_pubkey: Pubkey::new_from_array(*taker_pubkey),
+            vault_pubkey: Pubkey::new_from_array(*vault_pubkey),
+            takers_token_to_receive_account_pubkey: Pubkey::new_from_array(*takers_token_to_receive_account_pubkey),
+            fill_amount: u64::from_le_bytes(*fill_amount),
+            payment: u64::from_le_bytes(*payment),
+            bump_seed: bump_seed[0],
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, CounterOffer::LEN];
+        let (
+            is_initialized_dst,
+            escrow_pubkey_dst,
+            taker_pubkey_dst,
+            vault_pubkey_dst,
+            takers_token_to_receive_account_pubkey_dst,
+            fill_amount_dst,
+            payment_dst,
+            bump_seed_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 8, 8, 1];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        escrow_pubkey_dst.copy_from_slice(self.escrow_pubkey.as_ref());
+        taker_pubkey_dst.copy_from_slice(self.taker_pubkey.as_ref());
+        vault_pubkey_dst.copy_from_slice(self.vault_pubkey.as_ref());
+        takers_token_to_receive_account_pubkey_dst.copy_from_slice(self.takers_token_to_receive_account_pubkey.as_ref());
+        *fill_amount_dst = self.fill_amount.to_le_bytes();
+        *payment_dst = self.payment.to_le_bytes();
+        bump_seed_dst[0] = self.bump_seed;
+    }
+}
+
+// program wide settings, held in the single PDA at Config::find_pda
+pub struct Config {
+    pub is_initialized: bool,
+    // may update the config
+    pub admin_pubkey: Pubkey,
+    // fees go to this owner's associated token account, or its wallet for native payments
+    pub treasury_owner_pubkey: Pubkey,
+    pub fee_bps: u16,
+    pub bump_seed: u8,
+}
+
+impl Config {
+    pub fn find_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[CONFIG_PDA_SEED], program_id)
+    }
+
+    // the upgradeable loader's account holding the program's upgrade authority, who alone may create the config
+    pub fn find_program_data(program_id: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+    }
+
+    // the fee is rounded down so the initializer is never charged more than fee_bps
+    pub fn fee_for(&self, payment: u64) -> Option<u64> {
+        let fee = (payment as u128).checked_mul(self.fee_bps as u128)? / FEE_BPS_DENOMINATOR as u128;
+        u64::try_from(fee).ok()
+    }
+
+    // where the fee on a payment in the escrow's expected asset is sent, token_program owns the expected mint
+    pub fn treasury_account(&self, escrow: &Escrow, token_program: &Pubkey) -> Pubkey {
+        match escrow.expected_kind {
+            AssetKind::Token => spl_associated_token_account::get_associated_token_address_with_program_id(
+                &self.treasury_owner_pubkey,
+                &escrow.expected_mint,
+                token_program,
+            ),
+            AssetKind::Native => self.treasury_owner_pubkey,
+        }
+    }
+}
+
+impl Sealed for Config {}
+
+impl IsInitialized for Config {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Config {
+    const LEN: usize = 68;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Config::LEN];
+        let (is_initialized, admin_pubkey, treasury_owner_pubkey, fee_bps, bump_seed) =
+            array_refs![src, 1, 32, 32, 2, 1];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(Config {
+            is_initialized,
+            admin_pubkey: Pubkey::new_from_array(*admin_pubkey),
+            treasury_owner_pubkey: Pubkey::new_from_array(*treasury_owner_pubkey),
+            fee_bps: u16::from_le_bytes(*fee_bps),
+            bump_seed: bump_seed[0],
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Config::LEN];
+        let (is_initialized_dst, admin_pubkey_dst, treasury_owner_pubkey_dst, fee_bps_dst, bump_seed_dst) =
+            mut_array_refs![dst, 1, 32, 32, 2, 1];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        admin_pubkey_dst.copy_from_slice(self.admin_pubkey.as_ref());
+        treasury_owner_pubkey_dst.copy_from_slice(self.treasury_owner_pubkey.as_ref());
+        *fee_bps_dst = self.fee_bps.to_le_bytes();
+        bump_seed_dst[0] = self.bump_seed;
+    }
+}
+
+// one side of a basket trade: a deposit vault, or the initializer's account receiving a payment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BasketLeg {
+    pub token_account_pubkey: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+impl BasketLeg {
+    pub const LEN: usize = 72;
+
+    fn unpack_from_slice(src: &[u8]) -> Self {
+        let src = array_ref![src, 0, BasketLeg::LEN];
+        let (token_account_pubkey, mint, amount) = array_refs![src, 32, 32, 8];
+        BasketLeg {
+            token_account_pubkey: Pubkey::new_from_array(*token_account_pubkey),
+            mint: Pubkey::new_from_array(*mint),
+            amount: u64::from_le_bytes(*amount),
+        }
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, BasketLeg::LEN];
+        let (token_account_pubkey_dst, mint_dst, amount_dst) = mut_array_refs![dst, 32, 32, 8];
+        token_account_pubkey_dst.copy_from_slice(self.token_account_pubkey.as_ref());
+        mint_dst.copy_from_slice(self.mint.as_ref());
+        *amount_dst = self.amount.to_le_bytes();
+    }
+}
+
+// an escrow of several token deposits for several token payments, settled all at once.
+// the account is sized for its leg counts, see BasketEscrow::len
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasketEscrow {
+    pub is_initialized: bool,
+    pub initializer_pubkey: Pubkey,
+    pub unlock_time: u64,
+    pub time_out: u64,
+    pub bump_seed: u8,
+    pub clock_mode: ClockMode,
+    pub deposits: Vec<BasketLeg>,
+    pub payments: Vec<BasketLeg>,
+}
+
+impl BasketEscrow {
+    pub const HEADER_LEN: usize = 53;
+
+    pub fn len(deposit_count: usize, payment_count: usize) -> usize {
+        Self::HEADER_LEN + BasketLeg::LEN * (deposit_count + payment_count)
+    }
+
+    pub fn valid_leg_count(count: usize) -> bool {
+        (1..=MAX_BASKET_LEGS).contains(&count)
+    }
+
+    // the leg counts are read from the header and must account for the whole buffer
+    pub fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        let header = src.get(..Self::HEADER_LEN).ok_or(ProgramError::InvalidAccountData)?;
+        let header = array_ref![header, 0, BasketEscrow::HEADER_LEN];
+        let (
+            is_initialized,
+            initializer_pubkey,
+            unlock_time,
+            time_out,
+            bump_seed,
+            clock_mode,
+            deposit_count,
+            payment_count,
+        ) = array_refs![header, 1, 32, 8, 8, 1, 1, 1, 1];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let (deposit_count, payment_count) = (deposit_count[0] as usize, payment_count[0] as usize);
+        if src.len() != Self::len(deposit_count, payment_count) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut legs = src[Self::HEADER_LEN..]
+            .chunks_exact(BasketLeg::LEN)
+            .map(BasketLeg::unpack_from_slice);
+        let deposits = legs.by_ref().take(deposit_count).collect();
+        let payments = legs.collect();
+
+        Ok(BasketEscrow {
+            is_initialized,
+            initializer_pubkey: Pubkey::new_from_array(*initializer_pubkey),
+            unlock_time: u64::from_le_bytes(*unlock_time),
+            time_out: u64::from_le_bytes(*time_out),
+            bump_seed: bump_seed[0],
+            clock_mode: ClockMode::try_from(clock_mode[0])?,
+            deposits,
+            payments,
+        })
+    }
+
+    pub fn pack(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() != Self::len(self.deposits.len(), self.payments.len()) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (header, legs) = dst.split_at_mut(Self::HEADER_LEN);
+        let header = array_mut_ref![header, 0, BasketEscrow::HEADER_LEN];
+        let (
+            is_initialized_dst,
+            initializer_pubkey_dst,
+            unlock_time_dst,
+            time_out_dst,
+            bump_seed_dst,
+            clock_mode_dst,
+            deposit_count_dst,
+            payment_count_dst,
+        ) = mut_array_refs![header, 1, 32, 8, 8, 1, 1, 1, 1];
+
+        is_initialized_dst[0] = self.is_initialized as u8;
+        initializer_pubkey_dst.copy_from_slice(self.initializer_pubkey.as_ref());
+        *unlock_time_dst = self.unlock_time.to_le_bytes();
+        *time_out_dst = self.time_out.to_le_bytes();
+        bump_seed_dst[0] = self.bump_seed;
+        clock_mode_dst[0] = self.clock_mode as u8;
+        deposit_count_dst[0] = self.deposits.len() as u8;
+        payment_count_dst[0] = self.payments.len() as u8;
+
+        for (leg, leg_dst) in self.deposits.iter().chain(&self.payments).zip(legs.chunks_exact_mut(BasketLeg::LEN)) {
+            leg.pack_into_slice(leg_dst);
+        }
+        Ok(())
+    }
+
+    // an account created with the right size but not yet initialized, as InitBasket expects it
+    pub fn is_uninitialized(src: &[u8]) -> bool {
+        src.iter().all(|byte| *byte == 0)
+    }
+}
+
+impl IsInitialized for BasketEscrow {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
     }
 }
@@ -0,0 +1,169 @@
+use paulx_escrow_contract::{
+    cpi,
+    processor::Processor,
+    state::{AssetKind, ClockMode, Escrow},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+};
+use solana_program_test::{processor, tokio, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+const VAULT_SEED: &[u8] = b"vault";
+const DEPOSIT: u64 = 50;
+const EXPECTED: u64 = 30;
+
+// a program holding tokens under its PDA, which opens and cancels escrows through the cpi module
+fn process_vault(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let escrow_program = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let (_authority, bump_seed) = Pubkey::find_program_address(&[VAULT_SEED], program_id);
+    let signer_seeds: &[&[&[u8]]] = &[&[VAULT_SEED, &[bump_seed]]];
+
+    if instruction_data[0] == 0 {
+        let accounts = cpi::InitEscrow {
+            initializer: authority.clone(),
+            temp_token_account: next_account_info(account_info_iter)?.clone(),
+            token_to_receive_account: next_account_info(account_info_iter)?.clone(),
+            escrow_account: next_account_info(account_info_iter)?.clone(),
+            rent: next_account_info(account_info_iter)?.clone(),
+            token_program: next_account_info(account_info_iter)?.clone(),
+            refund_token_account: next_account_info(account_info_iter)?.clone(),
+        };
+        cpi::init_escrow(escrow_program, &accounts, EXPECTED, 0, 1000, ClockMode::Slot, AssetKind::Token, AssetKind::Token, None, None, None, signer_seeds)
+    } else {
+        let accounts = cpi::Cancel {
+            initializer: authority.clone(),
+            pdas_temp_token_account: next_account_info(account_info_iter)?.clone(),
+            initializers_sent_token_account: next_account_info(account_info_iter)?.clone(),
+            escrow_account: next_account_info(account_info_iter)?.clone(),
+            token_program: next_account_info(account_info_iter)?.clone(),
+            pda: next_account_info(account_info_iter)?.clone(),
+            deposit_mint: next_account_info(account_info_iter)?.clone(),
+        };
+        cpi::cancel(escrow_program, &accounts, signer_seeds)
+    }
+}
+
+async fn process(context: &mut ProgramTestContext, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(instructions, Some(&context.payer.pubkey()), &all_signers, blockhash);
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|err| err.unwrap())
+}
+
+async fn create_mint(context: &mut ProgramTestContext) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    process(
+        context,
+        &[
+            system_instruction::create_account(&payer, &mint.pubkey(), rent.minimum_balance(Mint::LEN), Mint::LEN as u64, &spl_token::id()),
+            spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), &payer, None, 0).unwrap(),
+        ],
+        &[&mint],
+    )
+    .await
+    .unwrap();
+    mint.pubkey()
+}
+
+async fn create_token_account(context: &mut ProgramTestContext, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+    let account = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    let mut instructions = vec![
+        system_instruction::create_account(&payer, &account.pubkey(), rent.minimum_balance(TokenAccount::LEN), TokenAccount::LEN as u64, &spl_token::id()),
+        spl_token::instruction::initialize_account(&spl_token::id(), &account.pubkey(), mint, owner).unwrap(),
+    ];
+    if amount > 0 {
+        instructions.push(spl_token::instruction::mint_to(&spl_token::id(), mint, &account.pubkey(), &payer, &[], amount).unwrap());
+    }
+    process(context, &instructions, &[&account]).await.unwrap();
+    account.pubkey()
+}
+
+#[tokio::test]
+async fn test_cpi_init_and_cancel() {
+    let escrow_program_id = Pubkey::new_unique();
+    let vault_program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("paulx_escrow_contract", escrow_program_id, processor!(Processor::process));
+    program_test.add_program("vault_program", vault_program_id, processor!(process_vault));
+    let mut context = program_test.start_with_context().await;
+
+    let (authority, _bump_seed) = Pubkey::find_program_address(&[VAULT_SEED], &vault_program_id);
+    let mint_x = create_mint(&mut context).await;
+    let mint_y = create_mint(&mut context).await;
+    let temp = create_token_account(&mut context, &mint_x, &authority, DEPOSIT).await;
+    let receive = create_token_account(&mut context, &mint_y, &authority, 0).await;
+    let refund = create_token_account(&mut context, &mint_x, &authority, 0).await;
+
+    let escrow = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    let open = Instruction {
+        program_id: vault_program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(escrow_program_id, false),
+            AccountMeta::new_readonly(authority, false),
+            AccountMeta::new(temp, false),
+            AccountMeta::new_readonly(receive, false),
+            AccountMeta::new(escrow.pubkey(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(refund, false),
+        ],
+        data: vec![0],
+    };
+    process(
+        &mut context,
+        &[
+            system_instruction::create_account(&payer, &escrow.pubkey(), rent.minimum_balance(Escrow::LEN), Escrow::LEN as u64, &escrow_program_id),
+            open,
+        ],
+        &[&escrow],
+    )
+    .await
+    .unwrap();
+
+    let escrow_info = Escrow::unpack(&context.banks_client.get_account(escrow.pubkey()).await.unwrap().unwrap().data).unwrap();
+    assert_eq!(escrow_info.initializer_pubkey, authority);
+    assert_eq!(escrow_info.expected_amount, EXPECTED);
+
+    // the vault program signs for its PDA again to take the deposit back
+    let cancel = Instruction {
+        program_id: vault_program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(escrow_program_id, false),
+            AccountMeta::new(authority, false),
+            AccountMeta::new(temp, false),
+            AccountMeta::new(refund, false),
+            AccountMeta::new(escrow.pubkey(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(Escrow::find_pda(&escrow.pubkey(), &escrow_program_id).0, false),
+            AccountMeta::new(mint_x, false),
+        ],
+        data: vec![1],
+    };
+    process(&mut context, &[cancel], &[]).await.unwrap();
+
+    let refund_account = context.banks_client.get_account(refund).await.unwrap().unwrap();
+    assert_eq!(TokenAccount::unpack(&refund_account.data).unwrap().amount, DEPOSIT);
+    assert!(context.banks_client.get_account(escrow.pubkey()).await.unwrap().is_none());
+}
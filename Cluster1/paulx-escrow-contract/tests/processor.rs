@@ -0,0 +1,1695 @@
+use paulx_escrow_contract::{
+    error::EscrowError,
+    instruction::{self, EscrowInstruction},
+    processor::Processor,
+    state::{AssetKind, BasketEscrow, ClockMode, Config, CounterOffer, Escrow, PriceBand, PriceFeed, EXPIRE_TIP_LAMPORTS, MAX_PRICE_STALENESS_SLOTS},
+};
+use solana_program_test::{processor, tokio, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    bpf_loader_upgradeable,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id, instruction::create_associated_token_account,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+use spl_token_2022::{
+    extension::{
+        transfer_fee::{instruction::initialize_transfer_fee_config, TransferFeeConfig},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    state::{Account as Token2022Account, Mint as Token2022Mint},
+};
+
+const UNLOCK_DELAY: u64 = 100;
+const TIMEOUT_WINDOW: u64 = 1000;
+const DEPOSIT: u64 = 50;
+const EXPECTED: u64 = 30;
+const NATIVE_AMOUNT: u64 = 500_000_000;
+const FUNDING: u64 = 1_000_000_000;
+
+struct Env {
+    context: ProgramTestContext,
+    program_id: Pubkey,
+    alice: Keypair,
+    bob: Keypair,
+    mint_x: Pubkey,
+    mint_y: Pubkey,
+    alice_x: Pubkey,
+    alice_y: Pubkey,
+    bob_x: Pubkey,
+    bob_y: Pubkey,
+    // owner of the fee treasury, fees in Y go to its associated token account
+    treasury: Pubkey,
+    treasury_y: Pubkey,
+}
+
+struct OpenEscrow {
+    escrow: Pubkey,
+    vault: Pubkey,
+    unlock_time: u64,
+    time_out: u64,
+}
+
+async fn process(context: &mut ProgramTestContext, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(instructions, Some(&context.payer.pubkey()), &all_signers, blockhash);
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|err| err.unwrap())
+}
+
+async fn get_account(context: &mut ProgramTestContext, pubkey: &Pubkey) -> Option<Account> {
+    context.banks_client.get_account(*pubkey).await.unwrap()
+}
+
+// the price Quote returns, simulated without landing a transaction
+async fn quote(context: &mut ProgramTestContext, program_id: &Pubkey, escrow: &Pubkey, amount: u64) -> u64 {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let ix = instruction::quote(program_id, escrow, amount);
+    let transaction = Transaction::new_signed_with_payer(&[ix], Some(&context.payer.pubkey()), &[&context.payer], blockhash);
+    let simulation = context.banks_client.simulate_transaction(transaction).await.unwrap();
+    let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+    assert_eq!(return_data.program_id, *program_id);
+    u64::from_le_bytes(return_data.data.try_into().unwrap())
+}
+
+async fn token_balance(context: &mut ProgramTestContext, pubkey: &Pubkey) -> u64 {
+    let account = get_account(context, pubkey).await.unwrap();
+    TokenAccount::unpack(&account.data).unwrap().amount
+}
+
+async fn lamports(context: &mut ProgramTestContext, pubkey: &Pubkey) -> u64 {
+    get_account(context, pubkey).await.map(|account| account.lamports).unwrap_or(0)
+}
+
+async fn fund(context: &mut ProgramTestContext, pubkey: &Pubkey) {
+    let payer = context.payer.pubkey();
+    process(context, &[system_instruction::transfer(&payer, pubkey, FUNDING)], &[]).await.unwrap();
+}
+
+async fn create_mint(context: &mut ProgramTestContext) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    process(
+        context,
+        &[
+            system_instruction::create_account(&payer, &mint.pubkey(), rent.minimum_balance(Mint::LEN), Mint::LEN as u64, &spl_token::id()),
+            spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), &payer, None, 0).unwrap(),
+        ],
+        &[&mint],
+    )
+    .await
+    .unwrap();
+    mint.pubkey()
+}
+
+fn create_token_account_instructions(payer: &Pubkey, account: &Pubkey, mint: &Pubkey, owner: &Pubkey, lamports: u64) -> Vec<Instruction> {
+    vec![
+        system_instruction::create_account(payer, account, lamports, TokenAccount::LEN as u64, &spl_token::id()),
+        spl_token::instruction::initialize_account(&spl_token::id(), account, mint, owner).unwrap(),
+    ]
+}
+
+async fn create_token_account(context: &mut ProgramTestContext, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+    let account = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    let mut instructions = create_token_account_instructions(&payer, &account.pubkey(), mint, owner, rent.minimum_balance(TokenAccount::LEN));
+    if amount > 0 {
+        instructions.push(spl_token::instruction::mint_to(&spl_token::id(), mint, &account.pubkey(), &payer, &[], amount).unwrap());
+    }
+    process(context, &instructions, &[&account]).await.unwrap();
+    account.pubkey()
+}
+
+async fn setup() -> Env {
+    let program_id = Pubkey::new_unique();
+    let alice = Keypair::new();
+    let bob = Keypair::new();
+    let mut program_test = ProgramTest::new("paulx_escrow_contract", program_id, processor!(Processor::process));
+    // alice deployed the program, so she is the one who can create its config
+    program_test.add_account(Config::find_program_data(&program_id), program_data_account(&alice.pubkey()));
+    let mut context = program_test.start_with_context().await;
+
+    let mint_x = create_mint(&mut context).await;
+    let mint_y = create_mint(&mut context).await;
+    let alice_x = create_token_account(&mut context, &mint_x, &alice.pubkey(), DEPOSIT).await;
+    let alice_y = create_token_account(&mut context, &mint_y, &alice.pubkey(), 0).await;
+    let bob_x = create_token_account(&mut context, &mint_x, &bob.pubkey(), 0).await;
+    let bob_y = create_token_account(&mut context, &mint_y, &bob.pubkey(), EXPECTED).await;
+
+    let treasury = Pubkey::new_unique();
+    let treasury_y = get_associated_token_address(&treasury, &mint_y);
+
+    Env { context, program_id, alice, bob, mint_x, mint_y, alice_x, alice_y, bob_x, bob_y, treasury, treasury_y }
+}
+
+// the upgradeable loader's ProgramData header: the variant tag, the deployment slot and the upgrade authority
+fn program_data_account(upgrade_authority: &Pubkey) -> Account {
+    let mut data = vec![3, 0, 0, 0];
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(1);
+    data.extend_from_slice(upgrade_authority.as_ref());
+    Account { lamports: Rent::default().minimum_balance(data.len()), data, owner: bpf_loader_upgradeable::id(), executable: false, rent_epoch: 0 }
+}
+
+async fn init_escrow(env: &mut Env) -> OpenEscrow {
+    init_escrow_for(env, None).await
+}
+
+// creates and funds alice's temp account and opens the escrow over it in one transaction
+async fn init_escrow_for(env: &mut Env, allowed_taker: Option<Pubkey>) -> OpenEscrow {
+    let vault = Keypair::new();
+    let escrow = Keypair::new();
+    let instructions = init_escrow_instructions(env, &vault.pubkey(), &escrow.pubkey(), allowed_taker).await;
+    let alice_keypair = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice_keypair, &vault, &escrow]).await.unwrap();
+
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &escrow.pubkey()).await.unwrap().data).unwrap();
+    OpenEscrow { escrow: escrow.pubkey(), vault: vault.pubkey(), unlock_time: escrow_info.unlock_time, time_out: escrow_info.time_out }
+}
+
+async fn init_escrow_instructions(env: &mut Env, vault: &Pubkey, escrow: &Pubkey, allowed_taker: Option<Pubkey>) -> Vec<Instruction> {
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let payer = env.context.payer.pubkey();
+    let alice = env.alice.pubkey();
+
+    let mut instructions = create_token_account_instructions(&payer, vault, &env.mint_x, &alice, rent.minimum_balance(TokenAccount::LEN));
+    instructions.push(spl_token::instruction::transfer(&spl_token::id(), &env.alice_x, vault, &alice, &[], DEPOSIT).unwrap());
+    instructions.push(system_instruction::create_account(&payer, escrow, rent.minimum_balance(Escrow::LEN), Escrow::LEN as u64, &env.program_id));
+    instructions.push(instruction::init_escrow(
+        &env.program_id,
+        &alice,
+        vault,
+        &env.alice_y,
+        escrow,
+        &env.alice_x,
+        &spl_token::id(),
+        EXPECTED,
+        UNLOCK_DELAY,
+        TIMEOUT_WINDOW,
+        ClockMode::Slot,
+        AssetKind::Token,
+        AssetKind::Token,
+        allowed_taker,
+        None,
+        None,
+    ));
+    instructions
+}
+
+// creates the escrow account and has the program create the vault and move alice's deposit into it
+async fn init_escrow_with_vault_instructions(env: &mut Env, escrow: &Pubkey) -> Vec<Instruction> {
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let payer = env.context.payer.pubkey();
+    vec![
+        system_instruction::create_account(&payer, escrow, rent.minimum_balance(Escrow::LEN), Escrow::LEN as u64, &env.program_id),
+        instruction::init_escrow_with_vault(
+            &env.program_id,
+            &env.alice.pubkey(),
+            &env.alice_x,
+            &env.alice_y,
+            escrow,
+            &env.alice_x,
+            &env.mint_x,
+            &spl_token::id(),
+            DEPOSIT,
+            EXPECTED,
+            UNLOCK_DELAY,
+            TIMEOUT_WINDOW,
+            ClockMode::Slot,
+            AssetKind::Token,
+            None,
+            None,
+            None,
+        ),
+    ]
+}
+
+fn exchange_instruction(env: &Env, open: &OpenEscrow, amount: u64) -> Instruction {
+    instruction::exchange(
+        &env.program_id,
+        &env.bob.pubkey(),
+        &env.bob_y,
+        &env.bob_x,
+        &open.vault,
+        &env.alice.pubkey(),
+        &env.alice_y,
+        &open.escrow,
+        &env.treasury_y,
+        &env.mint_x,
+        &env.mint_y,
+        &spl_token::id(),
+        &spl_token::id(),
+        amount,
+        EXPECTED,
+        amount,
+    )
+}
+
+async fn exchange(env: &mut Env, open: &OpenEscrow, amount: u64) -> Result<(), TransactionError> {
+    let ix = exchange_instruction(env, open, amount);
+    let bob = env.bob.insecure_clone();
+    process(&mut env.context, &[ix], &[&bob]).await
+}
+
+fn custom_error(error: EscrowError) -> TransactionError {
+    TransactionError::InstructionError(0, custom(error))
+}
+
+fn custom(error: EscrowError) -> InstructionError {
+    InstructionError::Custom(error as u32)
+}
+
+// the account at `slot` of `ix` swapped for `pubkey`, keeping the slot's signer and writable flags
+fn spoof(ix: &Instruction, slot: usize, pubkey: Pubkey) -> (usize, AccountMeta) {
+    (slot, AccountMeta { pubkey, ..ix.accounts[slot].clone() })
+}
+
+fn unsigned(ix: &Instruction, slot: usize) -> (usize, AccountMeta) {
+    (slot, AccountMeta { is_signer: false, ..ix.accounts[slot].clone() })
+}
+
+fn readonly(ix: &Instruction, slot: usize) -> (usize, AccountMeta) {
+    (slot, AccountMeta { is_writable: false, ..ix.accounts[slot].clone() })
+}
+
+// a copy of the account under a new key and owned by another program, holding state the escrow program never wrote
+async fn spoofed_copy(context: &mut ProgramTestContext, pubkey: &Pubkey) -> Pubkey {
+    let mut account = get_account(context, pubkey).await.unwrap();
+    account.owner = Pubkey::new_unique();
+    let spoofed = Pubkey::new_unique();
+    context.set_account(&spoofed, &account.into());
+    spoofed
+}
+
+// sends `instructions` once per case with one account of the last instruction replaced, each case has to
+// fail on that instruction with its own error. only the keypairs still signing in the case are passed
+async fn assert_spoofed(context: &mut ProgramTestContext, instructions: &[Instruction], signers: &[&Keypair], cases: Vec<((usize, AccountMeta), InstructionError)>) {
+    let index = instructions.len() - 1;
+    for ((slot, meta), error) in cases {
+        let mut spoofed = instructions.to_vec();
+        spoofed[index].accounts[slot] = meta;
+        let signers: Vec<&Keypair> = signers
+            .iter()
+            .copied()
+            .filter(|signer| spoofed.iter().flat_map(|ix| &ix.accounts).any(|account| account.is_signer && account.pubkey == signer.pubkey()))
+            .collect();
+        assert_eq!(
+            process(context, &spoofed, &signers).await.unwrap_err(),
+            TransactionError::InstructionError(index as u8, error),
+            "slot {}",
+            slot
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_init_escrow() {
+    let mut env = setup().await;
+    let start_slot = env.context.banks_client.get_root_slot().await.unwrap();
+    let open = init_escrow(&mut env).await;
+
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &open.escrow).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.initializer_pubkey, env.alice.pubkey());
+    assert_eq!(escrow_info.temp_token_account_pubkey, open.vault);
+    assert_eq!(escrow_info.initializer_token_to_receive_account_pubkey, env.alice_y);
+    assert_eq!(escrow_info.expected_amount, EXPECTED);
+    assert_eq!(escrow_info.deposit_amount, DEPOSIT);
+    assert_eq!(escrow_info.deposit_mint, env.mint_x);
+    assert_eq!(escrow_info.expected_mint, env.mint_y);
+    assert!(escrow_info.unlock_time >= start_slot + UNLOCK_DELAY);
+    assert_eq!(escrow_info.time_out, escrow_info.unlock_time + TIMEOUT_WINDOW);
+
+    let vault = TokenAccount::unpack(&get_account(&mut env.context, &open.vault).await.unwrap().data).unwrap();
+    assert_eq!(vault.owner, Escrow::find_pda(&open.escrow, &env.program_id).0);
+    assert_eq!(vault.amount, DEPOSIT);
+    assert_eq!(token_balance(&mut env.context, &env.alice_x).await, 0);
+}
+
+#[tokio::test]
+async fn test_init_escrow_with_vault() {
+    let mut env = setup().await;
+    // alice pays the vault's rent
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    let escrow = Keypair::new();
+    let vault = Escrow::find_vault(&escrow.pubkey(), &env.mint_x, &spl_token::id(), &env.program_id);
+
+    // someone creating the vault first and sending tokens to it neither blocks the escrow nor adds to the deposit
+    let payer = env.context.payer.pubkey();
+    let pda = Escrow::find_pda(&escrow.pubkey(), &env.program_id).0;
+    let instructions = [
+        create_associated_token_account(&payer, &pda, &env.mint_x, &spl_token::id()),
+        spl_token::instruction::mint_to(&spl_token::id(), &env.mint_x, &vault, &payer, &[], 5).unwrap(),
+    ];
+    process(&mut env.context, &instructions, &[]).await.unwrap();
+
+    let instructions = init_escrow_with_vault_instructions(&mut env, &escrow.pubkey()).await;
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice, &escrow]).await.unwrap();
+
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &escrow.pubkey()).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.temp_token_account_pubkey, vault);
+    assert_eq!(escrow_info.deposit_amount, DEPOSIT);
+    assert_eq!(escrow_info.deposit_mint, env.mint_x);
+    assert_eq!(escrow_info.expected_amount, EXPECTED);
+    assert_eq!(escrow_info.refund_token_account_pubkey, env.alice_x);
+    let vault_info = TokenAccount::unpack(&get_account(&mut env.context, &vault).await.unwrap().data).unwrap();
+    assert_eq!(vault_info.owner, pda);
+    assert_eq!(vault_info.amount, DEPOSIT + 5);
+    assert_eq!(token_balance(&mut env.context, &env.alice_x).await, 0);
+
+    // from there on it is an ordinary escrow, a full fill sweeps the extra tokens too
+    let open = OpenEscrow { escrow: escrow.pubkey(), vault, unlock_time: escrow_info.unlock_time, time_out: escrow_info.time_out };
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+    exchange(&mut env, &open, DEPOSIT).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT + 5);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, EXPECTED);
+    assert!(get_account(&mut env.context, &vault).await.is_none());
+}
+
+#[tokio::test]
+async fn test_init_escrow_with_vault_spoofed_accounts() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    let escrow = Keypair::new();
+    let instructions = init_escrow_with_vault_instructions(&mut env, &escrow.pubkey()).await;
+    let ix = instructions.last().unwrap();
+    let cases = vec![
+        (unsigned(ix, 0), InstructionError::MissingRequiredSignature),
+        (readonly(ix, 1), InstructionError::InvalidAccountData),
+        (spoof(ix, 1, Pubkey::new_unique()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(ix, 2, Pubkey::new_unique()), custom(EscrowError::WrongTempAccount)),
+        (spoof(ix, 3, Pubkey::new_unique()), custom(EscrowError::WrongReceiveAccountOwner)),
+        (spoof(ix, 4, Pubkey::new_unique()), custom(EscrowError::NotProgramOwned)),
+        (spoof(ix, 5, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(ix, 6, env.bob_x), custom(EscrowError::WrongReceiveAccount)),
+        (spoof(ix, 7, env.mint_y), InstructionError::InvalidAccountData),
+        (spoof(ix, 8, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(ix, 9, Pubkey::new_unique()), InstructionError::IncorrectProgramId),
+        (spoof(ix, 10, Pubkey::new_unique()), InstructionError::IncorrectProgramId),
+    ];
+    let (alice, bob) = (env.alice.insecure_clone(), env.bob.insecure_clone());
+    assert_spoofed(&mut env.context, &instructions, &[&alice, &bob, &escrow], cases).await;
+    assert_eq!(token_balance(&mut env.context, &env.alice_x).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_exchange() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    let rent_locked = lamports(&mut env.context, &open.escrow).await + lamports(&mut env.context, &open.vault).await;
+    let alice_lamports = lamports(&mut env.context, &env.alice.pubkey()).await;
+
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+    exchange(&mut env, &open, DEPOSIT).await.unwrap();
+
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT);
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, 0);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, EXPECTED);
+    assert!(get_account(&mut env.context, &open.escrow).await.is_none());
+    assert!(get_account(&mut env.context, &open.vault).await.is_none());
+    assert_eq!(lamports(&mut env.context, &env.alice.pubkey()).await, alice_lamports + rent_locked);
+}
+
+#[tokio::test]
+async fn test_exchange_allowed_taker() {
+    let mut env = setup().await;
+    let bob = env.bob.pubkey();
+    let open = init_escrow_for(&mut env, Some(bob)).await;
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+
+    // carol holds the right tokens but is not the named counterparty
+    let carol = Keypair::new();
+    let carol_x = create_token_account(&mut env.context, &env.mint_x, &carol.pubkey(), 0).await;
+    let carol_y = create_token_account(&mut env.context, &env.mint_y, &carol.pubkey(), EXPECTED).await;
+    let ix = instruction::exchange(
+        &env.program_id,
+        &carol.pubkey(),
+        &carol_y,
+        &carol_x,
+        &open.vault,
+        &env.alice.pubkey(),
+        &env.alice_y,
+        &open.escrow,
+        &env.treasury_y,
+        &env.mint_x,
+        &env.mint_y,
+        &spl_token::id(),
+        &spl_token::id(),
+        DEPOSIT,
+        EXPECTED,
+        DEPOSIT,
+    );
+    assert_eq!(process(&mut env.context, &[ix], &[&carol]).await.unwrap_err(), custom_error(EscrowError::UnauthorizedTaker));
+
+    exchange(&mut env, &open, DEPOSIT).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_exchange_partial_fill() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+
+    // 20 of 50 deposited costs ceil(20 * 30 / 50) = 12
+    exchange(&mut env, &open, 20).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, 20);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, 12);
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &open.escrow).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.deposit_amount, 30);
+    assert_eq!(escrow_info.expected_amount, 18);
+    assert_eq!(token_balance(&mut env.context, &open.vault).await, 30);
+
+    assert_eq!(exchange(&mut env, &open, 31).await.unwrap_err(), custom_error(EscrowError::InvalidFillAmount));
+    assert_eq!(exchange(&mut env, &open, 0).await.unwrap_err(), custom_error(EscrowError::InvalidFillAmount));
+
+    exchange(&mut env, &open, 30).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, EXPECTED);
+    assert!(get_account(&mut env.context, &open.escrow).await.is_none());
+    assert!(get_account(&mut env.context, &open.vault).await.is_none());
+}
+
+// the init_escrow instructions for a dutch auction starting at alice's expected amount, or an oracle-gated escrow
+async fn init_priced_instructions(env: &mut Env, vault: &Pubkey, escrow: &Pubkey, auction_end_price: Option<u64>, price_band: Option<PriceBand>) -> Vec<Instruction> {
+    let mut instructions = init_escrow_instructions(env, vault, escrow, None).await;
+    instructions.last_mut().unwrap().data = EscrowInstruction::InitEscrow {
+        amount: EXPECTED,
+        unlock_delay: UNLOCK_DELAY,
+        timeout_window: TIMEOUT_WINDOW,
+        clock_mode: ClockMode::Slot,
+        deposit_kind: AssetKind::Token,
+        expected_kind: AssetKind::Token,
+        allowed_taker: None,
+        auction_end_price,
+        price_band,
+    }.pack();
+    instructions
+}
+
+#[tokio::test]
+async fn test_dutch_auction() {
+    let mut env = setup().await;
+    let (vault, escrow) = (Keypair::new(), Keypair::new());
+    let instructions = init_priced_instructions(&mut env, &vault.pubkey(), &escrow.pubkey(), Some(10), None).await;
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice, &vault, &escrow]).await.unwrap();
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &escrow.pubkey()).await.unwrap().data).unwrap();
+    let open = OpenEscrow { escrow: escrow.pubkey(), vault: vault.pubkey(), unlock_time: escrow_info.unlock_time, time_out: escrow_info.time_out };
+    let program_id = env.program_id;
+
+    // the whole deposit starts at the expected amount and loses 20 over the timeout window
+    assert_eq!(quote(&mut env.context, &program_id, &open.escrow, DEPOSIT).await, EXPECTED);
+    env.context.warp_to_slot(open.unlock_time + TIMEOUT_WINDOW / 2).unwrap();
+    assert_eq!(quote(&mut env.context, &program_id, &open.escrow, DEPOSIT).await, 20);
+    assert_eq!(quote(&mut env.context, &program_id, &open.escrow, DEPOSIT / 2).await, 10);
+
+    // the deadlines the price is drawn between can't move under a running auction
+    let ixs = [
+        instruction::reset_time_lock(&program_id, &env.alice.pubkey(), &open.escrow),
+        instruction::set_time_lock(&program_id, &env.alice.pubkey(), &open.escrow, open.unlock_time, open.time_out + 100),
+    ];
+    for ix in ixs {
+        assert_eq!(process(&mut env.context, &[ix], &[&alice]).await.unwrap_err(), custom_error(EscrowError::AuctionRunning));
+    }
+
+    let bob = env.bob.insecure_clone();
+    let mut ix = exchange_instruction(&env, &open, DEPOSIT / 2);
+    ix.data = EscrowInstruction::Exchange { amount: DEPOSIT / 2, max_payment: 10, min_receive: DEPOSIT / 2 }.pack();
+    process(&mut env.context, &[ix], &[&bob]).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED - 10);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, 10);
+
+    // the rest keeps falling at the same rate per unit of deposit
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &open.escrow).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.deposit_amount, DEPOSIT / 2);
+    assert_eq!(escrow_info.expected_amount, 15);
+    assert_eq!(quote(&mut env.context, &program_id, &open.escrow, DEPOSIT / 2).await, 10);
+    env.context.warp_to_slot(open.time_out).unwrap();
+    assert_eq!(quote(&mut env.context, &program_id, &open.escrow, DEPOSIT / 2).await, 5);
+    assert_eq!(quote(&mut env.context, &program_id, &open.escrow, 1).await, 1);
+}
+
+#[tokio::test]
+async fn test_dutch_auction_rejects_rising_price() {
+    let mut env = setup().await;
+    let (vault, escrow) = (Keypair::new(), Keypair::new());
+    let instructions = init_priced_instructions(&mut env, &vault.pubkey(), &escrow.pubkey(), Some(EXPECTED + 1), None).await;
+    let alice = env.alice.insecure_clone();
+    assert_eq!(
+        process(&mut env.context, &instructions, &[&alice, &vault, &escrow]).await.unwrap_err(),
+        TransactionError::InstructionError(instructions.len() as u8 - 1, custom(EscrowError::InvalidAuction)),
+    );
+}
+
+// writes a mock oracle's price feed, owned by some oracle program the escrow never checks
+fn set_price_feed(context: &mut ProgramTestContext, oracle: &Pubkey, price: u64, publish_slot: u64) {
+    let mut data = vec![0; PriceFeed::LEN];
+    PriceFeed { is_initialized: true, price, publish_slot }.pack_into_slice(&mut data);
+    let account = Account { lamports: Rent::default().minimum_balance(PriceFeed::LEN), data, owner: Pubkey::new_unique(), executable: false, rent_epoch: 0 };
+    context.set_account(oracle, &account.into());
+}
+
+#[tokio::test]
+async fn test_oracle_gated_exchange() {
+    let mut env = setup().await;
+    let (vault, escrow) = (Keypair::new(), Keypair::new());
+    let oracle = Pubkey::new_unique();
+    let price_band = PriceBand { oracle, min_price: 90, max_price: 110 };
+    let instructions = init_priced_instructions(&mut env, &vault.pubkey(), &escrow.pubkey(), None, Some(price_band)).await;
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice, &vault, &escrow]).await.unwrap();
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &escrow.pubkey()).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.price_band, Some(price_band));
+    let open = OpenEscrow { escrow: escrow.pubkey(), vault: vault.pubkey(), unlock_time: escrow_info.unlock_time, time_out: escrow_info.time_out };
+
+    // set_account has to come after the warp, which checks the bank's capitalization
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+    let slot = env.context.banks_client.get_root_slot().await.unwrap();
+    let bob = env.bob.insecure_clone();
+    let ix = exchange_instruction(&env, &open, DEPOSIT / 2);
+    let with_feed = |price_feed: Pubkey| {
+        let mut ix = ix.clone();
+        ix.accounts.push(AccountMeta::new_readonly(price_feed, false));
+        ix
+    };
+
+    assert_eq!(process(&mut env.context, std::slice::from_ref(&ix), &[&bob]).await.unwrap_err(), TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys));
+    let ix = with_feed(Pubkey::new_unique());
+    assert_eq!(process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(), custom_error(EscrowError::WrongOracle));
+
+    set_price_feed(&mut env.context, &oracle, 111, slot);
+    assert_eq!(process(&mut env.context, &[with_feed(oracle)], &[&bob]).await.unwrap_err(), custom_error(EscrowError::PriceOutOfBand));
+    set_price_feed(&mut env.context, &oracle, 100, slot - MAX_PRICE_STALENESS_SLOTS - 1);
+    assert_eq!(process(&mut env.context, &[with_feed(oracle)], &[&bob]).await.unwrap_err(), custom_error(EscrowError::StalePriceFeed));
+    set_price_feed(&mut env.context, &oracle, 100, slot + MAX_PRICE_STALENESS_SLOTS);
+    assert_eq!(process(&mut env.context, &[with_feed(oracle)], &[&bob]).await.unwrap_err(), custom_error(EscrowError::StalePriceFeed));
+
+    set_price_feed(&mut env.context, &oracle, 90, slot - MAX_PRICE_STALENESS_SLOTS);
+    process(&mut env.context, &[with_feed(oracle)], &[&bob]).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT / 2);
+}
+
+#[tokio::test]
+async fn test_oracle_gated_rejects_inverted_band() {
+    let mut env = setup().await;
+    let (vault, escrow) = (Keypair::new(), Keypair::new());
+    let price_band = PriceBand { oracle: Pubkey::new_unique(), min_price: 2, max_price: 1 };
+    let instructions = init_priced_instructions(&mut env, &vault.pubkey(), &escrow.pubkey(), None, Some(price_band)).await;
+    let alice = env.alice.insecure_clone();
+    assert_eq!(
+        process(&mut env.context, &instructions, &[&alice, &vault, &escrow]).await.unwrap_err(),
+        TransactionError::InstructionError(instructions.len() as u8 - 1, custom(EscrowError::InvalidPriceBand)),
+    );
+}
+
+#[tokio::test]
+async fn test_exchange_max_payment() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+    let bob = env.bob.insecure_clone();
+
+    // half the deposit costs 15 of Y, bob only accepts 14
+    let mut ix = exchange_instruction(&env, &open, DEPOSIT / 2);
+    ix.data = EscrowInstruction::Exchange { amount: DEPOSIT / 2, max_payment: 14, min_receive: DEPOSIT / 2 }.pack();
+    assert_eq!(process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(), custom_error(EscrowError::MaxPaymentExceeded));
+
+    let mut ix = exchange_instruction(&env, &open, DEPOSIT / 2);
+    ix.data = EscrowInstruction::Exchange { amount: DEPOSIT / 2, max_payment: 15, min_receive: DEPOSIT / 2 }.pack();
+    process(&mut env.context, &[ix], &[&bob]).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT / 2);
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED - 15);
+}
+
+#[tokio::test]
+async fn test_exchange_before_unlock() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+
+    env.context.warp_to_slot(open.unlock_time - 1).unwrap();
+    assert_eq!(exchange(&mut env, &open, DEPOSIT).await.unwrap_err(), custom_error(EscrowError::EscrowUnlockTime));
+    assert_eq!(token_balance(&mut env.context, &open.vault).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_exchange_at_and_after_timeout() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+
+    env.context.warp_to_slot(open.time_out).unwrap();
+    exchange(&mut env, &open, 10).await.unwrap();
+
+    env.context.warp_to_slot(open.time_out + 1).unwrap();
+    assert_eq!(exchange(&mut env, &open, 10).await.unwrap_err(), custom_error(EscrowError::EscrowTimeout));
+}
+
+#[tokio::test]
+async fn test_exchange_wrong_mint() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+
+    // bob tries to pay with a worthless token instead of Y
+    let mint_z = create_mint(&mut env.context).await;
+    let bob_z = create_token_account(&mut env.context, &mint_z, &env.bob.pubkey(), EXPECTED).await;
+    let mut ix = exchange_instruction(&env, &open, DEPOSIT);
+    ix.accounts[1].pubkey = bob_z;
+    let bob = env.bob.insecure_clone();
+    assert_eq!(
+        process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(),
+        custom_error(EscrowError::ExpectedMintMismatch)
+    );
+}
+
+#[tokio::test]
+async fn test_init_escrow_spoofed_accounts() {
+    let mut env = setup().await;
+    let vault = Keypair::new();
+    let escrow = Keypair::new();
+    let instructions = init_escrow_instructions(&mut env, &vault.pubkey(), &escrow.pubkey(), None).await;
+    let ix = instructions.last().unwrap();
+    let cases = vec![
+        (spoof(ix, 0, env.bob.pubkey()), custom(EscrowError::WrongTempAccount)),
+        (spoof(ix, 1, env.bob_x), custom(EscrowError::WrongTempAccount)),
+        (spoof(ix, 1, Pubkey::new_unique()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(ix, 2, vault.pubkey()), custom(EscrowError::DuplicateAccount)),
+        (spoof(ix, 2, Pubkey::new_unique()), custom(EscrowError::WrongReceiveAccountOwner)),
+        (spoof(ix, 3, Pubkey::new_unique()), custom(EscrowError::NotProgramOwned)),
+        (spoof(ix, 4, sysvar::clock::id()), InstructionError::InvalidArgument),
+        (spoof(ix, 5, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(ix, 6, env.bob_x), custom(EscrowError::WrongReceiveAccount)),
+        (spoof(ix, 6, env.alice_y), custom(EscrowError::DepositMintMismatch)),
+    ];
+    let (alice, bob) = (env.alice.insecure_clone(), env.bob.insecure_clone());
+    assert_spoofed(&mut env.context, &instructions, &[&alice, &bob, &vault, &escrow], cases).await;
+    assert_eq!(token_balance(&mut env.context, &env.alice_x).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_exchange_spoofed_accounts() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    init_config(&mut env, 100).await.unwrap();
+    let open = init_escrow(&mut env).await;
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+    let spoofed_escrow = spoofed_copy(&mut env.context, &open.escrow).await;
+
+    let ix = exchange_instruction(&env, &open, DEPOSIT);
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 1, open.vault), custom(EscrowError::DuplicateAccount)),
+        (spoof(&ix, 2, env.bob_y), custom(EscrowError::DepositMintMismatch)),
+        (spoof(&ix, 3, env.bob_x), custom(EscrowError::WrongTempAccount)),
+        (spoof(&ix, 4, env.bob.pubkey()), custom(EscrowError::WrongInitializer)),
+        (spoof(&ix, 5, env.bob_y), custom(EscrowError::WrongReceiveAccount)),
+        (spoof(&ix, 6, spoofed_escrow), custom(EscrowError::NotProgramOwned)),
+        (readonly(&ix, 6), InstructionError::InvalidAccountData),
+        (spoof(&ix, 7, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(&ix, 8, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(&ix, 9, Pubkey::new_unique()), InstructionError::IncorrectProgramId),
+        (spoof(&ix, 10, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(&ix, 11, env.alice_y), custom(EscrowError::WrongTreasury)),
+        (spoof(&ix, 12, env.mint_y), InstructionError::InvalidAccountData),
+        (spoof(&ix, 13, env.mint_x), InstructionError::InvalidAccountData),
+        (spoof(&ix, 14, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+    ];
+    let bob = env.bob.insecure_clone();
+    assert_spoofed(&mut env.context, &[ix], &[&bob], cases).await;
+    assert_eq!(token_balance(&mut env.context, &open.vault).await, DEPOSIT);
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED);
+}
+
+#[tokio::test]
+async fn test_cancel() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    let rent_locked = lamports(&mut env.context, &open.escrow).await + lamports(&mut env.context, &open.vault).await;
+    let alice_lamports = lamports(&mut env.context, &env.alice.pubkey()).await;
+
+    let ix = instruction::cancel(&env.program_id, &env.alice.pubkey(), &open.vault, &env.alice_x, &open.escrow, &env.mint_x, &spl_token::id());
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+
+    assert_eq!(token_balance(&mut env.context, &env.alice_x).await, DEPOSIT);
+    assert!(get_account(&mut env.context, &open.escrow).await.is_none());
+    assert!(get_account(&mut env.context, &open.vault).await.is_none());
+    assert_eq!(lamports(&mut env.context, &env.alice.pubkey()).await, alice_lamports + rent_locked);
+}
+
+#[tokio::test]
+async fn test_cancel_by_other_signer() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+
+    let ix = instruction::cancel(&env.program_id, &env.bob.pubkey(), &open.vault, &env.bob_x, &open.escrow, &env.mint_x, &spl_token::id());
+    let bob = env.bob.insecure_clone();
+    assert_eq!(process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(), custom_error(EscrowError::WrongInitializer));
+    assert_eq!(token_balance(&mut env.context, &open.vault).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_cancel_spoofed_accounts() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    let spoofed_escrow = spoofed_copy(&mut env.context, &open.escrow).await;
+
+    let ix = instruction::cancel(&env.program_id, &env.alice.pubkey(), &open.vault, &env.alice_x, &open.escrow, &env.mint_x, &spl_token::id());
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 1, env.bob_x), custom(EscrowError::WrongTempAccount)),
+        (spoof(&ix, 2, env.bob.pubkey()), custom(EscrowError::WrongInitializer)),
+        (spoof(&ix, 3, open.vault), custom(EscrowError::DuplicateAccount)),
+        (spoof(&ix, 3, env.alice_y), custom(EscrowError::DepositMintMismatch)),
+        (spoof(&ix, 4, spoofed_escrow), custom(EscrowError::NotProgramOwned)),
+        (spoof(&ix, 5, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(&ix, 6, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(&ix, 7, env.mint_y), InstructionError::InvalidAccountData),
+    ];
+    let alice = env.alice.insecure_clone();
+    assert_spoofed(&mut env.context, &[ix], &[&alice], cases).await;
+    assert_eq!(token_balance(&mut env.context, &open.vault).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_expire_spoofed_accounts() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    // set_account has to come after the warp, which checks the bank's capitalization
+    env.context.warp_to_slot(open.time_out + 1).unwrap();
+    let spoofed_escrow = spoofed_copy(&mut env.context, &open.escrow).await;
+
+    let ix = instruction::expire(&env.program_id, &env.bob.pubkey(), &open.vault, &env.alice.pubkey(), &env.alice_x, &open.escrow, &env.mint_x, &spl_token::id());
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 1, env.bob_x), custom(EscrowError::WrongTempAccount)),
+        (spoof(&ix, 2, env.bob.pubkey()), custom(EscrowError::WrongInitializer)),
+        (spoof(&ix, 3, env.bob_x), custom(EscrowError::WrongReceiveAccount)),
+        (spoof(&ix, 4, spoofed_escrow), custom(EscrowError::NotProgramOwned)),
+        (spoof(&ix, 5, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(&ix, 6, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(&ix, 7, env.mint_y), InstructionError::InvalidAccountData),
+    ];
+    let bob = env.bob.insecure_clone();
+    assert_spoofed(&mut env.context, &[ix], &[&bob], cases).await;
+    assert_eq!(token_balance(&mut env.context, &open.vault).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_time_lock_spoofed_accounts() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    let spoofed_escrow = spoofed_copy(&mut env.context, &open.escrow).await;
+
+    let ix = instruction::reset_time_lock(&env.program_id, &env.alice.pubkey(), &open.escrow);
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 0, env.bob.pubkey()), custom(EscrowError::WrongInitializer)),
+        (spoof(&ix, 1, spoofed_escrow), custom(EscrowError::NotProgramOwned)),
+        (readonly(&ix, 1), InstructionError::InvalidAccountData),
+    ];
+    let (alice, bob) = (env.alice.insecure_clone(), env.bob.insecure_clone());
+    assert_spoofed(&mut env.context, &[ix], &[&alice, &bob], cases).await;
+}
+
+#[tokio::test]
+async fn test_reset_time_lock() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+
+    let warp_slot = open.unlock_time + 10;
+    env.context.warp_to_slot(warp_slot).unwrap();
+    let ix = instruction::reset_time_lock(&env.program_id, &env.alice.pubkey(), &open.escrow);
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &open.escrow).await.unwrap().data).unwrap();
+    assert!(escrow_info.unlock_time >= warp_slot + 100);
+    assert_eq!(escrow_info.time_out, escrow_info.unlock_time + 1000);
+
+    // the escrow is locked again after the reset
+    assert_eq!(exchange(&mut env, &open, DEPOSIT).await.unwrap_err(), custom_error(EscrowError::EscrowUnlockTime));
+}
+
+#[tokio::test]
+async fn test_time_lock_updates() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    let alice = env.alice.insecure_clone();
+
+    let ix = instruction::extend_timeout(&env.program_id, &env.alice.pubkey(), &open.escrow, 500);
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+    let ix = instruction::advance_unlock(&env.program_id, &env.alice.pubkey(), &open.escrow, 50);
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &open.escrow).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.unlock_time, open.unlock_time - 50);
+    assert_eq!(escrow_info.time_out, open.time_out + 500);
+
+    // unlock may not be moved into the past or past the timeout
+    let ix = instruction::advance_unlock(&env.program_id, &env.alice.pubkey(), &open.escrow, UNLOCK_DELAY);
+    assert_eq!(process(&mut env.context, &[ix], &[&alice]).await.unwrap_err(), custom_error(EscrowError::InvalidTimeLock));
+    let ix = instruction::set_time_lock(&env.program_id, &env.alice.pubkey(), &open.escrow, open.time_out, open.time_out);
+    assert_eq!(process(&mut env.context, &[ix], &[&alice]).await.unwrap_err(), custom_error(EscrowError::InvalidTimeLock));
+
+    let ix = instruction::set_time_lock(&env.program_id, &env.alice.pubkey(), &open.escrow, open.time_out, open.time_out + 10);
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &open.escrow).await.unwrap().data).unwrap();
+    assert_eq!((escrow_info.unlock_time, escrow_info.time_out), (open.time_out, open.time_out + 10));
+}
+
+#[tokio::test]
+async fn test_expire() {
+    let mut env = setup().await;
+    let open = init_escrow(&mut env).await;
+    let rent_locked = lamports(&mut env.context, &open.escrow).await + lamports(&mut env.context, &open.vault).await;
+    let alice_lamports = lamports(&mut env.context, &env.alice.pubkey()).await;
+
+    // the cranker needs a rent exempt balance to receive the tip
+    let payer = env.context.payer.pubkey();
+    let fund_bob = system_instruction::transfer(&payer, &env.bob.pubkey(), 1_000_000_000);
+    process(&mut env.context, &[fund_bob], &[]).await.unwrap();
+    let bob_lamports = lamports(&mut env.context, &env.bob.pubkey()).await;
+
+    let ix = instruction::expire(&env.program_id, &env.bob.pubkey(), &open.vault, &env.alice.pubkey(), &env.alice_x, &open.escrow, &env.mint_x, &spl_token::id());
+    let bob = env.bob.insecure_clone();
+
+    env.context.warp_to_slot(open.time_out).unwrap();
+    assert_eq!(process(&mut env.context, std::slice::from_ref(&ix), &[&bob]).await.unwrap_err(), custom_error(EscrowError::EscrowNotExpired));
+
+    env.context.warp_to_slot(open.time_out + 1).unwrap();
+    process(&mut env.context, &[ix], &[&bob]).await.unwrap();
+
+    assert_eq!(token_balance(&mut env.context, &env.alice_x).await, DEPOSIT);
+    assert!(get_account(&mut env.context, &open.escrow).await.is_none());
+    assert!(get_account(&mut env.context, &open.vault).await.is_none());
+    assert_eq!(lamports(&mut env.context, &env.bob.pubkey()).await, bob_lamports + EXPIRE_TIP_LAMPORTS);
+    assert_eq!(lamports(&mut env.context, &env.alice.pubkey()).await, alice_lamports + rent_locked - EXPIRE_TIP_LAMPORTS);
+}
+
+#[tokio::test]
+async fn test_native_deposit() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    fund(&mut env.context, &env.bob.pubkey()).await;
+    let escrow = Keypair::new();
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let alice = env.alice.pubkey();
+
+    // alice funds the escrow account with the SOL she is offering on top of its rent
+    let instructions = [
+        system_instruction::create_account(&alice, &escrow.pubkey(), rent.minimum_balance(Escrow::LEN) + NATIVE_AMOUNT, Escrow::LEN as u64, &env.program_id),
+        instruction::init_escrow(&env.program_id, &alice, &escrow.pubkey(), &env.alice_y, &escrow.pubkey(), &alice, &spl_token::id(), EXPECTED, 0, TIMEOUT_WINDOW, ClockMode::Slot, AssetKind::Native, AssetKind::Token, None, None, None),
+    ];
+    let alice_keypair = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice_keypair, &escrow]).await.unwrap();
+
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &escrow.pubkey()).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.deposit_kind, AssetKind::Native);
+    assert_eq!(escrow_info.deposit_amount, NATIVE_AMOUNT);
+    assert_eq!(escrow_info.deposit_mint, spl_token::native_mint::id());
+    let alice_lamports = lamports(&mut env.context, &alice).await;
+    let bob_lamports = lamports(&mut env.context, &env.bob.pubkey()).await;
+
+    let exchange_ix = |amount| instruction::exchange(
+        &env.program_id,
+        &env.bob.pubkey(),
+        &env.bob_y,
+        &env.bob.pubkey(),
+        &escrow.pubkey(),
+        &alice,
+        &env.alice_y,
+        &escrow.pubkey(),
+        &env.treasury_y,
+        &spl_token::native_mint::id(),
+        &env.mint_y,
+        &spl_token::id(),
+        &spl_token::id(),
+        amount,
+        EXPECTED,
+        amount,
+    );
+    let (half, full) = (exchange_ix(NATIVE_AMOUNT / 2), exchange_ix(NATIVE_AMOUNT / 2));
+    let bob = env.bob.insecure_clone();
+    process(&mut env.context, &[half], &[&bob]).await.unwrap();
+    assert_eq!(lamports(&mut env.context, &env.bob.pubkey()).await, bob_lamports + NATIVE_AMOUNT / 2);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, EXPECTED / 2);
+
+    process(&mut env.context, &[full], &[&bob]).await.unwrap();
+    assert_eq!(lamports(&mut env.context, &env.bob.pubkey()).await, bob_lamports + NATIVE_AMOUNT);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, EXPECTED);
+    assert!(get_account(&mut env.context, &escrow.pubkey()).await.is_none());
+    assert_eq!(lamports(&mut env.context, &alice).await, alice_lamports + rent.minimum_balance(Escrow::LEN));
+}
+
+#[tokio::test]
+async fn test_native_payment() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    fund(&mut env.context, &env.bob.pubkey()).await;
+    let vault = Keypair::new();
+    let escrow = Keypair::new();
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let payer = env.context.payer.pubkey();
+    let alice = env.alice.pubkey();
+
+    // alice asks to be paid in SOL straight to her wallet
+    let mut instructions = create_token_account_instructions(&payer, &vault.pubkey(), &env.mint_x, &alice, rent.minimum_balance(TokenAccount::LEN));
+    instructions.push(spl_token::instruction::transfer(&spl_token::id(), &env.alice_x, &vault.pubkey(), &alice, &[], DEPOSIT).unwrap());
+    instructions.push(system_instruction::create_account(&payer, &escrow.pubkey(), rent.minimum_balance(Escrow::LEN), Escrow::LEN as u64, &env.program_id));
+    instructions.push(instruction::init_escrow(&env.program_id, &alice, &vault.pubkey(), &alice, &escrow.pubkey(), &env.alice_x, &spl_token::id(), NATIVE_AMOUNT, 0, TIMEOUT_WINDOW, ClockMode::Slot, AssetKind::Token, AssetKind::Native, None, None, None));
+    let alice_keypair = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice_keypair, &vault, &escrow]).await.unwrap();
+
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &escrow.pubkey()).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.expected_kind, AssetKind::Native);
+    assert_eq!(escrow_info.expected_mint, spl_token::native_mint::id());
+    let rent_locked = lamports(&mut env.context, &escrow.pubkey()).await + lamports(&mut env.context, &vault.pubkey()).await;
+    let alice_lamports = lamports(&mut env.context, &alice).await;
+    let bob_lamports = lamports(&mut env.context, &env.bob.pubkey()).await;
+
+    let ix = instruction::exchange(
+        &env.program_id,
+        &env.bob.pubkey(),
+        &env.bob.pubkey(),
+        &env.bob_x,
+        &vault.pubkey(),
+        &alice,
+        &alice,
+        &escrow.pubkey(),
+        &env.treasury,
+        &env.mint_x,
+        &spl_token::native_mint::id(),
+        &spl_token::id(),
+        &spl_token::id(),
+        DEPOSIT,
+        NATIVE_AMOUNT,
+        DEPOSIT,
+    );
+    let bob = env.bob.insecure_clone();
+    process(&mut env.context, &[ix], &[&bob]).await.unwrap();
+
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT);
+    assert_eq!(lamports(&mut env.context, &env.bob.pubkey()).await, bob_lamports - NATIVE_AMOUNT);
+    assert_eq!(lamports(&mut env.context, &alice).await, alice_lamports + NATIVE_AMOUNT + rent_locked);
+    assert!(get_account(&mut env.context, &escrow.pubkey()).await.is_none());
+    assert!(get_account(&mut env.context, &vault.pubkey()).await.is_none());
+}
+
+#[tokio::test]
+async fn test_native_cancel() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    let escrow = Keypair::new();
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let alice = env.alice.pubkey();
+
+    let instructions = [
+        system_instruction::create_account(&alice, &escrow.pubkey(), rent.minimum_balance(Escrow::LEN) + NATIVE_AMOUNT, Escrow::LEN as u64, &env.program_id),
+        instruction::init_escrow(&env.program_id, &alice, &escrow.pubkey(), &env.alice_y, &escrow.pubkey(), &alice, &spl_token::id(), EXPECTED, UNLOCK_DELAY, TIMEOUT_WINDOW, ClockMode::Slot, AssetKind::Native, AssetKind::Token, None, None, None),
+    ];
+    let alice_keypair = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice_keypair, &escrow]).await.unwrap();
+    let alice_lamports = lamports(&mut env.context, &alice).await;
+    let escrow_lamports = lamports(&mut env.context, &escrow.pubkey()).await;
+
+    let ix = instruction::cancel(&env.program_id, &alice, &escrow.pubkey(), &alice, &escrow.pubkey(), &spl_token::native_mint::id(), &spl_token::id());
+    process(&mut env.context, &[ix], &[&alice_keypair]).await.unwrap();
+
+    assert!(get_account(&mut env.context, &escrow.pubkey()).await.is_none());
+    assert_eq!(lamports(&mut env.context, &alice).await, alice_lamports + escrow_lamports);
+}
+
+struct OpenBasket {
+    basket: Pubkey,
+    // (vault, bob's account to receive, alice's refund account, mint) per deposit
+    deposits: Vec<(Pubkey, Pubkey, Pubkey, Pubkey)>,
+    // (bob's sending account, alice's account to receive, mint) per payment
+    payments: Vec<(Pubkey, Pubkey, Pubkey)>,
+    time_out: u64,
+}
+
+async fn init_basket(env: &mut Env) -> OpenBasket {
+    let (instructions, keypairs, mut open) = init_basket_instructions(env).await;
+    let alice = env.alice.insecure_clone();
+    let mut signers = vec![&alice];
+    signers.extend(&keypairs);
+    process(&mut env.context, &instructions, &signers).await.unwrap();
+
+    let basket_info = BasketEscrow::unpack(&get_account(&mut env.context, &open.basket).await.unwrap().data).unwrap();
+    open.time_out = basket_info.time_out;
+    open
+}
+
+// alice offers 50 X and 20 Z for 30 Y and 10 W, the basket and both vaults are created by the returned keypairs
+async fn init_basket_instructions(env: &mut Env) -> (Vec<Instruction>, Vec<Keypair>, OpenBasket) {
+    let mint_z = create_mint(&mut env.context).await;
+    let mint_w = create_mint(&mut env.context).await;
+    let alice = env.alice.pubkey();
+    let bob = env.bob.pubkey();
+    let alice_z = create_token_account(&mut env.context, &mint_z, &alice, 20).await;
+    let alice_w = create_token_account(&mut env.context, &mint_w, &alice, 0).await;
+    let bob_z = create_token_account(&mut env.context, &mint_z, &bob, 0).await;
+    let bob_w = create_token_account(&mut env.context, &mint_w, &bob, 10).await;
+
+    let basket = Keypair::new();
+    let vault_x = Keypair::new();
+    let vault_z = Keypair::new();
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let payer = env.context.payer.pubkey();
+    let basket_len = BasketEscrow::len(2, 2);
+
+    let mut instructions = create_token_account_instructions(&payer, &vault_x.pubkey(), &env.mint_x, &alice, rent.minimum_balance(TokenAccount::LEN));
+    instructions.extend(create_token_account_instructions(&payer, &vault_z.pubkey(), &mint_z, &alice, rent.minimum_balance(TokenAccount::LEN)));
+    instructions.push(spl_token::instruction::transfer(&spl_token::id(), &env.alice_x, &vault_x.pubkey(), &alice, &[], DEPOSIT).unwrap());
+    instructions.push(spl_token::instruction::transfer(&spl_token::id(), &alice_z, &vault_z.pubkey(), &alice, &[], 20).unwrap());
+    instructions.push(system_instruction::create_account(&payer, &basket.pubkey(), rent.minimum_balance(basket_len), basket_len as u64, &env.program_id));
+    instructions.push(instruction::init_basket(
+        &env.program_id,
+        &alice,
+        &basket.pubkey(),
+        &[vault_x.pubkey(), vault_z.pubkey()],
+        &[env.alice_y, alice_w],
+        &[EXPECTED, 10],
+        0,
+        TIMEOUT_WINDOW,
+        ClockMode::Slot,
+        &spl_token::id(),
+    ));
+
+    let open = OpenBasket {
+        basket: basket.pubkey(),
+        deposits: vec![(vault_x.pubkey(), env.bob_x, env.alice_x, env.mint_x), (vault_z.pubkey(), bob_z, alice_z, mint_z)],
+        payments: vec![(env.bob_y, env.alice_y, env.mint_y), (bob_w, alice_w, mint_w)],
+        time_out: 0,
+    };
+    (instructions, vec![basket, vault_x, vault_z], open)
+}
+
+#[tokio::test]
+async fn test_basket_exchange() {
+    let mut env = setup().await;
+    let open = init_basket(&mut env).await;
+
+    let basket_info = BasketEscrow::unpack(&get_account(&mut env.context, &open.basket).await.unwrap().data).unwrap();
+    assert_eq!(basket_info.deposits.iter().map(|leg| leg.amount).collect::<Vec<_>>(), vec![DEPOSIT, 20]);
+    assert_eq!(basket_info.payments.iter().map(|leg| leg.amount).collect::<Vec<_>>(), vec![EXPECTED, 10]);
+    let pda = Escrow::find_pda(&open.basket, &env.program_id).0;
+    for (vault, _, _, _) in &open.deposits {
+        let vault = TokenAccount::unpack(&get_account(&mut env.context, vault).await.unwrap().data).unwrap();
+        assert_eq!(vault.owner, pda);
+    }
+
+    let deposit_legs: Vec<_> = open.deposits.iter().map(|(vault, to, _, mint)| (*vault, *to, *mint)).collect();
+    let bob = env.bob.insecure_clone();
+
+    // a payment leg missing from the accounts fails the whole exchange
+    let ix = instruction::exchange_basket(&env.program_id, &env.bob.pubkey(), &open.basket, &env.alice.pubkey(), &deposit_legs, &open.payments[..1], &spl_token::id());
+    assert!(process(&mut env.context, &[ix], &[&bob]).await.is_err());
+
+    let ix = instruction::exchange_basket(&env.program_id, &env.bob.pubkey(), &open.basket, &env.alice.pubkey(), &deposit_legs, &open.payments, &spl_token::id());
+    process(&mut env.context, &[ix], &[&bob]).await.unwrap();
+
+    assert_eq!(token_balance(&mut env.context, &open.deposits[0].1).await, DEPOSIT);
+    assert_eq!(token_balance(&mut env.context, &open.deposits[1].1).await, 20);
+    assert_eq!(token_balance(&mut env.context, &open.payments[0].1).await, EXPECTED);
+    assert_eq!(token_balance(&mut env.context, &open.payments[1].1).await, 10);
+    assert!(get_account(&mut env.context, &open.basket).await.is_none());
+    for (vault, _, _, _) in &open.deposits {
+        assert!(get_account(&mut env.context, vault).await.is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_basket_exchange_after_timeout() {
+    let mut env = setup().await;
+    let open = init_basket(&mut env).await;
+
+    env.context.warp_to_slot(open.time_out + 1).unwrap();
+    let deposit_legs: Vec<_> = open.deposits.iter().map(|(vault, to, _, mint)| (*vault, *to, *mint)).collect();
+    let ix = instruction::exchange_basket(&env.program_id, &env.bob.pubkey(), &open.basket, &env.alice.pubkey(), &deposit_legs, &open.payments, &spl_token::id());
+    let bob = env.bob.insecure_clone();
+    assert_eq!(process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(), custom_error(EscrowError::EscrowTimeout));
+}
+
+#[tokio::test]
+async fn test_basket_cancel() {
+    let mut env = setup().await;
+    let open = init_basket(&mut env).await;
+    let alice = env.alice.insecure_clone();
+
+    let refund_legs: Vec<_> = open.deposits.iter().map(|(vault, _, refund, mint)| (*vault, *refund, *mint)).collect();
+    let ix = instruction::cancel_basket(&env.program_id, &env.bob.pubkey(), &open.basket, &refund_legs, &spl_token::id());
+    let bob = env.bob.insecure_clone();
+    assert!(process(&mut env.context, &[ix], &[&bob]).await.is_err());
+
+    let ix = instruction::cancel_basket(&env.program_id, &env.alice.pubkey(), &open.basket, &refund_legs, &spl_token::id());
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+
+    assert_eq!(token_balance(&mut env.context, &open.deposits[0].2).await, DEPOSIT);
+    assert_eq!(token_balance(&mut env.context, &open.deposits[1].2).await, 20);
+    assert!(get_account(&mut env.context, &open.basket).await.is_none());
+    for (vault, _, _, _) in &open.deposits {
+        assert!(get_account(&mut env.context, vault).await.is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_init_basket_spoofed_accounts() {
+    let mut env = setup().await;
+    let (instructions, keypairs, open) = init_basket_instructions(&mut env).await;
+    let (vault_x, vault_z) = (open.deposits[0].0, open.deposits[1].0);
+    let ix = instructions.last().unwrap();
+    let cases = vec![
+        (spoof(ix, 0, env.bob.pubkey()), custom(EscrowError::WrongTempAccount)),
+        (spoof(ix, 1, Pubkey::new_unique()), custom(EscrowError::NotProgramOwned)),
+        (spoof(ix, 2, sysvar::clock::id()), InstructionError::InvalidArgument),
+        (spoof(ix, 3, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(ix, 4, env.bob_x), custom(EscrowError::WrongTempAccount)),
+        (spoof(ix, 5, vault_x), custom(EscrowError::DuplicateAccount)),
+        (spoof(ix, 6, vault_z), custom(EscrowError::DuplicateAccount)),
+        (spoof(ix, 7, Pubkey::new_unique()), custom(EscrowError::WrongTokenProgram)),
+    ];
+    let (alice, bob) = (env.alice.insecure_clone(), env.bob.insecure_clone());
+    let mut signers = vec![&alice, &bob];
+    signers.extend(&keypairs);
+    assert_spoofed(&mut env.context, &instructions, &signers, cases).await;
+}
+
+#[tokio::test]
+async fn test_basket_exchange_spoofed_accounts() {
+    let mut env = setup().await;
+    let open = init_basket(&mut env).await;
+    let spoofed_basket = spoofed_copy(&mut env.context, &open.basket).await;
+    let vault_x = open.deposits[0].0;
+
+    // legs start at slot 5, three accounts each: the deposits, then the payments
+    let deposit_legs: Vec<_> = open.deposits.iter().map(|(vault, to, _, mint)| (*vault, *to, *mint)).collect();
+    let ix = instruction::exchange_basket(&env.program_id, &env.bob.pubkey(), &open.basket, &env.alice.pubkey(), &deposit_legs, &open.payments, &spl_token::id());
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 1, spoofed_basket), custom(EscrowError::NotProgramOwned)),
+        (spoof(&ix, 2, env.bob.pubkey()), custom(EscrowError::WrongInitializer)),
+        (spoof(&ix, 3, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(&ix, 4, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(&ix, 5, env.bob_x), custom(EscrowError::WrongTempAccount)),
+        (spoof(&ix, 6, vault_x), custom(EscrowError::DuplicateAccount)),
+        (spoof(&ix, 7, env.mint_y), InstructionError::InvalidAccountData),
+        (spoof(&ix, 11, env.alice_x), custom(EscrowError::ExpectedMintMismatch)),
+        (spoof(&ix, 12, env.bob_y), custom(EscrowError::WrongReceiveAccount)),
+        (spoof(&ix, 13, env.mint_x), InstructionError::InvalidAccountData),
+    ];
+    let bob = env.bob.insecure_clone();
+    assert_spoofed(&mut env.context, &[ix], &[&bob], cases).await;
+    assert_eq!(token_balance(&mut env.context, &vault_x).await, DEPOSIT);
+}
+
+#[tokio::test]
+async fn test_basket_cancel_spoofed_accounts() {
+    let mut env = setup().await;
+    let open = init_basket(&mut env).await;
+    let spoofed_basket = spoofed_copy(&mut env.context, &open.basket).await;
+    let vault_x = open.deposits[0].0;
+
+    let refund_legs: Vec<_> = open.deposits.iter().map(|(vault, _, refund, mint)| (*vault, *refund, *mint)).collect();
+    let ix = instruction::cancel_basket(&env.program_id, &env.alice.pubkey(), &open.basket, &refund_legs, &spl_token::id());
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 0, env.bob.pubkey()), custom(EscrowError::WrongInitializer)),
+        (spoof(&ix, 1, spoofed_basket), custom(EscrowError::NotProgramOwned)),
+        (spoof(&ix, 2, spl_token_2022::id()), custom(EscrowError::WrongTokenProgram)),
+        (spoof(&ix, 3, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(&ix, 4, env.bob_x), custom(EscrowError::WrongTempAccount)),
+        (spoof(&ix, 5, vault_x), custom(EscrowError::DuplicateAccount)),
+        (spoof(&ix, 6, env.mint_y), InstructionError::InvalidAccountData),
+    ];
+    let (alice, bob) = (env.alice.insecure_clone(), env.bob.insecure_clone());
+    assert_spoofed(&mut env.context, &[ix], &[&alice, &bob], cases).await;
+    assert_eq!(token_balance(&mut env.context, &vault_x).await, DEPOSIT);
+}
+
+async fn init_config(env: &mut Env, fee_bps: u16) -> Result<(), TransactionError> {
+    let payer = env.context.payer.pubkey();
+    let instructions = [
+        create_associated_token_account(&payer, &env.treasury, &env.mint_y, &spl_token::id()),
+        instruction::init_config(&env.program_id, &env.alice.pubkey(), &env.treasury, fee_bps),
+    ];
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice]).await
+}
+
+#[tokio::test]
+async fn test_exchange_with_fee() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    // 1000 bps is the most the config allows
+    assert_eq!(init_config(&mut env, 1_001).await.unwrap_err(), TransactionError::InstructionError(1, InstructionError::Custom(EscrowError::FeeTooHigh as u32)));
+    init_config(&mut env, 1_000).await.unwrap();
+
+    let config = Config::unpack(&get_account(&mut env.context, &Config::find_pda(&env.program_id).0).await.unwrap().data).unwrap();
+    assert_eq!(config.admin_pubkey, env.alice.pubkey());
+    assert_eq!(config.treasury_owner_pubkey, env.treasury);
+    assert_eq!(config.fee_bps, 1_000);
+
+    let open = init_escrow(&mut env).await;
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+
+    // a treasury other than the configured one is rejected
+    let mut ix = exchange_instruction(&env, &open, DEPOSIT);
+    ix.accounts[11].pubkey = env.alice_y;
+    let bob = env.bob.insecure_clone();
+    assert_eq!(process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(), TransactionError::InstructionError(0, custom(EscrowError::WrongTreasury)));
+
+    // bob pays the full 30, 10% of it goes to the treasury
+    exchange(&mut env, &open, DEPOSIT).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, DEPOSIT);
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, 0);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, 27);
+    assert_eq!(token_balance(&mut env.context, &env.treasury_y).await, 3);
+}
+
+#[tokio::test]
+async fn test_update_config() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    let config_pda = Config::find_pda(&env.program_id).0;
+    // lamports sent to the config address ahead of time don't stop it from being created
+    fund(&mut env.context, &config_pda).await;
+    init_config(&mut env, 30).await.unwrap();
+    assert_eq!(lamports(&mut env.context, &config_pda).await, FUNDING);
+    let alice = env.alice.insecure_clone();
+    let bob = env.bob.insecure_clone();
+
+    // only the admin can update, and creating it again fails
+    let ix = instruction::update_config(&env.program_id, &env.bob.pubkey(), &env.bob.pubkey(), &env.bob.pubkey(), 0);
+    assert!(process(&mut env.context, &[ix], &[&bob]).await.is_err());
+    let ix = instruction::init_config(&env.program_id, &env.alice.pubkey(), &env.treasury, 30);
+    assert!(process(&mut env.context, &[ix], &[&alice]).await.is_err());
+
+    // alice hands the config over to bob
+    let ix = instruction::update_config(&env.program_id, &env.alice.pubkey(), &env.bob.pubkey(), &env.bob.pubkey(), 50);
+    process(&mut env.context, &[ix], &[&alice, &bob]).await.unwrap();
+    let config = Config::unpack(&get_account(&mut env.context, &config_pda).await.unwrap().data).unwrap();
+    assert_eq!((config.admin_pubkey, config.treasury_owner_pubkey, config.fee_bps), (env.bob.pubkey(), env.bob.pubkey(), 50));
+
+    let ix = instruction::update_config(&env.program_id, &env.alice.pubkey(), &env.alice.pubkey(), &env.alice.pubkey(), 0);
+    assert!(process(&mut env.context, &[ix], &[&alice]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_config_spoofed_accounts() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    let (alice, bob) = (env.alice.insecure_clone(), env.bob.insecure_clone());
+
+    let ix = instruction::init_config(&env.program_id, &env.alice.pubkey(), &env.treasury, 30);
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 1, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (spoof(&ix, 3, Pubkey::new_unique()), InstructionError::IncorrectProgramId),
+        (spoof(&ix, 4, Pubkey::new_unique()), custom(EscrowError::NotUpgradeAuthority)),
+    ];
+    assert_spoofed(&mut env.context, &[ix], &[&alice], cases).await;
+
+    // only the upgrade authority can create the config
+    fund(&mut env.context, &env.bob.pubkey()).await;
+    let ix = instruction::init_config(&env.program_id, &env.bob.pubkey(), &env.treasury, 30);
+    assert_eq!(
+        process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(),
+        TransactionError::InstructionError(0, custom(EscrowError::NotUpgradeAuthority))
+    );
+
+    init_config(&mut env, 30).await.unwrap();
+    let ix = instruction::update_config(&env.program_id, &env.alice.pubkey(), &env.treasury, &env.alice.pubkey(), 50);
+    let cases = vec![
+        (spoof(&ix, 0, env.bob.pubkey()), InstructionError::InvalidAccountData),
+        (spoof(&ix, 1, Pubkey::new_unique()), custom(EscrowError::WrongPda)),
+        (readonly(&ix, 1), InstructionError::InvalidAccountData),
+        ((3, AccountMeta::new_readonly(env.bob.pubkey(), false)), InstructionError::MissingRequiredSignature),
+    ];
+    assert_spoofed(&mut env.context, &[ix], &[&alice, &bob], cases).await;
+}
+
+// a Token-2022 mint charging 1% on every transfer
+async fn create_fee_mint(context: &mut ProgramTestContext) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    let space = ExtensionType::try_calculate_account_len::<Token2022Mint>(&[ExtensionType::TransferFeeConfig]).unwrap();
+    process(
+        context,
+        &[
+            system_instruction::create_account(&payer, &mint.pubkey(), rent.minimum_balance(space), space as u64, &spl_token_2022::id()),
+            initialize_transfer_fee_config(&spl_token_2022::id(), &mint.pubkey(), None, Some(&payer), 100, u64::MAX).unwrap(),
+            spl_token_2022::instruction::initialize_mint2(&spl_token_2022::id(), &mint.pubkey(), &payer, None, 0).unwrap(),
+        ],
+        &[&mint],
+    )
+    .await
+    .unwrap();
+    mint.pubkey()
+}
+
+fn create_token_2022_account_instructions(payer: &Pubkey, account: &Pubkey, mint: &Pubkey, owner: &Pubkey, rent: &Rent) -> Vec<Instruction> {
+    let space = ExtensionType::try_calculate_account_len::<Token2022Account>(&[ExtensionType::TransferFeeAmount]).unwrap();
+    vec![
+        system_instruction::create_account(payer, account, rent.minimum_balance(space), space as u64, &spl_token_2022::id()),
+        spl_token_2022::instruction::initialize_account3(&spl_token_2022::id(), account, mint, owner).unwrap(),
+    ]
+}
+
+async fn create_token_2022_account(context: &mut ProgramTestContext, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+    let account = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let payer = context.payer.pubkey();
+    let mut instructions = create_token_2022_account_instructions(&payer, &account.pubkey(), mint, owner, &rent);
+    if amount > 0 {
+        instructions.push(spl_token_2022::instruction::mint_to_checked(&spl_token_2022::id(), mint, &account.pubkey(), &payer, &[], amount, 0).unwrap());
+    }
+    process(context, &instructions, &[&account]).await.unwrap();
+    account.pubkey()
+}
+
+async fn token_2022_balance(context: &mut ProgramTestContext, pubkey: &Pubkey) -> u64 {
+    let account = get_account(context, pubkey).await.unwrap();
+    StateWithExtensions::<Token2022Account>::unpack(&account.data).unwrap().base.amount
+}
+
+#[tokio::test]
+async fn test_token_2022_transfer_fee_exchange() {
+    let mut env = setup().await;
+    let fee_mint_x = create_fee_mint(&mut env.context).await;
+    let fee_mint_y = create_fee_mint(&mut env.context).await;
+    let alice = env.alice.pubkey();
+    let bob = env.bob.pubkey();
+    let alice_x = create_token_2022_account(&mut env.context, &fee_mint_x, &alice, 1_000).await;
+    let alice_y = create_token_2022_account(&mut env.context, &fee_mint_y, &alice, 0).await;
+    let bob_x = create_token_2022_account(&mut env.context, &fee_mint_x, &bob, 0).await;
+    let bob_y = create_token_2022_account(&mut env.context, &fee_mint_y, &bob, 100).await;
+
+    // 1% of alice's 1000 is withheld in the vault on the way in
+    let vault = Keypair::new();
+    let escrow = Keypair::new();
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let payer = env.context.payer.pubkey();
+    let mut instructions = create_token_2022_account_instructions(&payer, &vault.pubkey(), &fee_mint_x, &alice, &rent);
+    instructions.push(
+        spl_token_2022::instruction::transfer_checked(&spl_token_2022::id(), &alice_x, &fee_mint_x, &vault.pubkey(), &alice, &[], 1_000, 0).unwrap(),
+    );
+    instructions.push(system_instruction::create_account(&payer, &escrow.pubkey(), rent.minimum_balance(Escrow::LEN), Escrow::LEN as u64, &env.program_id));
+    instructions.push(instruction::init_escrow(
+        &env.program_id,
+        &alice,
+        &vault.pubkey(),
+        &alice_y,
+        &escrow.pubkey(),
+        &alice_x,
+        &spl_token_2022::id(),
+        EXPECTED,
+        0,
+        TIMEOUT_WINDOW,
+        ClockMode::Slot,
+        AssetKind::Token,
+        AssetKind::Token,
+        None,
+        None,
+        None,
+    ));
+    let alice_keypair = env.alice.insecure_clone();
+    process(&mut env.context, &instructions, &[&alice_keypair, &vault, &escrow]).await.unwrap();
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &escrow.pubkey()).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.deposit_amount, 990);
+
+    let treasury_y = get_associated_token_address_with_program_id(&env.treasury, &fee_mint_y, &spl_token_2022::id());
+    let mut ix = instruction::exchange(
+        &env.program_id,
+        &bob,
+        &bob_y,
+        &bob_x,
+        &vault.pubkey(),
+        &alice,
+        &alice_y,
+        &escrow.pubkey(),
+        &treasury_y,
+        &fee_mint_x,
+        &fee_mint_y,
+        &spl_token_2022::id(),
+        &spl_token_2022::id(),
+        990,
+        EXPECTED,
+        980,
+    );
+    let bob_keypair = env.bob.insecure_clone();
+    // the 1% fee on bob's payment takes his debit to 31
+    assert_eq!(process(&mut env.context, std::slice::from_ref(&ix), &[&bob_keypair]).await.unwrap_err(), custom_error(EscrowError::MaxPaymentExceeded));
+    // and 1% of the deposit is withheld on the way out, so only 980 of the 990 he takes arrive
+    ix.data = EscrowInstruction::Exchange { amount: 990, max_payment: 31, min_receive: 990 }.pack();
+    assert_eq!(process(&mut env.context, std::slice::from_ref(&ix), &[&bob_keypair]).await.unwrap_err(), custom_error(EscrowError::MinReceiveNotMet));
+    ix.data = EscrowInstruction::Exchange { amount: 990, max_payment: 31, min_receive: 980 }.pack();
+    process(&mut env.context, &[ix], &[&bob_keypair]).await.unwrap();
+
+    // bob covers the fee on his payment so alice gets exactly what she asked for
+    assert_eq!(token_2022_balance(&mut env.context, &alice_y).await, EXPECTED);
+    assert_eq!(token_2022_balance(&mut env.context, &bob_y).await, 100 - EXPECTED - 1);
+    assert_eq!(token_2022_balance(&mut env.context, &bob_x).await, 980);
+    // the vault's withheld fees are harvested to the mint before it is closed
+    assert!(get_account(&mut env.context, &vault.pubkey()).await.is_none());
+    assert!(get_account(&mut env.context, &escrow.pubkey()).await.is_none());
+    let mint_data = get_account(&mut env.context, &fee_mint_x).await.unwrap().data;
+    let mint_info = StateWithExtensions::<Token2022Mint>::unpack(&mint_data).unwrap();
+    assert_eq!(u64::from(mint_info.get_extension::<TransferFeeConfig>().unwrap().withheld_amount), 10);
+}
+
+// bob offers `payment` of his Y for `fill_amount` of alice's X
+fn propose_counter_instruction(env: &Env, open: &OpenEscrow, fill_amount: u64, payment: u64) -> Instruction {
+    instruction::propose_counter(&env.program_id, &env.bob.pubkey(), &env.bob_y, &env.bob_x, &open.escrow, &env.mint_y, &spl_token::id(), fill_amount, payment)
+}
+
+async fn propose_counter(env: &mut Env, open: &OpenEscrow, fill_amount: u64, payment: u64) -> Result<(), TransactionError> {
+    let ix = propose_counter_instruction(env, open, fill_amount, payment);
+    let bob = env.bob.insecure_clone();
+    process(&mut env.context, &[ix], &[&bob]).await
+}
+
+fn accept_counter_instruction(env: &Env, open: &OpenEscrow) -> Instruction {
+    instruction::accept_counter(
+        &env.program_id,
+        &env.alice.pubkey(),
+        &open.escrow,
+        &open.vault,
+        &env.alice_y,
+        &env.bob.pubkey(),
+        &env.bob_x,
+        &env.treasury_y,
+        &env.mint_x,
+        &env.mint_y,
+        &spl_token::id(),
+        &spl_token::id(),
+    )
+}
+
+fn withdraw_counter_instruction(env: &Env, open: &OpenEscrow) -> Instruction {
+    instruction::withdraw_counter(&env.program_id, &env.bob.pubkey(), &env.bob_y, &open.escrow, &env.mint_y, &spl_token::id())
+}
+
+async fn withdraw_counter(env: &mut Env, open: &OpenEscrow) -> Result<(), TransactionError> {
+    let ix = withdraw_counter_instruction(env, open);
+    let bob = env.bob.insecure_clone();
+    process(&mut env.context, &[ix], &[&bob]).await
+}
+
+async fn open_counters(context: &mut ProgramTestContext, escrow: &Pubkey) -> u32 {
+    Escrow::unpack(&get_account(context, escrow).await.unwrap().data).unwrap().open_counters
+}
+
+#[tokio::test]
+async fn test_accept_counter() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.alice.pubkey()).await;
+    fund(&mut env.context, &env.bob.pubkey()).await;
+    init_config(&mut env, 1_000).await.unwrap();
+    let open = init_escrow(&mut env).await;
+    let (counter, _bump_seed) = CounterOffer::find_pda(&open.escrow, &env.bob.pubkey(), &env.program_id);
+    let counter_vault = CounterOffer::find_vault(&open.escrow, &env.bob.pubkey(), &env.mint_y, &spl_token::id(), &env.program_id);
+
+    // half the deposit is listed at 15, bob offers 20 for it
+    propose_counter(&mut env, &open, 25, 20).await.unwrap();
+    let counter_info = CounterOffer::unpack(&get_account(&mut env.context, &counter).await.unwrap().data).unwrap();
+    assert_eq!(counter_info.escrow_pubkey, open.escrow);
+    assert_eq!(counter_info.taker_pubkey, env.bob.pubkey());
+    assert_eq!(counter_info.vault_pubkey, counter_vault);
+    assert_eq!(counter_info.takers_token_to_receive_account_pubkey, env.bob_x);
+    assert_eq!((counter_info.fill_amount, counter_info.payment), (25, 20));
+    assert_eq!(TokenAccount::unpack(&get_account(&mut env.context, &counter_vault).await.unwrap().data).unwrap().owner, counter);
+    assert_eq!(token_balance(&mut env.context, &counter_vault).await, 20);
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED - 20);
+    assert_eq!(open_counters(&mut env.context, &open.escrow).await, 1);
+
+    // accepting settles in the escrow's window, like an exchange
+    let alice = env.alice.insecure_clone();
+    let ix = accept_counter_instruction(&env, &open);
+    assert_eq!(process(&mut env.context, std::slice::from_ref(&ix), &[&alice]).await.unwrap_err(), custom_error(EscrowError::EscrowUnlockTime));
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+    let mut wrong_treasury = ix.clone();
+    wrong_treasury.accounts[14].pubkey = env.alice_y;
+    assert_eq!(process(&mut env.context, &[wrong_treasury], &[&alice]).await.unwrap_err(), custom_error(EscrowError::WrongTreasury));
+    let bob_lamports = lamports(&mut env.context, &env.bob.pubkey()).await;
+    let rent_locked = lamports(&mut env.context, &counter).await + lamports(&mut env.context, &counter_vault).await;
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+
+    // the fee is 10% of the counter-offer's payment, the rest of the escrow keeps its own price
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, 25);
+    assert_eq!(token_balance(&mut env.context, &env.alice_y).await, 18);
+    assert_eq!(token_balance(&mut env.context, &env.treasury_y).await, 2);
+    assert!(get_account(&mut env.context, &counter).await.is_none());
+    assert!(get_account(&mut env.context, &counter_vault).await.is_none());
+    assert_eq!(lamports(&mut env.context, &env.bob.pubkey()).await, bob_lamports + rent_locked);
+    let escrow_info = Escrow::unpack(&get_account(&mut env.context, &open.escrow).await.unwrap().data).unwrap();
+    assert_eq!(escrow_info.deposit_amount, DEPOSIT - 25);
+    assert_eq!(escrow_info.expected_amount, EXPECTED - 15);
+    assert_eq!(escrow_info.open_counters, 0);
+    assert_eq!(token_balance(&mut env.context, &open.vault).await, DEPOSIT - 25);
+
+    // a timed out escrow takes no new counter-offers
+    env.context.warp_to_slot(open.time_out + 1).unwrap();
+    assert_eq!(propose_counter(&mut env, &open, 5, 5).await.unwrap_err(), custom_error(EscrowError::EscrowTimeout));
+}
+
+#[tokio::test]
+async fn test_withdraw_counter() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.bob.pubkey()).await;
+    let open = init_escrow(&mut env).await;
+    let (counter, _bump_seed) = CounterOffer::find_pda(&open.escrow, &env.bob.pubkey(), &env.program_id);
+    let counter_vault = CounterOffer::find_vault(&open.escrow, &env.bob.pubkey(), &env.mint_y, &spl_token::id(), &env.program_id);
+
+    // lamports sent to the counter-offer's address and a vault created ahead of time don't block it,
+    // and the tokens already in the vault aren't counted as bob's payment
+    fund(&mut env.context, &counter).await;
+    let payer = env.context.payer.pubkey();
+    let instructions = [
+        create_associated_token_account(&payer, &counter, &env.mint_y, &spl_token::id()),
+        spl_token::instruction::mint_to(&spl_token::id(), &env.mint_y, &counter_vault, &payer, &[], 3).unwrap(),
+    ];
+    process(&mut env.context, &instructions, &[]).await.unwrap();
+
+    // one counter-offer per taker, withdrawing it returns the whole vault and frees the address
+    propose_counter(&mut env, &open, DEPOSIT, 20).await.unwrap();
+    assert_eq!(CounterOffer::unpack(&get_account(&mut env.context, &counter).await.unwrap().data).unwrap().payment, 20);
+    assert_eq!(
+        propose_counter(&mut env, &open, DEPOSIT, 5).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)
+    );
+    withdraw_counter(&mut env, &open).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED + 3);
+    assert!(get_account(&mut env.context, &counter).await.is_none());
+    assert_eq!(open_counters(&mut env.context, &open.escrow).await, 0);
+
+    // ignoring a counter-offer doesn't hold the escrow, it cancels with one open and the taker withdraws afterwards
+    propose_counter(&mut env, &open, DEPOSIT, 25).await.unwrap();
+    assert_eq!(open_counters(&mut env.context, &open.escrow).await, 1);
+    let ix = instruction::cancel(&env.program_id, &env.alice.pubkey(), &open.vault, &env.alice_x, &open.escrow, &env.mint_x, &spl_token::id());
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+    assert!(get_account(&mut env.context, &open.escrow).await.is_none());
+    assert_eq!(token_balance(&mut env.context, &env.alice_x).await, DEPOSIT);
+    withdraw_counter(&mut env, &open).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED + 3);
+    assert!(get_account(&mut env.context, &counter).await.is_none());
+    assert!(get_account(&mut env.context, &counter_vault).await.is_none());
+}
+
+#[tokio::test]
+async fn test_propose_counter_rejected() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.bob.pubkey()).await;
+    let bob = env.bob.insecure_clone();
+
+    let open = init_escrow(&mut env).await;
+    for (fill_amount, payment, error) in [
+        (0, 20, EscrowError::InvalidFillAmount),
+        (DEPOSIT + 1, 20, EscrowError::InvalidFillAmount),
+        (DEPOSIT, 0, EscrowError::InvalidCounter),
+    ] {
+        let ix = propose_counter_instruction(&env, &open, fill_amount, payment);
+        assert_eq!(process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(), custom_error(error));
+    }
+
+    let ix = propose_counter_instruction(&env, &open, DEPOSIT, 20);
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 1, env.bob_x), custom(EscrowError::ExpectedMintMismatch)),
+        (spoof(&ix, 2, env.alice_y), custom(EscrowError::DepositMintMismatch)),
+        (spoof(&ix, 4, Pubkey::new_unique()), custom(EscrowError::WrongCounterAccount)),
+        (spoof(&ix, 5, Pubkey::new_unique()), custom(EscrowError::WrongCounterAccount)),
+        (spoof(&ix, 6, env.mint_x), InstructionError::InvalidAccountData),
+        (spoof(&ix, 9, Pubkey::new_unique()), InstructionError::IncorrectProgramId),
+    ];
+    assert_spoofed(&mut env.context, &[ix], &[&bob], cases).await;
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED);
+
+    // only the allowed taker may counter
+    let ix = instruction::cancel(&env.program_id, &env.alice.pubkey(), &open.vault, &env.alice_x, &open.escrow, &env.mint_x, &spl_token::id());
+    let alice = env.alice.insecure_clone();
+    process(&mut env.context, &[ix], &[&alice]).await.unwrap();
+    let open = init_escrow_for(&mut env, Some(alice.pubkey())).await;
+    let ix = propose_counter_instruction(&env, &open, DEPOSIT, 20);
+    assert_eq!(process(&mut env.context, &[ix], &[&bob]).await.unwrap_err(), custom_error(EscrowError::UnauthorizedTaker));
+}
+
+#[tokio::test]
+async fn test_accept_counter_rejected() {
+    let mut env = setup().await;
+    fund(&mut env.context, &env.bob.pubkey()).await;
+    let open = init_escrow(&mut env).await;
+    let bob = env.bob.insecure_clone();
+    let alice = env.alice.insecure_clone();
+    propose_counter(&mut env, &open, 25, 10).await.unwrap();
+    env.context.warp_to_slot(open.unlock_time).unwrap();
+    let (counter, _bump_seed) = CounterOffer::find_pda(&open.escrow, &env.bob.pubkey(), &env.program_id);
+    let spoofed_counter = spoofed_copy(&mut env.context, &counter).await;
+
+    let ix = accept_counter_instruction(&env, &open);
+    let cases = vec![
+        (unsigned(&ix, 0), InstructionError::MissingRequiredSignature),
+        (spoof(&ix, 0, env.bob.pubkey()), custom(EscrowError::WrongInitializer)),
+        (spoof(&ix, 4, env.bob_y), custom(EscrowError::WrongReceiveAccount)),
+        (spoof(&ix, 5, spoofed_counter), custom(EscrowError::NotProgramOwned)),
+        (spoof(&ix, 6, Pubkey::new_unique()), custom(EscrowError::WrongCounterAccount)),
+        (spoof(&ix, 7, Pubkey::new_unique()), custom(EscrowError::WrongCounterAccount)),
+        (spoof(&ix, 8, env.alice_x), custom(EscrowError::WrongReceiveAccount)),
+    ];
+    assert_spoofed(&mut env.context, std::slice::from_ref(&ix), &[&alice, &bob], cases).await;
+
+    // only the taker can withdraw
+    let mut withdraw_ix = withdraw_counter_instruction(&env, &open);
+    withdraw_ix.accounts[0].pubkey = env.alice.pubkey();
+    assert_eq!(process(&mut env.context, &[withdraw_ix], &[&alice]).await.unwrap_err(), custom_error(EscrowError::WrongCounterAccount));
+
+    // a counter-offer for more than is left after a partial fill is stale, it can't be accepted
+    exchange(&mut env, &open, 30).await.unwrap();
+    assert_eq!(process(&mut env.context, &[ix], &[&alice]).await.unwrap_err(), custom_error(EscrowError::InvalidFillAmount));
+    assert_eq!(token_balance(&mut env.context, &env.bob_x).await, 30);
+
+    // but bob gets the whole vault back, having paid 18 for the 30 he took
+    let counter_vault = CounterOffer::find_vault(&open.escrow, &env.bob.pubkey(), &env.mint_y, &spl_token::id(), &env.program_id);
+    withdraw_counter(&mut env, &open).await.unwrap();
+    assert_eq!(token_balance(&mut env.context, &env.bob_y).await, EXPECTED - 18);
+    assert!(get_account(&mut env.context, &counter_vault).await.is_none());
+    assert_eq!(open_counters(&mut env.context, &open.escrow).await, 0);
+}
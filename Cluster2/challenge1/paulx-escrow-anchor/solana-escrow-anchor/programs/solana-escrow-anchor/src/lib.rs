@@ -13,19 +13,37 @@ pub mod solana_escrow_anchor {
 
     const ESCROW_PDA_SEED: &[u8] = b"escrow";
 
-    pub fn initialize(ctx: Context<Initialize>, amount: u64) -> ProgramResult {
+    // Bounds for the caller-chosen time lock, in slots (~400ms each)
+    const MAX_UNLOCK_DELAY: u64 = 30 * 216_000;
+    const MIN_TIMEOUT_WINDOW: u64 = 150;
+    const MAX_TIMEOUT_WINDOW: u64 = 30 * 216_000;
+
+    pub fn initialize(ctx: Context<Initialize>, amount: u64, unlock_delay: u64, timeout_window: u64, allowed_taker: Option<Pubkey>) -> Result<()> {
+        if unlock_delay > MAX_UNLOCK_DELAY
+            || !(MIN_TIMEOUT_WINDOW..=MAX_TIMEOUT_WINDOW).contains(&timeout_window)
+        {
+            return Err(ErrorCode::TimeLockOutOfRange.into());
+        }
+
         // Store data in escrow account
         let escrow_account = &mut ctx.accounts.escrow_account;
 
         let clock = Clock::get()?;
-        escrow_account.unlock_time = clock.slot + 100;
-        escrow_account.time_out = &escrow_account.unlock_time + 1000;
+        escrow_account.unlock_time = clock.slot
+            .checked_add(unlock_delay)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        escrow_account.time_out = escrow_account.unlock_time
+            .checked_add(timeout_window)
+            .ok_or(ErrorCode::AmountOverflow)?;
 
         escrow_account.is_initialized = true;
         escrow_account.initializer_pubkey = *ctx.accounts.initializer.to_account_info().key;
         escrow_account.temp_token_account_pubkey = *ctx.accounts.temp_token_account.to_account_info().key;
         escrow_account.initializer_token_to_receive_account_pubkey = *ctx.accounts.token_to_receive_account.to_account_info().key;
         escrow_account.expected_amount = amount;
+        escrow_account.deposit_mint = ctx.accounts.temp_token_account.mint;
+        escrow_account.expected_mint = ctx.accounts.token_to_receive_account.mint;
+        escrow_account.allowed_taker = allowed_taker;
 
         // Create PDA, which will own the temp token account
         let (pda, _bump_seed) = Pubkey::find_program_address(&[ESCROW_PDA_SEED], ctx.program_id);
@@ -143,6 +161,7 @@ pub struct Cancel<'info> {
         mut,
         constraint = escrow_account.initializer_pubkey == initializer.key(),
         constraint = escrow_account.temp_token_account_pubkey == pda_token_account.key(),
+        constraint = escrow_account.deposit_mint == pda_token_account.mint @ ErrorCode::DepositMintMismatch,
         close = initializer
     )]
     pub escrow_account: Account<'info, Escrow>,
@@ -165,9 +184,14 @@ pub struct Exchange<'info> {
     #[account(mut)]
     pub initializers_token_to_receive_account: Account<'info, TokenAccount>,
     #[account(mut, close = initializers_main_account,
+        constraint = escrow_account.allowed_taker.map_or(true, |allowed_taker| allowed_taker == taker.key()) @ ErrorCode::UnauthorizedTaker,
         constraint = escrow_account.temp_token_account_pubkey == *pdas_temp_token_account.to_account_info().key @ ProgramError::InvalidAccountData,
         constraint = escrow_account.initializer_pubkey == *initializers_main_account.to_account_info().key @ ProgramError::InvalidAccountData,
         constraint = escrow_account.initializer_token_to_receive_account_pubkey == *initializers_token_to_receive_account.to_account_info().key @ ProgramError::InvalidAccountData,
+        constraint = escrow_account.deposit_mint == pdas_temp_token_account.mint @ ErrorCode::DepositMintMismatch,
+        constraint = escrow_account.deposit_mint == takers_token_to_receive_account.mint @ ErrorCode::DepositMintMismatch,
+        constraint = escrow_account.expected_mint == takers_sending_token_account.mint @ ErrorCode::ExpectedMintMismatch,
+        constraint = escrow_account.expected_mint == initializers_token_to_receive_account.mint @ ErrorCode::ExpectedMintMismatch,
     )]
     pub escrow_account: Box<Account<'info, Escrow>>,
     #[account(address = spl_token::id())]
@@ -184,18 +208,24 @@ pub struct Escrow {
     pub expected_amount: u64,
     pub unlock_time: u64,
     pub time_out: u64,
+    pub deposit_mint: Pubkey,
+    pub expected_mint: Pubkey,
+    // when set, the only key allowed to take the escrow
+    pub allowed_taker: Option<Pubkey>,
 }
 
 const DISCRIMINATOR_LENGTH: usize = 8;
 const BOOL_LENGTH: usize = 1;
 const PUBLIC_KEY_LENGTH: usize = 32;
 const U64_LENGTH: usize = 8;
+const OPTION_TAG_LENGTH: usize = 1;
 
 impl Escrow {
     const LEN: usize = DISCRIMINATOR_LENGTH +
         BOOL_LENGTH +
-        PUBLIC_KEY_LENGTH * 3 +
-        U64_LENGTH;
+        PUBLIC_KEY_LENGTH * 5 +
+        U64_LENGTH * 3 +
+        OPTION_TAG_LENGTH + PUBLIC_KEY_LENGTH;
 }
 
 impl<'info> From<&mut Initialize<'info>> for CpiContext<'_, '_, '_, 'info, SetAuthority<'info>> {
@@ -217,6 +247,16 @@ pub enum ErrorCode {
     EscrowUnlockTime,
     #[msg("Current slot is greater than timeout time")]
     EscrowTimeout,
+    #[msg("Unlock delay or timeout window is out of range")]
+    TimeLockOutOfRange,
+    #[msg("Amount Overflow")]
+    AmountOverflow,
+    #[msg("Token account mint does not match the escrow's deposit mint")]
+    DepositMintMismatch,
+    #[msg("Token account mint does not match the escrow's expected mint")]
+    ExpectedMintMismatch,
+    #[msg("Escrow can only be taken by its allowed taker")]
+    UnauthorizedTaker,
 }
 
 impl<'info> Cancel<'info> {
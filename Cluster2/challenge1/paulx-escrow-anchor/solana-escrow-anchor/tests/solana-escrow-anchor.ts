@@ -162,6 +162,9 @@ describe('solana-escrow-anchor', () => {
         console.log("Sending Alice's transaction...");
         let initTx = await program.rpc.initialize(
             new anchor.BN(terms.aliceExpectedAmount),
+            new anchor.BN(terms.unlockDelay),
+            new anchor.BN(terms.timeoutWindow),
+            null,
             {
                 accounts: {
                     initializer: aliceKeypair.publicKey,
@@ -42,6 +42,8 @@ export const getProgramId = () => {
 export const getTerms = (): {
     aliceExpectedAmount: number;
     bobExpectedAmount: number;
+    unlockDelay: number;
+    timeoutWindow: number;
 } => {
     return JSON.parse(fs.readFileSync(`./tests/terms.json`) as unknown as string);
 };